@@ -37,6 +37,9 @@ pub enum Error {
     #[snafu(display("Unable to create data backend"))]
     UnableToCreateBackend { source: runtime::datafusion::Error },
 
+    #[snafu(display("Unable to initialize the query engine: {source}"))]
+    UnableToInitializeDataFusion { source: runtime::datafusion::Error },
+
     #[snafu(display("Failed to start pods watcher: {source}"))]
     UnableToInitializePodsWatcher { source: runtime::NotifyError },
 }
@@ -68,7 +71,9 @@ pub struct Args {
 
 pub async fn run(args: Args) -> Result<()> {
     let current_dir = env::current_dir().unwrap_or(PathBuf::from("."));
-    let df = Arc::new(RwLock::new(runtime::datafusion::DataFusion::new()));
+    let df = runtime::datafusion::DataFusion::new_with_config(&args.runtime.query)
+        .context(UnableToInitializeDataFusionSnafu)?;
+    let df = Arc::new(RwLock::new(df));
     let pods_watcher = PodsWatcher::new(current_dir.clone());
     let app: Arc<RwLock<Option<App>>> =
         match App::new(current_dir.clone()).context(UnableToConstructSpiceAppSnafu) {
@@ -83,6 +88,10 @@ pub async fn run(args: Args) -> Result<()> {
 
     rt.load_secrets().await;
 
+    rt.load_access_policy().await;
+
+    rt.load_system_tables().await;
+
     rt.load_datasets().await;
 
     rt.load_models().await;
@@ -4,7 +4,7 @@ use std::path::PathBuf;
 
 use snafu::prelude::*;
 use spicepod::{
-    component::{dataset::Dataset, model::Model, secrets::Secrets},
+    component::{access::Role, dataset::Dataset, model::Model, secrets::Secrets},
     Spicepod,
 };
 
@@ -18,6 +18,8 @@ pub struct App {
 
     pub models: Vec<Model>,
 
+    pub roles: Vec<Role>,
+
     pub spicepods: Vec<Spicepod>,
 }
 
@@ -41,6 +43,7 @@ impl App {
         let secrets = spicepod_root.secrets.clone();
         let mut datasets: Vec<Dataset> = vec![];
         let mut models: Vec<Model> = vec![];
+        let mut roles: Vec<Role> = spicepod_root.roles.clone();
         for dataset in &spicepod_root.datasets {
             datasets.push(dataset.clone());
         }
@@ -64,6 +67,9 @@ impl App {
             for model in &dependent_spicepod.models {
                 models.push(model.clone());
             }
+            for role in &dependent_spicepod.roles {
+                roles.push(role.clone());
+            }
             spicepods.push(dependent_spicepod);
         }
 
@@ -74,6 +80,7 @@ impl App {
             secrets,
             datasets,
             models,
+            roles,
             spicepods,
         })
     }
@@ -1,17 +1,49 @@
+use std::{collections::HashMap, sync::Arc};
+
 use arrow::{
     array::{array, Array, RecordBatch},
-    datatypes::{DataType, SchemaRef},
+    compute::interleave,
+    datatypes::{DataType, SchemaRef, TimeUnit},
+    row::{OwnedRow, RowConverter, SortField},
 };
 
 use bigdecimal_0_3_0::BigDecimal;
 
-use time::{OffsetDateTime, PrimitiveDateTime};
+use snafu::prelude::*;
+
+use time::{Date, OffsetDateTime, PrimitiveDateTime};
 
 use sea_query::{
-    Alias, ColumnDef, ColumnType, Index, InsertStatement, IntoIden, IntoIndexColumn,
-    PostgresQueryBuilder, Query, SimpleExpr, Table,
+    Alias, ColumnDef, ColumnType, Expr, Index, InsertStatement, IntoIden, IntoIndexColumn,
+    OnConflict, PostgresQueryBuilder, Query, SimpleExpr, Table, Value,
 };
 
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Data type {data_type} can't be written as a SQL value"))]
+    UnsupportedDataType { data_type: DataType },
+
+    #[snafu(display("Unable to read a column of type {data_type}"))]
+    UnableToDowncastColumn { data_type: DataType },
+
+    #[snafu(display("Timestamp {nanos}ns since the Unix epoch is out of range"))]
+    InvalidTimestamp { nanos: i128 },
+
+    #[snafu(display("Date {days} days since the Unix epoch is out of range"))]
+    InvalidDate { days: i32 },
+
+    #[snafu(display("The rows have no key column {key}"))]
+    MissingKeyColumn { key: String },
+
+    #[snafu(display("Unable to compare the key columns of the rows: {source}"))]
+    UnableToCompareKeys { source: arrow::error::ArrowError },
+
+    #[snafu(display("Unable to collapse the rows with the same keys: {source}"))]
+    UnableToCollapseRows { source: arrow::error::ArrowError },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
 pub struct CreateTableBuilder {
     schema: SchemaRef,
     table_name: String,
@@ -67,6 +99,7 @@ impl CreateTableBuilder {
 pub struct InsertBuilder {
     table_name: String,
     record_batches: Vec<RecordBatch>,
+    conflict_keys: Vec<String>,
 }
 
 impl InsertBuilder {
@@ -75,138 +108,34 @@ impl InsertBuilder {
         Self {
             table_name: table_name.to_string(),
             record_batches,
+            conflict_keys: Vec::new(),
         }
     }
 
-    #[allow(clippy::too_many_lines)]
+    /// Update the existing row instead of failing when a row with the same `keys` already exists.
+    ///
+    /// Rows that share the same keys within the inserted batches are collapsed to the last one.
+    #[must_use]
+    pub fn on_conflict_update(mut self, keys: Vec<&str>) -> Self {
+        self.conflict_keys = keys.into_iter().map(ToString::to_string).collect();
+        self
+    }
+
     pub fn construct_insert_stmt(
         &self,
         insert_stmt: &mut InsertStatement,
         record_batch: &RecordBatch,
-    ) {
+    ) -> Result<()> {
         for row in 0..record_batch.num_rows() {
-            let mut row_values: Vec<SimpleExpr> = vec![];
-            for col in 0..record_batch.num_columns() {
-                let column = record_batch.column(col);
-                match column.data_type() {
-                    DataType::Int8 => {
-                        let array = column.as_any().downcast_ref::<array::Int8Array>();
-                        if let Some(valid_array) = array {
-                            row_values.push(valid_array.value(row).into());
-                        }
-                    }
-                    DataType::Int16 => {
-                        let array = column.as_any().downcast_ref::<array::Int16Array>();
-                        if let Some(valid_array) = array {
-                            row_values.push(valid_array.value(row).into());
-                        }
-                    }
-                    DataType::Int32 => {
-                        let array = column.as_any().downcast_ref::<array::Int32Array>();
-                        if let Some(valid_array) = array {
-                            row_values.push(valid_array.value(row).into());
-                        }
-                    }
-                    DataType::Int64 => {
-                        let array = column.as_any().downcast_ref::<array::Int64Array>();
-                        if let Some(valid_array) = array {
-                            row_values.push(valid_array.value(row).into());
-                        }
-                    }
-                    DataType::UInt8 => {
-                        let array = column.as_any().downcast_ref::<array::UInt8Array>();
-                        if let Some(valid_array) = array {
-                            row_values.push(valid_array.value(row).into());
-                        }
-                    }
-                    DataType::UInt16 => {
-                        let array = column.as_any().downcast_ref::<array::UInt16Array>();
-                        if let Some(valid_array) = array {
-                            row_values.push(valid_array.value(row).into());
-                        }
-                    }
-                    DataType::UInt32 => {
-                        let array = column.as_any().downcast_ref::<array::UInt32Array>();
-                        if let Some(valid_array) = array {
-                            row_values.push(valid_array.value(row).into());
-                        }
-                    }
-                    DataType::UInt64 => {
-                        let array = column.as_any().downcast_ref::<array::UInt64Array>();
-                        if let Some(valid_array) = array {
-                            row_values.push(valid_array.value(row).into());
-                        }
-                    }
-                    DataType::Float32 => {
-                        let array = column.as_any().downcast_ref::<array::Float32Array>();
-                        if let Some(valid_array) = array {
-                            row_values.push(valid_array.value(row).into());
-                        }
-                    }
-                    DataType::Float64 => {
-                        let array = column.as_any().downcast_ref::<array::Float64Array>();
-                        if let Some(valid_array) = array {
-                            row_values.push(valid_array.value(row).into());
-                        }
-                    }
-                    DataType::Utf8 => {
-                        let array = column.as_any().downcast_ref::<array::StringArray>();
-                        if let Some(valid_array) = array {
-                            row_values.push(valid_array.value(row).into());
-                        }
-                    }
-                    DataType::Boolean => {
-                        let array = column.as_any().downcast_ref::<array::BooleanArray>();
-                        if let Some(valid_array) = array {
-                            row_values.push(valid_array.value(row).into());
-                        }
-                    }
-                    DataType::Decimal128(_, scale) => {
-                        let array = column.as_any().downcast_ref::<array::Decimal128Array>();
-                        if let Some(valid_array) = array {
-                            row_values.push(
-                                BigDecimal::new(valid_array.value(row).into(), i64::from(*scale))
-                                    .into(),
-                            );
-                        }
-                    }
-                    DataType::Timestamp(_, _) => {
-                        let array = column
-                            .as_any()
-                            .downcast_ref::<array::TimestampMicrosecondArray>();
-                        if let Some(valid_array) = array {
-                            match OffsetDateTime::from_unix_timestamp(
-                                valid_array.value(row) / 1_000_000,
-                            ) {
-                                Ok(offset_time) => {
-                                    row_values.push(
-                                        PrimitiveDateTime::new(
-                                            offset_time.date(),
-                                            offset_time.time(),
-                                        )
-                                        .into(),
-                                    );
-                                }
-                                Err(_) => {
-                                    return;
-                                }
-                            };
-                        }
-                    }
-                    _ => unimplemented!(
-                        "Data type mapping not implemented for {:?}",
-                        column.data_type()
-                    ),
-                }
-            }
-            insert_stmt.values_panic(row_values);
+            insert_stmt.values_panic(row_values(record_batch, row)?);
         }
+
+        Ok(())
     }
 
-    #[must_use]
-    pub fn build(&self) -> String {
-        let columns: Vec<Alias> = (self.record_batches[0])
-            .schema()
+    pub fn build(&self) -> Result<String> {
+        let schema = self.record_batches[0].schema();
+        let columns: Vec<Alias> = schema
             .fields()
             .iter()
             .map(|field| Alias::new(field.name()))
@@ -217,13 +146,315 @@ impl InsertBuilder {
             .columns(columns)
             .to_owned();
 
+        if self.conflict_keys.is_empty() {
+            for record_batch in &self.record_batches {
+                self.construct_insert_stmt(&mut insert_stmt, record_batch)?;
+            }
+            return Ok(insert_stmt.to_string(PostgresQueryBuilder));
+        }
+
+        // A single statement can't update the same row twice, so only the last row for each key is kept.
+        for (batch, row) in last_row_per_key(&self.record_batches, &self.conflict_keys)? {
+            insert_stmt.values_panic(row_values(&self.record_batches[batch], row)?);
+        }
+
+        let update_columns: Vec<Alias> = schema
+            .fields()
+            .iter()
+            .filter(|field| !self.conflict_keys.contains(field.name()))
+            .map(|field| Alias::new(field.name()))
+            .collect();
+        let mut on_conflict = OnConflict::columns(
+            self.conflict_keys
+                .iter()
+                .map(Alias::new)
+                .collect::<Vec<_>>(),
+        );
+        if update_columns.is_empty() {
+            on_conflict.do_nothing();
+        } else {
+            on_conflict.update_columns(update_columns);
+        }
+        insert_stmt.on_conflict(on_conflict);
+
+        Ok(insert_stmt.to_string(PostgresQueryBuilder))
+    }
+}
+
+/// Builds a `DELETE` of the rows that have the same values in the `keys` columns as a row of the
+/// record batches.
+pub struct DeleteBuilder {
+    table_name: String,
+    record_batches: Vec<RecordBatch>,
+    keys: Vec<String>,
+}
+
+impl DeleteBuilder {
+    #[must_use]
+    pub fn new(table_name: &str, record_batches: Vec<RecordBatch>, keys: Vec<&str>) -> Self {
+        Self {
+            table_name: table_name.to_string(),
+            record_batches,
+            keys: keys.into_iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    /// Deletes nothing if the record batches don't have all the `keys` columns.
+    pub fn build(&self) -> Result<String> {
+        let mut key_values: Vec<SimpleExpr> = Vec::new();
+        let mut has_keys = !self.keys.is_empty();
         for record_batch in &self.record_batches {
-            self.construct_insert_stmt(&mut insert_stmt, record_batch);
+            let schema = record_batch.schema();
+            let key_indices: Vec<usize> = self
+                .keys
+                .iter()
+                .filter_map(|key| schema.index_of(key).ok())
+                .collect();
+            if key_indices.len() != self.keys.len() {
+                has_keys = false;
+                break;
+            }
+
+            for row in 0..record_batch.num_rows() {
+                let values = row_values(record_batch, row)?;
+                let mut values: Vec<SimpleExpr> = key_indices
+                    .iter()
+                    .filter_map(|i| values.get(*i).cloned())
+                    .collect();
+                if values.len() == 1 {
+                    key_values.append(&mut values);
+                } else {
+                    key_values.push(SimpleExpr::Tuple(values));
+                }
+            }
         }
-        insert_stmt.to_string(PostgresQueryBuilder)
+
+        let condition = match self.keys.as_slice() {
+            _ if !has_keys => Expr::value(false),
+            [key] => Expr::col(Alias::new(key)).is_in(key_values),
+            keys => Expr::tuple(keys.iter().map(|key| Expr::col(Alias::new(key)).into()))
+                .is_in(key_values),
+        };
+
+        Ok(Query::delete()
+            .from_table(Alias::new(&self.table_name))
+            .cond_where(condition)
+            .to_string(PostgresQueryBuilder))
     }
 }
 
+/// Converts a row of a record batch into SQL values, with `NULL` for the null cells.
+/// Returns the `(batch, row)` indices of the last row of `record_batches` for each distinct value of
+/// the `keys` columns, in the order the values first appear.
+pub fn last_row_per_key(
+    record_batches: &[RecordBatch],
+    keys: &[String],
+) -> Result<Vec<(usize, usize)>> {
+    let mut rows: Vec<(usize, usize)> = Vec::new();
+    let mut row_by_key: HashMap<OwnedRow, usize> = HashMap::new();
+    let mut key_converter: Option<RowConverter> = None;
+    for (batch, record_batch) in record_batches.iter().enumerate() {
+        let batch_schema = record_batch.schema();
+        let key_columns = keys
+            .iter()
+            .map(|key| {
+                batch_schema
+                    .index_of(key)
+                    .ok()
+                    .map(|i| Arc::clone(record_batch.column(i)))
+                    .context(MissingKeyColumnSnafu { key })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if key_converter.is_none() {
+            key_converter = Some(
+                RowConverter::new(
+                    key_columns
+                        .iter()
+                        .map(|column| SortField::new(column.data_type().clone()))
+                        .collect(),
+                )
+                .context(UnableToCompareKeysSnafu)?,
+            );
+        }
+        let Some(converter) = key_converter.as_mut() else {
+            continue;
+        };
+        let key_rows = converter
+            .convert_columns(&key_columns)
+            .context(UnableToCompareKeysSnafu)?;
+
+        for row in 0..record_batch.num_rows() {
+            let key = key_rows.row(row).owned();
+            if let Some(existing) = row_by_key.get(&key) {
+                rows[*existing] = (batch, row);
+            } else {
+                row_by_key.insert(key, rows.len());
+                rows.push((batch, row));
+            }
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Collapses the rows of `record_batches` that have the same values in the `keys` columns to the last
+/// one, like `InsertBuilder::on_conflict_update` does for the rows it inserts.
+pub fn dedup_by_keys(record_batches: &[RecordBatch], keys: &[String]) -> Result<Vec<RecordBatch>> {
+    let Some(first) = record_batches.first() else {
+        return Ok(Vec::new());
+    };
+    let rows = last_row_per_key(record_batches, keys)?;
+
+    let columns = (0..first.num_columns())
+        .map(|column| {
+            let arrays: Vec<&dyn Array> = record_batches
+                .iter()
+                .map(|record_batch| record_batch.column(column).as_ref())
+                .collect();
+            interleave(&arrays, &rows)
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .context(UnableToCollapseRowsSnafu)?;
+
+    Ok(vec![
+        RecordBatch::try_new(first.schema(), columns).context(UnableToCollapseRowsSnafu)?
+    ])
+}
+
+fn row_values(record_batch: &RecordBatch, row: usize) -> Result<Vec<SimpleExpr>> {
+    let mut row_values: Vec<SimpleExpr> = Vec::with_capacity(record_batch.num_columns());
+    for column in record_batch.columns() {
+        let column = column.as_ref();
+        let value = match column.data_type() {
+            DataType::Int8 => nullable(cell::<array::Int8Array, _>(column, row, |a| a.value(row))?),
+            DataType::Int16 => {
+                nullable(cell::<array::Int16Array, _>(column, row, |a| a.value(row))?)
+            }
+            DataType::Int32 => {
+                nullable(cell::<array::Int32Array, _>(column, row, |a| a.value(row))?)
+            }
+            DataType::Int64 => {
+                nullable(cell::<array::Int64Array, _>(column, row, |a| a.value(row))?)
+            }
+            DataType::UInt8 => {
+                nullable(cell::<array::UInt8Array, _>(column, row, |a| a.value(row))?)
+            }
+            DataType::UInt16 => nullable(cell::<array::UInt16Array, _>(column, row, |a| {
+                a.value(row)
+            })?),
+            DataType::UInt32 => nullable(cell::<array::UInt32Array, _>(column, row, |a| {
+                a.value(row)
+            })?),
+            DataType::UInt64 => nullable(cell::<array::UInt64Array, _>(column, row, |a| {
+                a.value(row)
+            })?),
+            DataType::Float32 => nullable(cell::<array::Float32Array, _>(column, row, |a| {
+                a.value(row)
+            })?),
+            DataType::Float64 => nullable(cell::<array::Float64Array, _>(column, row, |a| {
+                a.value(row)
+            })?),
+            DataType::Utf8 => nullable(cell::<array::StringArray, _>(column, row, |a| {
+                a.value(row).to_string()
+            })?),
+            DataType::LargeUtf8 => {
+                nullable(cell::<array::LargeStringArray, _>(column, row, |a| {
+                    a.value(row).to_string()
+                })?)
+            }
+            DataType::Boolean => nullable(cell::<array::BooleanArray, _>(column, row, |a| {
+                a.value(row)
+            })?),
+            DataType::Decimal128(_, scale) => {
+                nullable(cell::<array::Decimal128Array, _>(column, row, |a| {
+                    BigDecimal::new(a.value(row).into(), i64::from(*scale))
+                })?)
+            }
+            DataType::Date32 => {
+                let days = cell::<array::Date32Array, _>(column, row, |a| a.value(row))?;
+                let date = days
+                    .map(|days| {
+                        UNIX_EPOCH_JULIAN_DAY
+                            .checked_add(days)
+                            .and_then(|julian_day| Date::from_julian_day(julian_day).ok())
+                            .context(InvalidDateSnafu { days })
+                    })
+                    .transpose()?;
+                nullable(date)
+            }
+            DataType::Timestamp(unit, _) => {
+                let nanos = match unit {
+                    TimeUnit::Second => cell::<array::TimestampSecondArray, _>(column, row, |a| {
+                        i128::from(a.value(row)) * 1_000_000_000
+                    })?,
+                    TimeUnit::Millisecond => {
+                        cell::<array::TimestampMillisecondArray, _>(column, row, |a| {
+                            i128::from(a.value(row)) * 1_000_000
+                        })?
+                    }
+                    TimeUnit::Microsecond => {
+                        cell::<array::TimestampMicrosecondArray, _>(column, row, |a| {
+                            i128::from(a.value(row)) * 1_000
+                        })?
+                    }
+                    TimeUnit::Nanosecond => {
+                        cell::<array::TimestampNanosecondArray, _>(column, row, |a| {
+                            i128::from(a.value(row))
+                        })?
+                    }
+                };
+                // Timestamps are stored without a time zone, in UTC.
+                let timestamp = nanos
+                    .map(|nanos| {
+                        OffsetDateTime::from_unix_timestamp_nanos(nanos)
+                            .ok()
+                            .map(|timestamp| {
+                                PrimitiveDateTime::new(timestamp.date(), timestamp.time())
+                            })
+                            .context(InvalidTimestampSnafu { nanos })
+                    })
+                    .transpose()?;
+                nullable(timestamp)
+            }
+            data_type => {
+                return UnsupportedDataTypeSnafu {
+                    data_type: data_type.clone(),
+                }
+                .fail()
+            }
+        };
+        row_values.push(value);
+    }
+
+    Ok(row_values)
+}
+
+/// The Julian day of 1970-01-01, the epoch of `Date32` values.
+const UNIX_EPOCH_JULIAN_DAY: i32 = 2_440_588;
+
+/// Reads the cell of `column` at `row` with `value`, or returns `None` if it is null.
+fn cell<A: Array + 'static, T>(
+    column: &dyn Array,
+    row: usize,
+    value: impl FnOnce(&A) -> T,
+) -> Result<Option<T>> {
+    let array = column
+        .as_any()
+        .downcast_ref::<A>()
+        .context(UnableToDowncastColumnSnafu {
+            data_type: column.data_type().clone(),
+        })?;
+
+    Ok((!array.is_null(row)).then(|| value(array)))
+}
+
+fn nullable<T>(value: Option<T>) -> SimpleExpr
+where
+    Value: From<Option<T>>,
+{
+    SimpleExpr::Value(value.into())
+}
+
 fn map_data_type_to_column_type(data_type: &DataType) -> ColumnType {
     match data_type {
         DataType::Int8 => ColumnType::TinyInteger,
@@ -304,10 +535,113 @@ mod tests {
         .expect("Unable to build record batch");
         let record_batches = vec![batch1, batch2];
 
-        let sql = InsertBuilder::new("users", record_batches).build();
+        let sql = InsertBuilder::new("users", record_batches)
+            .build()
+            .expect("Unable to build insert");
         assert_eq!(sql, "INSERT INTO \"users\" (\"id\", \"name\", \"age\") VALUES (1, 'a', 10), (2, 'b', 20), (3, 'c', 30), (1, 'a', 10), (2, 'b', 20), (3, 'c', 30)");
     }
 
+    #[test]
+    fn test_table_insertion_on_conflict_update() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+        ]);
+        let batch = RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(array::Int32Array::from(vec![1, 2, 1])),
+                Arc::new(array::StringArray::from(vec!["a", "b", "c"])),
+            ],
+        )
+        .expect("Unable to build record batch");
+
+        let sql = InsertBuilder::new("users", vec![batch])
+            .on_conflict_update(vec!["id"])
+            .build()
+            .expect("Unable to build insert");
+        assert_eq!(sql, "INSERT INTO \"users\" (\"id\", \"name\") VALUES (1, 'c'), (2, 'b') ON CONFLICT (\"id\") DO UPDATE SET \"name\" = \"excluded\".\"name\"");
+    }
+
+    #[test]
+    fn test_table_insertion_nulls_and_timestamps() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new(
+                "updated_at",
+                DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+                true,
+            ),
+        ]);
+        let batch = RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(array::Int32Array::from(vec![1, 2])),
+                Arc::new(array::StringArray::from(vec![None, Some("b")])),
+                Arc::new(
+                    array::TimestampMillisecondArray::from(vec![Some(1_704_164_645_123), None])
+                        .with_timezone("UTC"),
+                ),
+            ],
+        )
+        .expect("Unable to build record batch");
+
+        let sql = InsertBuilder::new("users", vec![batch])
+            .on_conflict_update(vec!["id"])
+            .build()
+            .expect("Unable to build insert");
+        assert!(sql.starts_with(
+            "INSERT INTO \"users\" (\"id\", \"name\", \"updated_at\") VALUES (1, NULL, '2024-01-02 03:04:05"
+        ));
+        assert!(sql.contains("(2, 'b', NULL)"));
+
+        let schema = Schema::new(vec![Field::new("data", DataType::Binary, false)]);
+        let batch = RecordBatch::try_new(
+            Arc::new(schema),
+            vec![Arc::new(array::BinaryArray::from(vec![b"a".as_ref()]))],
+        )
+        .expect("Unable to build record batch");
+        assert!(matches!(
+            InsertBuilder::new("blobs", vec![batch]).build(),
+            Err(Error::UnsupportedDataType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_delete_by_keys() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+        ]);
+        let batch = RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(array::Int32Array::from(vec![1, 2])),
+                Arc::new(array::StringArray::from(vec!["a", "b"])),
+            ],
+        )
+        .expect("Unable to build record batch");
+
+        let sql = DeleteBuilder::new("users", vec![batch.clone()], vec!["id"])
+            .build()
+            .expect("Unable to build delete");
+        assert_eq!(sql, "DELETE FROM \"users\" WHERE \"id\" IN (1, 2)");
+
+        let sql = DeleteBuilder::new("users", vec![batch.clone()], vec!["id", "name"])
+            .build()
+            .expect("Unable to build delete");
+        assert_eq!(
+            sql,
+            "DELETE FROM \"users\" WHERE (\"id\", \"name\") IN ((1, 'a'), (2, 'b'))"
+        );
+
+        let sql = DeleteBuilder::new("users", vec![batch], vec!["missing"])
+            .build()
+            .expect("Unable to build delete");
+        assert_eq!(sql, "DELETE FROM \"users\" WHERE FALSE");
+    }
+
     #[test]
     fn test_table_creation_with_primary_keys() {
         let schema = Schema::new(vec![
@@ -22,6 +22,9 @@ pub enum Error {
 
     #[snafu(display("Unable to query arrow: {source}"))]
     UnableToQueryArrow { source: GenericError },
+
+    #[snafu(display("Unable to execute statement: {source}"))]
+    UnableToExecute { source: GenericError },
 }
 
 pub trait SyncDbConnection<T, P>: DbConnection<T, P> {
@@ -139,3 +142,25 @@ pub async fn query_arrow<T, P>(
         return Err(Error::UnableToDowncastConnection {});
     }
 }
+
+/// Execute the given SQL statement, returning the number of affected rows.
+///
+/// # Arguments
+///
+/// * `conn` - The database connection.
+/// * `sql` - The SQL statement.
+///
+/// # Errors
+///
+/// Returns an error if the execution fails.
+pub async fn execute<T, P>(conn: Box<dyn DbConnection<T, P>>, sql: &str) -> Result<u64, Error> {
+    if let Some(conn) = conn.as_sync() {
+        conn.execute(sql, &[]).context(UnableToExecuteSnafu {})
+    } else if let Some(conn) = conn.as_async() {
+        conn.execute(sql, &[])
+            .await
+            .context(UnableToExecuteSnafu {})
+    } else {
+        Err(Error::UnableToDowncastConnection {})
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::BTreeSet;
+
+use datafusion::common::tree_node::{TreeNode, VisitRecursion};
+use datafusion::logical_expr::{
+    CreateExternalTable, CreateMemoryTable, CreateView, DdlStatement, DropTable, DropView, Exists,
+    Expr, InSubquery, LogicalPlan,
+};
+use snafu::prelude::*;
+use spicepod::component::access::Role;
+
+use crate::auth::Principal;
+
+/// Tables in this schema describe the catalog and are readable by every principal.
+const INFORMATION_SCHEMA: &str = "information_schema";
+
+/// Stands for every dataset, so that only a write grant on all datasets allows statements that aren't
+/// scoped to a single table, i.e. `CREATE SCHEMA` or `COPY ... TO`.
+const ALL_DATASETS: &str = "*";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{principal} does not have read access to dataset {dataset}"))]
+    ReadNotAllowed { principal: String, dataset: String },
+
+    #[snafu(display("{principal} does not have write access to dataset {dataset}"))]
+    WriteNotAllowed { principal: String, dataset: String },
+
+    #[snafu(display("Authentication is required to access dataset {dataset}"))]
+    AuthenticationRequired { dataset: String },
+
+    #[snafu(display("Unable to inspect the query plan: {source}"))]
+    UnableToInspectPlan {
+        source: datafusion::error::DataFusionError,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The dataset grants of the roles defined in the spicepod.
+///
+/// Access is unrestricted when no roles are defined. Once roles are defined, requests without a principal
+/// (i.e. received by an endpoint with authentication disabled) are denied.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccessPolicy {
+    roles: Vec<Role>,
+}
+
+impl AccessPolicy {
+    #[must_use]
+    pub fn new(roles: Vec<Role>) -> Self {
+        AccessPolicy { roles }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        !self.roles.is_empty()
+    }
+
+    pub fn check_read(&self, principal: Option<&Principal>, dataset: &str) -> Result<()> {
+        match self.authenticated(principal, dataset)? {
+            Some(principal) if !self.is_allowed(principal, dataset, Role::can_read) => {
+                ReadNotAllowedSnafu {
+                    principal: principal.name.clone(),
+                    dataset,
+                }
+                .fail()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_write(&self, principal: Option<&Principal>, dataset: &str) -> Result<()> {
+        match self.authenticated(principal, dataset)? {
+            Some(principal) if !self.is_allowed(principal, dataset, Role::can_write) => {
+                WriteNotAllowedSnafu {
+                    principal: principal.name.clone(),
+                    dataset,
+                }
+                .fail()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Verifies that `principal` may read every table scanned by `plan` and write to every table it modifies.
+    pub fn check_plan(&self, principal: Option<&Principal>, plan: &LogicalPlan) -> Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        let mut tables = ReferencedTables::default();
+        tables.collect(plan)?;
+
+        for dataset in &tables.read {
+            self.check_read(principal, dataset)?;
+        }
+        for dataset in &tables.write {
+            self.check_write(principal, dataset)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the principal to check the grants of, or an error if roles are defined and there is none.
+    fn authenticated<'a>(
+        &self,
+        principal: Option<&'a Principal>,
+        dataset: &str,
+    ) -> Result<Option<&'a Principal>> {
+        ensure!(
+            principal.is_some() || !self.is_enabled(),
+            AuthenticationRequiredSnafu { dataset }
+        );
+        Ok(principal)
+    }
+
+    fn is_allowed(
+        &self,
+        principal: &Principal,
+        dataset: &str,
+        grant: impl Fn(&Role, &str) -> bool,
+    ) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+
+        self.roles
+            .iter()
+            .filter(|role| role.has_principal(&principal.name))
+            .any(|role| grant(role, dataset))
+    }
+}
+
+#[derive(Default)]
+struct ReferencedTables {
+    read: BTreeSet<String>,
+    write: BTreeSet<String>,
+}
+
+impl ReferencedTables {
+    fn collect(&mut self, plan: &LogicalPlan) -> Result<()> {
+        match plan {
+            LogicalPlan::TableScan(scan) => {
+                if scan.table_name.schema() != Some(INFORMATION_SCHEMA) {
+                    self.read.insert(scan.table_name.table().to_string());
+                }
+            }
+            LogicalPlan::Dml(dml) => {
+                self.write.insert(dml.table_name.table().to_string());
+            }
+            LogicalPlan::Ddl(ddl) => {
+                let table = match ddl {
+                    DdlStatement::CreateExternalTable(CreateExternalTable { name, .. })
+                    | DdlStatement::CreateMemoryTable(CreateMemoryTable { name, .. })
+                    | DdlStatement::CreateView(CreateView { name, .. })
+                    | DdlStatement::DropTable(DropTable { name, .. })
+                    | DdlStatement::DropView(DropView { name, .. }) => name.table(),
+                    _ => ALL_DATASETS,
+                };
+                self.write.insert(table.to_string());
+            }
+            // The output is written outside of any dataset.
+            LogicalPlan::Copy(_) => {
+                self.write.insert(ALL_DATASETS.to_string());
+            }
+            _ => {}
+        }
+
+        // Subqueries in expressions are not inputs of the plan, i.e. `WHERE id IN (SELECT ...)`
+        for expr in plan.expressions() {
+            let mut subqueries = vec![];
+            expr.apply(&mut |expr| {
+                match expr {
+                    Expr::ScalarSubquery(subquery)
+                    | Expr::Exists(Exists { subquery, .. })
+                    | Expr::InSubquery(InSubquery { subquery, .. }) => {
+                        subqueries.push(subquery.subquery.clone());
+                    }
+                    _ => {}
+                }
+                Ok(VisitRecursion::Continue)
+            })
+            .context(UnableToInspectPlanSnafu)?;
+
+            for subquery in subqueries {
+                self.collect(&subquery)?;
+            }
+        }
+
+        for input in plan.inputs() {
+            self.collect(input)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role(principals: &[&str], read: &[&str], write: &[&str]) -> Role {
+        let to_vec = |values: &[&str]| values.iter().map(ToString::to_string).collect();
+        Role {
+            name: "test".to_string(),
+            principals: to_vec(principals),
+            read: to_vec(read),
+            write: to_vec(write),
+        }
+    }
+
+    #[test]
+    fn test_dataset_grants() {
+        let policy = AccessPolicy::new(vec![
+            role(&["analyst"], &["orders"], &[]),
+            role(&["ingest"], &["*"], &["orders"]),
+        ]);
+        let analyst = Principal::new("analyst");
+        let ingest = Principal::new("ingest");
+
+        assert!(policy.check_read(Some(&analyst), "orders").is_ok());
+        assert!(policy.check_read(Some(&analyst), "payments").is_err());
+        assert!(policy.check_write(Some(&analyst), "orders").is_err());
+        assert!(policy.check_read(Some(&ingest), "payments").is_ok());
+        assert!(policy.check_write(Some(&ingest), "orders").is_ok());
+        assert!(policy.check_write(Some(&ingest), "payments").is_err());
+        assert!(policy
+            .check_read(Some(&Principal::new("unknown")), "orders")
+            .is_err());
+
+        // Unauthenticated requests are denied once roles are defined, pods without roles are not restricted
+        assert!(matches!(
+            policy.check_read(None, "orders"),
+            Err(Error::AuthenticationRequired { .. })
+        ));
+        assert!(AccessPolicy::default()
+            .check_write(Some(&analyst), "payments")
+            .is_ok());
+        assert!(AccessPolicy::default()
+            .check_write(None, "payments")
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_check_plan_includes_subqueries() {
+        let ctx = datafusion::execution::context::SessionContext::new();
+        ctx.sql("CREATE TABLE orders (id INT, customer_id INT)")
+            .await
+            .expect("create orders");
+        ctx.sql("CREATE TABLE payments (order_id INT)")
+            .await
+            .expect("create payments");
+
+        let policy = AccessPolicy::new(vec![role(&["analyst"], &["orders"], &[])]);
+        let analyst = Principal::new("analyst");
+
+        let plan = ctx
+            .state()
+            .create_logical_plan("SELECT * FROM orders")
+            .await
+            .expect("plan");
+        assert!(policy.check_plan(Some(&analyst), &plan).is_ok());
+        assert!(policy.check_plan(None, &plan).is_err());
+
+        let plan = ctx
+            .state()
+            .create_logical_plan("SELECT * FROM orders WHERE id IN (SELECT order_id FROM payments)")
+            .await
+            .expect("plan");
+        assert!(policy.check_plan(Some(&analyst), &plan).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_plan_includes_ddl() {
+        let ctx = datafusion::execution::context::SessionContext::new();
+        ctx.sql("CREATE TABLE orders (id INT, customer_id INT)")
+            .await
+            .expect("create orders");
+
+        let policy = AccessPolicy::new(vec![
+            role(&["analyst"], &["orders"], &[]),
+            role(&["admin"], &["*"], &["*"]),
+        ]);
+        let analyst = Principal::new("analyst");
+        let guest = Principal::new("guest");
+        let admin = Principal::new("admin");
+
+        for sql in [
+            "DROP TABLE orders",
+            "CREATE TABLE orders_copy AS SELECT * FROM orders",
+            "CREATE VIEW orders_view AS SELECT * FROM orders",
+            "CREATE SCHEMA archive",
+            "COPY orders TO '/tmp/orders.csv'",
+        ] {
+            let plan = ctx.state().create_logical_plan(sql).await.expect("plan");
+            assert!(
+                policy.check_plan(Some(&guest), &plan).is_err(),
+                "{sql} should be denied without any grant"
+            );
+            assert!(
+                policy.check_plan(Some(&analyst), &plan).is_err(),
+                "{sql} should be denied without a write grant"
+            );
+            assert!(policy.check_plan(Some(&admin), &plan).is_ok());
+        }
+
+        // Planning does not execute the statement, so the table is still there
+        assert!(ctx.table_exist("orders").expect("table_exist"));
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use secrecy::{ExposeSecret, SecretString};
+use secrets::Secret;
+use uuid::Uuid;
+
+/// The secret key prefix for static API keys, i.e. `api_key_dashboards` is the key for the `dashboards` principal.
+const API_KEY_PREFIX: &str = "api_key_";
+
+/// The identity of an authenticated client.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Principal {
+    pub name: String,
+}
+
+impl Principal {
+    #[must_use]
+    pub fn new(name: impl Into<String>) -> Self {
+        Principal { name: name.into() }
+    }
+}
+
+struct IssuedToken {
+    principal: Principal,
+    expires_at: Instant,
+}
+
+/// Validates client credentials against the runtime auth secret and issues bearer tokens.
+///
+/// The auth secret may contain:
+/// - `username` and `password` for basic auth.
+/// - `api_key` and any number of `api_key_<principal>` static API keys, usable as bearer tokens.
+pub struct Authenticator {
+    basic: Option<(String, SecretString)>,
+    api_keys: Vec<(Principal, SecretString)>,
+    tokens: Mutex<HashMap<String, IssuedToken>>,
+    token_ttl: Duration,
+}
+
+impl Authenticator {
+    #[must_use]
+    pub fn new(secret: Option<&Secret>, token_ttl: Duration) -> Self {
+        let mut basic = None;
+        let mut api_keys = Vec::new();
+
+        if let Some(secret) = secret {
+            if let (Some(username), Some(password)) =
+                (secret.get("username"), secret.get("password"))
+            {
+                basic = Some((
+                    username.to_string(),
+                    SecretString::from(password.to_string()),
+                ));
+            }
+
+            for (key, value) in secret.iter() {
+                let principal = if key == "api_key" {
+                    Principal::new("api_key")
+                } else if let Some(name) = key.strip_prefix(API_KEY_PREFIX) {
+                    Principal::new(name)
+                } else {
+                    continue;
+                };
+                api_keys.push((principal, value.clone()));
+            }
+        }
+
+        if basic.is_none() && api_keys.is_empty() {
+            tracing::warn!("Authentication is enabled but no credentials are configured, all requests will be rejected");
+        }
+
+        Authenticator {
+            basic,
+            api_keys,
+            tokens: Mutex::new(HashMap::new()),
+            token_ttl,
+        }
+    }
+
+    #[must_use]
+    pub fn authenticate_basic(&self, username: &str, password: &str) -> Option<Principal> {
+        let (expected_username, expected_password) = self.basic.as_ref()?;
+        if constant_time_eq(username, expected_username)
+            && constant_time_eq(password, expected_password.expose_secret())
+        {
+            return Some(Principal::new(username));
+        }
+
+        None
+    }
+
+    /// Validates a static API key or a token previously issued by `issue_token`.
+    #[must_use]
+    pub fn authenticate_bearer(&self, token: &str) -> Option<Principal> {
+        for (principal, api_key) in &self.api_keys {
+            if constant_time_eq(token, api_key.expose_secret()) {
+                return Some(principal.clone());
+            }
+        }
+
+        let mut tokens = self.tokens.lock().ok()?;
+        let now = Instant::now();
+        tokens.retain(|_, issued| issued.expires_at > now);
+        tokens.get(token).map(|issued| issued.principal.clone())
+    }
+
+    /// Validates the value of an `authorization` header, either `Basic <base64 credentials>` or `Bearer <token>`.
+    #[must_use]
+    pub fn authenticate_header(&self, authorization: &str) -> Option<Principal> {
+        let (scheme, credentials) = authorization.trim().split_once(' ')?;
+        let credentials = credentials.trim();
+
+        if scheme.eq_ignore_ascii_case("bearer") {
+            return self.authenticate_bearer(credentials);
+        }
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = STANDARD.decode(credentials).ok()?;
+            let decoded = String::from_utf8(decoded).ok()?;
+            let (username, password) = decoded.split_once(':')?;
+            return self.authenticate_basic(username, password);
+        }
+
+        None
+    }
+
+    /// Issues a bearer token for `principal` that expires after the configured token lifetime.
+    #[must_use]
+    pub fn issue_token(&self, principal: Principal) -> String {
+        let token = Uuid::new_v4().to_string();
+        let now = Instant::now();
+
+        if let Ok(mut tokens) = self.tokens.lock() {
+            tokens.retain(|_, issued| issued.expires_at > now);
+            tokens.insert(
+                token.clone(),
+                IssuedToken {
+                    principal,
+                    expires_at: now + self.token_ttl,
+                },
+            );
+        }
+
+        token
+    }
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authenticator(token_ttl: Duration) -> Authenticator {
+        let secret = Secret::new(HashMap::from([
+            ("username".to_string(), "spice".to_string()),
+            ("password".to_string(), "secret".to_string()),
+            ("api_key_dashboards".to_string(), "key-1".to_string()),
+        ]));
+        Authenticator::new(Some(&secret), token_ttl)
+    }
+
+    #[test]
+    fn test_authenticate_header() {
+        let auth = authenticator(Duration::from_secs(60));
+
+        let basic = format!("Basic {}", STANDARD.encode("spice:secret"));
+        assert_eq!(
+            auth.authenticate_header(&basic),
+            Some(Principal::new("spice"))
+        );
+
+        let wrong_password = format!("Basic {}", STANDARD.encode("spice:wrong"));
+        assert_eq!(auth.authenticate_header(&wrong_password), None);
+
+        assert_eq!(
+            auth.authenticate_header("Bearer key-1"),
+            Some(Principal::new("dashboards"))
+        );
+        assert_eq!(auth.authenticate_header("Bearer key-2"), None);
+        assert_eq!(auth.authenticate_header("key-1"), None);
+    }
+
+    #[test]
+    fn test_issued_tokens_expire() {
+        let auth = authenticator(Duration::from_secs(60));
+        let token = auth.issue_token(Principal::new("spice"));
+        assert_eq!(
+            auth.authenticate_bearer(&token),
+            Some(Principal::new("spice"))
+        );
+
+        let auth = authenticator(Duration::ZERO);
+        let token = auth.issue_token(Principal::new("spice"));
+        assert_eq!(auth.authenticate_bearer(&token), None);
+    }
+}
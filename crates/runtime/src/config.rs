@@ -1,4 +1,8 @@
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::query::limits::{parse_byte_size, parse_duration};
 
 #[derive(Debug, Clone, clap::Parser)]
 pub struct Config {
@@ -28,4 +32,133 @@ pub struct Config {
         action
     )]
     pub open_telemetry_bind_address: SocketAddr,
+
+    #[clap(flatten)]
+    pub auth: AuthConfig,
+
+    #[clap(flatten)]
+    pub tls: TlsConfig,
+
+    #[clap(flatten)]
+    pub query: QueryConfig,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct AuthConfig {
+    /// Require clients of the Flight endpoint to authenticate.
+    #[arg(long = "flight-auth", action, help_heading = "Authentication")]
+    pub flight_auth: bool,
+
+    /// Require clients of the HTTP API to authenticate.
+    #[arg(long = "http-auth", action, help_heading = "Authentication")]
+    pub http_auth: bool,
+
+    /// Also require authentication for the HTTP `/health` endpoint, which is open by default.
+    #[arg(long = "http-auth-health", action, help_heading = "Authentication")]
+    pub http_auth_health: bool,
+
+    /// Name of the secret that holds the credentials clients authenticate with.
+    ///
+    /// The secret may define `username` and `password` for basic auth, and `api_key` or
+    /// `api_key_<name>` keys that are accepted as bearer tokens or in the `X-API-Key` header.
+    #[arg(
+        long = "auth-secret",
+        value_name = "SECRET_NAME",
+        default_value = "spiced_auth",
+        help_heading = "Authentication"
+    )]
+    pub auth_secret: String,
+
+    /// Lifetime of the bearer tokens issued by the Flight handshake, in seconds.
+    #[arg(
+        long = "auth-token-ttl",
+        value_name = "SECONDS",
+        default_value_t = 3600,
+        help_heading = "Authentication"
+    )]
+    pub token_ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct TlsConfig {
+    /// Path to the PEM encoded certificate chain used to serve the HTTP, Flight and OpenTelemetry endpoints over TLS.
+    #[arg(long = "tls-cert", value_name = "PATH", help_heading = "TLS")]
+    pub cert: Option<PathBuf>,
+
+    /// Path to the PEM encoded private key of the TLS certificate.
+    #[arg(long = "tls-key", value_name = "PATH", help_heading = "TLS")]
+    pub key: Option<PathBuf>,
+
+    /// Path to the PEM encoded CA certificates used to verify client certificates, enables mutual TLS.
+    #[arg(long = "tls-client-ca", value_name = "PATH", help_heading = "TLS")]
+    pub client_ca: Option<PathBuf>,
+
+    /// Name of the secret that holds the `cert`, `key` and `client_ca` TLS settings.
+    ///
+    /// Secret values may be file paths or PEM contents, and are overridden by the `--tls-*` options.
+    #[arg(long = "tls-secret", value_name = "SECRET_NAME", help_heading = "TLS")]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct QueryConfig {
+    /// Maximum time a query may run before it is cancelled, i.e. `30s` or `5m`.
+    ///
+    /// Clients may request a shorter timeout for a single query.
+    #[arg(
+        long = "query-timeout",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help_heading = "Query"
+    )]
+    pub timeout: Option<Duration>,
+
+    /// Memory shared by all running queries, i.e. `4GiB`. Operators that can spill to disk do so
+    /// once their share is used, others fail the query.
+    #[arg(
+        long = "query-memory-limit",
+        value_name = "BYTES",
+        value_parser = parse_byte_size,
+        help_heading = "Query"
+    )]
+    pub memory_limit: Option<usize>,
+
+    /// Directory that queries spill to when they exceed their share of `--query-memory-limit`.
+    ///
+    /// Defaults to the temporary directory of the OS.
+    #[arg(long = "query-spill-dir", value_name = "PATH", help_heading = "Query")]
+    pub spill_dir: Option<PathBuf>,
+
+    /// Maximum size of the results of a single query, i.e. `100MiB`.
+    ///
+    /// Clients may request a smaller limit for a single query.
+    #[arg(
+        long = "query-max-result-size",
+        value_name = "BYTES",
+        value_parser = parse_byte_size,
+        help_heading = "Query"
+    )]
+    pub max_result_size: Option<usize>,
+
+    /// Enables caching the results of queries against accelerated datasets, up to this size,
+    /// i.e. `512MiB`.
+    ///
+    /// Cached results are invalidated whenever a dataset they read is refreshed.
+    #[arg(
+        long = "query-cache-max-size",
+        value_name = "BYTES",
+        value_parser = parse_byte_size,
+        help_heading = "Query"
+    )]
+    pub cache_max_size: Option<usize>,
+
+    /// How long completed queries are kept in the `spice.runtime.query_history` table, `0s` disables it.
+    #[arg(
+        long = "query-history-retention",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        default_value = "1h",
+        help_heading = "Query"
+    )]
+    pub history_retention: Duration,
 }
@@ -7,6 +7,8 @@ use std::{collections::HashMap, sync::Arc};
 
 use self::{duckdb::DuckDBBackend, memtable::MemTableBackend};
 
+#[cfg(any(feature = "duckdb", feature = "postgres", feature = "sqlite"))]
+mod dml;
 #[cfg(feature = "duckdb")]
 pub mod duckdb;
 pub mod memtable;
@@ -26,6 +28,33 @@ pub enum Error {
     },
 }
 
+/// An existing acceleration table has no constraint to upsert its rows by the acceleration primary key.
+#[derive(Debug, Snafu)]
+#[snafu(display(
+    "The acceleration table {table} has no primary key or unique constraint on ({keys}), so upserts cannot be applied to it. Drop the table so it is recreated with the primary key, or remove primary_key from the acceleration."
+))]
+pub struct MissingPrimaryKeyConstraintError {
+    table: String,
+    keys: String,
+}
+
+/// Fails unless the existing acceleration `table` has a primary key or unique constraint on exactly the
+/// `primary_keys` columns.
+#[cfg(any(feature = "duckdb", feature = "postgres", feature = "sqlite"))]
+fn ensure_primary_key_constraint(
+    has_constraint: bool,
+    table: &str,
+    primary_keys: &[String],
+) -> std::result::Result<(), MissingPrimaryKeyConstraintError> {
+    if has_constraint {
+        return Ok(());
+    }
+    Err(MissingPrimaryKeyConstraintError {
+        table: table.to_string(),
+        keys: primary_keys.join(", "),
+    })
+}
+
 pub struct DataBackendBuilder {
     ctx: Arc<SessionContext>,
     name: String,
@@ -140,6 +169,7 @@ impl DataBackendBuilder {
                     self.params,
                     self.primary_keys,
                 )
+                .await
                 .boxed()
                 .context(BackendCreationFailedSnafu)?,
             )),
@@ -0,0 +1,310 @@
+//! Executes Flight SQL `UPDATE` and `DELETE` statements on the acceleration table of a SQL engine.
+//!
+//! The statement is written in the DataFusion dialect, so it is parsed and rendered again for the
+//! engine: identifiers are normalized and quoted like DataFusion resolves them, casts use the engine's
+//! type names, and anything the engines could read differently, like subqueries or functions outside
+//! a small portable set, is rejected.
+
+use std::ops::ControlFlow;
+
+use datafusion::{
+    execution::context::SessionContext,
+    sql::sqlparser::{
+        self,
+        ast::{Assignment, DataType, Expr, Ident, Statement, VisitMut, VisitorMut},
+        dialect::GenericDialect,
+        parser::Parser,
+    },
+};
+use db_connection_pool::{dbconnection, DbConnectionPool};
+use snafu::prelude::*;
+use spicepod::component::dataset::acceleration::Engine;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to parse SQL: {source}"))]
+    UnableToParseSql {
+        source: sqlparser::parser::ParserError,
+    },
+
+    #[snafu(display("Unsupported statement: {msg}"))]
+    UnsupportedStatement { msg: String },
+
+    #[snafu(display("Unable to connect to the acceleration: {source}"))]
+    UnableToConnect { source: db_connection_pool::Error },
+
+    #[snafu(display("Unable to execute statement: {source}"))]
+    UnableToExecute { source: dbconnection::Error },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Functions that take the same arguments and return the same results in DuckDB, Postgres and SQLite.
+const PORTABLE_FUNCTIONS: &[&str] = &[
+    "abs", "coalesce", "length", "lower", "ltrim", "nullif", "replace", "round", "rtrim", "substr",
+    "trim", "upper",
+];
+
+/// Executes an `UPDATE` or `DELETE` statement on the acceleration `table`, returning the number of
+/// affected rows.
+pub(crate) async fn execute<T: 'static, P: 'static>(
+    ctx: &SessionContext,
+    pool: &(dyn DbConnectionPool<T, P> + Send + Sync),
+    engine: Engine,
+    table: &str,
+    sql: &str,
+) -> Result<u64> {
+    let sql = render(sql, table, engine)?;
+
+    // The table is created lazily on the first update, so there are no rows to modify before it exists.
+    if !ctx.table_exist(table).unwrap_or(false) {
+        return Ok(0);
+    }
+
+    let conn = pool.connect().await.context(UnableToConnectSnafu)?;
+    tracing::trace!("{sql}");
+    dbconnection::execute(conn, &sql)
+        .await
+        .context(UnableToExecuteSnafu)
+}
+
+/// Renders the `UPDATE` or `DELETE` statement `sql` for `engine`, targeting the acceleration `table`.
+fn render(sql: &str, table: &str, engine: Engine) -> Result<String> {
+    let mut statements =
+        Parser::parse_sql(&GenericDialect {}, sql).context(UnableToParseSqlSnafu)?;
+    let (Some(statement), None) = (statements.pop(), statements.pop()) else {
+        return UnsupportedStatementSnafu {
+            msg: "expected a single statement",
+        }
+        .fail();
+    };
+
+    let mut renderer = ExprRenderer { engine };
+    let table = Ident::with_quote('"', table);
+    match statement {
+        Statement::Update {
+            table: target,
+            mut assignments,
+            from,
+            mut selection,
+            returning,
+            ..
+        } => {
+            ensure!(
+                target.joins.is_empty() && from.is_none() && returning.is_none(),
+                UnsupportedStatementSnafu {
+                    msg: "UPDATE only supports SET and WHERE clauses",
+                }
+            );
+
+            let assignments = assignments
+                .iter_mut()
+                .map(|Assignment { id, value }| {
+                    let column = id
+                        .last()
+                        .map(normalize)
+                        .context(UnsupportedStatementSnafu {
+                            msg: "missing column in SET",
+                        })?;
+                    renderer.render(value)?;
+                    Ok(format!("{column} = {value}"))
+                })
+                .collect::<Result<Vec<_>>>()?
+                .join(", ");
+            let selection = renderer.render_selection(selection.as_mut())?;
+            Ok(format!("UPDATE {table} SET {assignments}{selection}"))
+        }
+        Statement::Delete {
+            using,
+            mut selection,
+            returning,
+            ..
+        } => {
+            ensure!(
+                using.is_none() && returning.is_none(),
+                UnsupportedStatementSnafu {
+                    msg: "DELETE only supports a WHERE clause",
+                }
+            );
+
+            let selection = renderer.render_selection(selection.as_mut())?;
+            Ok(format!("DELETE FROM {table}{selection}"))
+        }
+        _ => UnsupportedStatementSnafu {
+            msg: "expected an UPDATE or DELETE statement",
+        }
+        .fail(),
+    }
+}
+
+/// Quotes an identifier, lowercasing it first unless it was quoted, like DataFusion resolves it.
+fn normalize(ident: &Ident) -> Ident {
+    match ident.quote_style {
+        Some(_) => Ident::with_quote('"', ident.value.clone()),
+        None => Ident::with_quote('"', ident.value.to_lowercase()),
+    }
+}
+
+struct ExprRenderer {
+    engine: Engine,
+}
+
+impl ExprRenderer {
+    fn render(&mut self, expr: &mut Expr) -> Result<()> {
+        match expr.visit(self) {
+            ControlFlow::Continue(()) => Ok(()),
+            ControlFlow::Break(e) => Err(e),
+        }
+    }
+
+    fn render_selection(&mut self, selection: Option<&mut Expr>) -> Result<String> {
+        match selection {
+            Some(selection) => {
+                self.render(selection)?;
+                Ok(format!(" WHERE {selection}"))
+            }
+            None => Ok(String::new()),
+        }
+    }
+
+    /// The engine's name for the type of a cast.
+    fn cast_type(&self, data_type: &DataType) -> Result<DataType> {
+        match self.engine {
+            #[cfg(feature = "postgres")]
+            Engine::Postgres => Ok(match data_type {
+                DataType::Double { .. } => DataType::DoublePrecision,
+                DataType::TinyInt { .. } => DataType::SmallInt(None),
+                _ => data_type.clone(),
+            }),
+            // SQLite only has storage classes, so a cast to any other type name is not portable.
+            #[cfg(feature = "sqlite")]
+            Engine::Sqlite => match data_type {
+                DataType::Int { .. }
+                | DataType::Integer { .. }
+                | DataType::BigInt { .. }
+                | DataType::SmallInt { .. }
+                | DataType::TinyInt { .. }
+                | DataType::Boolean => Ok(DataType::Integer(None)),
+                DataType::Float { .. }
+                | DataType::Real
+                | DataType::Double
+                | DataType::DoublePrecision
+                | DataType::Decimal { .. }
+                | DataType::Numeric { .. } => Ok(DataType::Real),
+                DataType::Varchar { .. } | DataType::Char { .. } | DataType::Text => {
+                    Ok(DataType::Text)
+                }
+                _ => UnsupportedStatementSnafu {
+                    msg: format!("cast to {data_type} is not supported by {}", self.engine),
+                }
+                .fail(),
+            },
+            _ => Ok(data_type.clone()),
+        }
+    }
+
+    fn render_expr(&self, expr: &mut Expr) -> Result<()> {
+        match expr {
+            Expr::Identifier(ident) => *ident = normalize(ident),
+            // The statement has a single table, so the column alone identifies it.
+            Expr::CompoundIdentifier(idents) => {
+                if let Some(column) = idents.last() {
+                    *expr = Expr::Identifier(normalize(column));
+                }
+            }
+            Expr::Cast { data_type, .. } => *data_type = self.cast_type(data_type)?,
+            Expr::TryCast { data_type, .. } | Expr::SafeCast { data_type, .. } => {
+                #[cfg(feature = "duckdb")]
+                if matches!(self.engine, Engine::DuckDB) {
+                    return Ok(());
+                }
+                return UnsupportedStatementSnafu {
+                    msg: format!(
+                        "TRY_CAST to {data_type} is not supported by {}",
+                        self.engine
+                    ),
+                }
+                .fail();
+            }
+            Expr::Function(function) => {
+                let name = function.name.to_string().to_lowercase();
+                ensure!(
+                    PORTABLE_FUNCTIONS.contains(&name.as_str()),
+                    UnsupportedStatementSnafu {
+                        msg: format!("function {name} is not supported in UPDATE or DELETE"),
+                    }
+                );
+            }
+            Expr::Subquery(_) | Expr::Exists { .. } | Expr::InSubquery { .. } => {
+                return UnsupportedStatementSnafu {
+                    msg: "subqueries are not supported in UPDATE or DELETE",
+                }
+                .fail();
+            }
+            Expr::Value(sqlparser::ast::Value::Placeholder(_)) => {
+                return UnsupportedStatementSnafu {
+                    msg: "parameters are not supported in UPDATE or DELETE",
+                }
+                .fail();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl VisitorMut for ExprRenderer {
+    type Break = Error;
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        match self.render_expr(expr) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(e) => ControlFlow::Break(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_quotes_identifiers_and_targets_the_table() {
+        let sql = render(
+            r#"UPDATE spice.public.trips SET Fare = lower("Note") WHERE trips.id = 1 AND note = 'it''s'"#,
+            "trips",
+            Engine::Arrow,
+        )
+        .expect("statement should render");
+        assert_eq!(
+            sql,
+            r#"UPDATE "trips" SET "fare" = lower("Note") WHERE "id" = 1 AND "note" = 'it''s'"#
+        );
+
+        let sql = render(
+            "DELETE FROM trips WHERE id IN (1, 2)",
+            "trips",
+            Engine::Arrow,
+        )
+        .expect("statement should render");
+        assert_eq!(sql, r#"DELETE FROM "trips" WHERE "id" IN (1, 2)"#);
+    }
+
+    #[test]
+    fn test_render_rejects_non_portable_statements() {
+        for sql in [
+            "DELETE FROM trips WHERE id IN (SELECT id FROM other)",
+            "DELETE FROM trips WHERE id = 1; DROP TABLE trips",
+            "UPDATE trips SET fare = now()",
+            "SELECT * FROM trips",
+        ] {
+            assert!(
+                matches!(
+                    render(sql, "trips", Engine::Arrow),
+                    Err(Error::UnsupportedStatement { .. })
+                ),
+                "{sql} should be rejected"
+            );
+        }
+    }
+}
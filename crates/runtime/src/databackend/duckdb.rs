@@ -3,9 +3,11 @@ use std::{
     collections::HashMap,
     fmt, mem,
     sync::{Arc, PoisonError},
+    time::SystemTime,
 };
 
 use arrow::record_batch::RecordBatch;
+use arrow_sql_gen::statement::{self, CreateTableBuilder};
 use datafusion::{execution::context::SessionContext, sql::TableReference};
 use db_connection_pool::{
     dbconnection::{self, duckdbconn::DuckDbConnection, SyncDbConnection},
@@ -14,12 +16,14 @@ use db_connection_pool::{
 };
 use duckdb::{vtab::arrow::arrow_recordbatch_to_query_params, DuckdbConnectionManager, ToSql};
 use snafu::{prelude::*, ResultExt};
-use spicepod::component::dataset::Dataset;
+use spicepod::component::dataset::{acceleration::Engine, Dataset};
 use sql_provider_datafusion::SqlTable;
 
 use crate::{
-    datapublisher::{AddDataResult, DataPublisher},
+    databackend::{self, dml},
+    datapublisher::{AddDataResult, DataPublisher, DeleteDataResult, ExecuteDmlResult},
     dataupdate::{DataUpdate, UpdateType},
+    retention,
 };
 
 #[derive(Debug, Snafu)]
@@ -50,6 +54,14 @@ pub enum Error {
 
     #[snafu(display("Unable to downcast DbConnection to DuckDbConnection"))]
     UnableToDowncastDbConnection {},
+
+    #[snafu(display("Unable to collapse rows with the same primary key: {source}"))]
+    UnableToCollapseRows { source: statement::Error },
+
+    #[snafu(display("{source}"))]
+    MissingPrimaryKeyConstraint {
+        source: databackend::MissingPrimaryKeyConstraintError,
+    },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -63,7 +75,7 @@ pub struct DuckDBBackend {
             + Sync,
     >,
     create_mutex: std::sync::Mutex<()>,
-    _primary_keys: Option<Vec<String>>,
+    primary_keys: Option<Vec<String>>,
 }
 
 impl DataPublisher for DuckDBBackend {
@@ -82,6 +94,7 @@ impl DataPublisher for DuckDBBackend {
                 name,
                 data: data_update.data,
                 update_type: data_update.update_type,
+                primary_keys: self.primary_keys.clone().unwrap_or_default(),
                 duckdb_conn: conn,
                 create_mutex: &self.create_mutex,
             };
@@ -93,6 +106,54 @@ impl DataPublisher for DuckDBBackend {
         })
     }
 
+    fn delete_expired_data(&self, dataset: Arc<Dataset>, cutoff: SystemTime) -> DeleteDataResult {
+        let pool = Arc::clone(&self.pool);
+        let name = self.name.clone();
+        Box::pin(async move {
+            let Some(predicate) = retention::expired_rows_predicate(&dataset, cutoff, |secs| {
+                format!("epoch_ms({})", secs * 1000)
+            }) else {
+                return Ok(0);
+            };
+
+            // The table is created lazily on the first update, so there is nothing to delete before it exists.
+            if !self.ctx.table_exist(name.as_str()).unwrap_or(false) {
+                return Ok(0);
+            }
+
+            let conn = pool.connect().await.context(DbConnectionPoolSnafu)?;
+            let Some(conn) = conn.as_sync() else {
+                return Err(
+                    Box::new(Error::UnableToDowncastDbConnection {}) as Box<dyn std::error::Error>
+                );
+            };
+
+            let sql = format!(r#"DELETE FROM "{name}" WHERE {predicate}"#);
+            tracing::trace!("{sql}");
+            let deleted = conn.execute(&sql, &[]).context(DbConnectionSnafu)?;
+
+            Ok(deleted)
+        })
+    }
+
+    fn execute_dml(&self, _dataset: Arc<Dataset>, sql: String) -> ExecuteDmlResult {
+        Box::pin(async move {
+            let affected = dml::execute(
+                &self.ctx,
+                self.pool.as_ref(),
+                Engine::DuckDB,
+                &self.name,
+                &sql,
+            )
+            .await?;
+            Ok(affected)
+        })
+    }
+
+    fn supports_dml(&self) -> bool {
+        true
+    }
+
     fn name(&self) -> &str {
         "DuckDB"
     }
@@ -100,7 +161,7 @@ impl DataPublisher for DuckDBBackend {
 
 impl DuckDBBackend {
     #[allow(clippy::needless_pass_by_value)]
-    pub fn new(
+    pub async fn new(
         ctx: Arc<SessionContext>,
         name: &str,
         mode: Mode,
@@ -109,13 +170,74 @@ impl DuckDBBackend {
     ) -> Result<Self> {
         let pool =
             DuckDbConnectionPool::new(name, &mode, &params).context(DbConnectionPoolSnafu)?;
-        Ok(DuckDBBackend {
+        let backend = DuckDBBackend {
             ctx,
             name: name.to_string(),
             pool: Arc::new(pool),
             create_mutex: std::sync::Mutex::new(()),
-            _primary_keys: primary_keys,
-        })
+            primary_keys,
+        };
+        backend.verify_primary_key_constraint().await?;
+
+        Ok(backend)
+    }
+
+    /// Checks that an existing table has a primary key or unique constraint on the configured primary keys.
+    ///
+    /// `INSERT OR REPLACE` requires such a constraint, so a table created before `primary_key` was configured
+    /// would otherwise fail on every insert.
+    async fn verify_primary_key_constraint(&self) -> Result<()> {
+        let Some(primary_keys) = self.primary_keys.clone().filter(|keys| !keys.is_empty()) else {
+            return Ok(());
+        };
+
+        let conn = self.pool.connect().await.context(DbConnectionPoolSnafu)?;
+        let Some(conn) = conn.as_any().downcast_ref::<DuckDbConnection>() else {
+            return UnableToDowncastDbConnectionSnafu {}.fail();
+        };
+
+        let table_exists: bool = conn
+            .conn
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM information_schema.tables WHERE table_name = ?)",
+                [&self.name],
+                |row| row.get(0),
+            )
+            .context(DuckDBSnafu)?;
+        // The table is created with the primary key on the first update.
+        if !table_exists {
+            return Ok(());
+        }
+
+        let mut stmt = conn
+            .conn
+            .prepare(
+                "SELECT constraint_index, unnest(constraint_column_names) FROM duckdb_constraints() \
+                 WHERE table_name = ? AND constraint_type IN ('PRIMARY KEY', 'UNIQUE')",
+            )
+            .context(DuckDBSnafu)?;
+        let rows = stmt
+            .query_map([&self.name], |row| {
+                Ok((row.get::<usize, i64>(0)?, row.get::<usize, String>(1)?))
+            })
+            .context(DuckDBSnafu)?;
+        let mut constraints: HashMap<i64, Vec<String>> = HashMap::new();
+        for row in rows {
+            let (index, column) = row.context(DuckDBSnafu)?;
+            constraints.entry(index).or_default().push(column);
+        }
+
+        let mut expected = primary_keys.clone();
+        expected.sort();
+        let has_constraint = constraints.into_values().any(|mut columns| {
+            columns.sort();
+            columns == expected
+        });
+
+        databackend::ensure_primary_key_constraint(has_constraint, &self.name, &primary_keys)
+            .context(MissingPrimaryKeyConstraintSnafu)?;
+
+        Ok(())
     }
 
     async fn initialize_datafusion(&self) -> Result<()> {
@@ -147,22 +269,42 @@ struct DuckDBUpdate<'a> {
     name: String,
     data: Vec<RecordBatch>,
     update_type: UpdateType,
+    primary_keys: Vec<String>,
     duckdb_conn: &'a mut dbconnection::duckdbconn::DuckDbConnection,
     create_mutex: &'a std::sync::Mutex<()>,
 }
 
 impl<'a> DuckDBUpdate<'a> {
     fn update(&mut self) -> Result<()> {
-        match self.update_type {
+        match self.update_type.clone() {
             UpdateType::Overwrite => self.create_table(true)?,
             UpdateType::Append => {
                 if !self.table_exists() {
                     self.create_table(false)?;
                 }
             }
+            UpdateType::Upsert { keys } => {
+                if self.table_exists() {
+                    self.delete_rows(&keys)?;
+                } else {
+                    self.create_table(false)?;
+                }
+            }
+            UpdateType::Delete { keys } => {
+                // There are no rows to delete before the table is created.
+                if self.table_exists() {
+                    self.delete_rows(&keys)?;
+                }
+                return Ok(());
+            }
         };
 
-        let data = mem::take(&mut self.data);
+        let mut data = mem::take(&mut self.data);
+        // `INSERT OR REPLACE` fails if a statement has the same key twice, so only the last row for each key is kept.
+        if !self.primary_keys.is_empty() {
+            data = statement::dedup_by_keys(&data, &self.primary_keys)
+                .context(UnableToCollapseRowsSnafu)?;
+        }
         for batch in data {
             self.insert_batch(&batch)?;
         }
@@ -173,8 +315,14 @@ impl<'a> DuckDBUpdate<'a> {
     }
 
     fn insert_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        // Rows with an existing primary key replace the current row instead of violating the constraint.
+        let insert = if self.primary_keys.is_empty() {
+            "INSERT"
+        } else {
+            "INSERT OR REPLACE"
+        };
         let sql = format!(
-            r#"INSERT INTO "{name}" SELECT * FROM arrow(?, ?)"#,
+            r#"{insert} INTO "{name}" SELECT * FROM arrow(?, ?)"#,
             name = self.name
         );
         tracing::trace!("{sql}");
@@ -192,6 +340,34 @@ impl<'a> DuckDBUpdate<'a> {
         Ok(())
     }
 
+    /// Deletes the rows that have the same `keys` as a row of the data.
+    fn delete_rows(&mut self, keys: &[String]) -> Result<()> {
+        let matches_keys = keys
+            .iter()
+            .map(|key| format!(r#"changes."{key}" = "{name}"."{key}""#, name = self.name))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+        let sql = format!(
+            r#"DELETE FROM "{name}" WHERE EXISTS (SELECT 1 FROM arrow(?, ?) AS changes WHERE {matches_keys})"#,
+            name = self.name
+        );
+        tracing::trace!("{sql}");
+
+        for batch in &self.data {
+            for sliced in Self::split_batch(batch) {
+                let params = arrow_recordbatch_to_query_params(sliced);
+                self.duckdb_conn
+                    .execute(
+                        &sql,
+                        &params.iter().map(|p| p as &dyn ToSql).collect::<Vec<_>>(),
+                    )
+                    .context(DbConnectionSnafu)?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn create_table(&mut self, drop_if_exists: bool) -> Result<()> {
         let _lock = self.create_mutex.lock().map_err(handle_poison)?;
 
@@ -207,6 +383,10 @@ impl<'a> DuckDBUpdate<'a> {
             }
         }
 
+        if !self.primary_keys.is_empty() {
+            return self.create_table_with_primary_keys();
+        }
+
         let Some(batch) = self.data.pop() else {
             return Ok(());
         };
@@ -240,6 +420,25 @@ impl<'a> DuckDBUpdate<'a> {
         Ok(())
     }
 
+    /// `CREATE TABLE AS` can't declare constraints, so tables with primary keys are created from the
+    /// schema up front and populated through the regular inserts.
+    fn create_table_with_primary_keys(&mut self) -> Result<()> {
+        let Some(batch) = self.data.first() else {
+            return Ok(());
+        };
+
+        let sql = CreateTableBuilder::new(batch.schema(), &self.name)
+            .primary_keys(self.primary_keys.iter().map(String::as_str).collect())
+            .build();
+        tracing::trace!("{sql}");
+
+        self.duckdb_conn
+            .execute(&sql, &[])
+            .context(DbConnectionSnafu)?;
+
+        Ok(())
+    }
+
     const MAX_BATCH_SIZE: usize = 2048;
 
     fn split_batch(batch: &RecordBatch) -> Vec<RecordBatch> {
@@ -281,6 +480,7 @@ fn handle_poison<T: fmt::Debug>(e: PoisonError<T>) -> Error {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testutil::string_values;
     use std::sync::Arc;
 
     use arrow::array::{Int32Array, StringArray};
@@ -293,6 +493,7 @@ mod tests {
         let name = "test_add_data";
         let backend =
             DuckDBBackend::new(Arc::clone(&ctx), name, Mode::Memory, Arc::new(None), None)
+                .await
                 .expect("Unable to create DuckDBBackend");
 
         let schema = Arc::new(Schema::new(vec![
@@ -328,4 +529,106 @@ mod tests {
             .expect("Unable to execute query");
         let _ = df.show().await;
     }
+
+    #[tokio::test]
+    async fn test_append_upserts_with_primary_keys() {
+        let ctx = Arc::new(SessionContext::new());
+        let name = "test_append_upserts_with_primary_keys";
+        let backend = DuckDBBackend::new(
+            Arc::clone(&ctx),
+            name,
+            Mode::Memory,
+            Arc::new(None),
+            Some(vec!["id".to_string()]),
+        )
+        .await
+        .expect("Unable to create DuckDBBackend");
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("value", DataType::Utf8, false),
+        ]));
+        let dataset = Arc::new(Dataset::new("test".to_string(), "test".to_string()));
+
+        for values in [vec!["a", "b"], vec!["c", "d"]] {
+            let batch = RecordBatch::try_new(
+                Arc::clone(&schema),
+                vec![
+                    Arc::new(Int32Array::from(vec![1, 2])),
+                    Arc::new(StringArray::from(values)),
+                ],
+            )
+            .expect("Unable to create record batch");
+
+            backend
+                .add_data(
+                    Arc::clone(&dataset),
+                    DataUpdate {
+                        data: vec![batch],
+                        update_type: UpdateType::Append,
+                    },
+                )
+                .await
+                .expect("Unable to add data");
+        }
+
+        let batches = ctx
+            .sql(&format!(r#"SELECT "value" FROM "{name}" ORDER BY "id""#))
+            .await
+            .expect("Unable to execute query")
+            .collect()
+            .await
+            .expect("Unable to collect results");
+        assert_eq!(string_values(&batches, 0), vec!["c", "d"]);
+    }
+
+    #[tokio::test]
+    async fn test_upsert_keeps_the_last_row_for_each_key() {
+        let ctx = Arc::new(SessionContext::new());
+        let name = "test_upsert_keeps_the_last_row_for_each_key";
+        let backend = DuckDBBackend::new(
+            Arc::clone(&ctx),
+            name,
+            Mode::Memory,
+            Arc::new(None),
+            Some(vec!["id".to_string()]),
+        )
+        .await
+        .expect("Unable to create DuckDBBackend");
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("value", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2, 1])),
+                Arc::new(StringArray::from(vec!["a", "b", "c"])),
+            ],
+        )
+        .expect("Unable to create record batch");
+
+        backend
+            .add_data(
+                Arc::new(Dataset::new("test".to_string(), "test".to_string())),
+                DataUpdate {
+                    data: vec![batch],
+                    update_type: UpdateType::Upsert {
+                        keys: vec!["id".to_string()],
+                    },
+                },
+            )
+            .await
+            .expect("Unable to add data");
+
+        let batches = ctx
+            .sql(&format!(r#"SELECT "value" FROM "{name}" ORDER BY "id""#))
+            .await
+            .expect("Unable to execute query")
+            .collect()
+            .await
+            .expect("Unable to collect results");
+        assert_eq!(string_values(&batches, 0), vec!["c", "b"]);
+    }
 }
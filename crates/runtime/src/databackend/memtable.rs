@@ -1,12 +1,14 @@
 use snafu::prelude::*;
 use spicepod::component::dataset::Dataset;
-use std::sync::Arc;
+use std::{sync::Arc, time::SystemTime};
+use tokio::sync::Mutex;
 
 use crate::{
-    datapublisher::{AddDataResult, DataPublisher},
+    datapublisher::{AddDataResult, DataPublisher, DeleteDataResult},
     dataupdate::{DataUpdate, UpdateType},
+    retention,
 };
-use arrow::record_batch::RecordBatch;
+use arrow::{array::Int64Array, record_batch::RecordBatch};
 use datafusion::{
     datasource::MemTable,
     error::DataFusionError,
@@ -31,6 +33,9 @@ pub enum Error {
 
     #[snafu(display("Invalid configuration: {msg}"))]
     InvalidConfiguration { msg: String },
+
+    #[snafu(display("Unable to delete data: {source}"))]
+    UnableToDeleteData { source: DataFusionError },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -38,6 +43,8 @@ type Result<T, E = Error> = std::result::Result<T, E>;
 pub struct MemTableBackend {
     ctx: Arc<SessionContext>,
     name: String,
+    // Deleting rows rewrites the whole table, so it must not interleave with appends.
+    update_mutex: Mutex<()>,
 }
 
 impl MemTableBackend {
@@ -46,7 +53,57 @@ impl MemTableBackend {
         MemTableBackend {
             ctx,
             name: name.to_owned(),
+            update_mutex: Mutex::new(()),
+        }
+    }
+
+    async fn count_rows(&self) -> Result<u64> {
+        let batches = self
+            .ctx
+            .sql(&format!(r#"SELECT COUNT(*) FROM "{}""#, self.name))
+            .await
+            .context(UnableToDeleteDataSnafu)?
+            .collect()
+            .await
+            .context(UnableToDeleteDataSnafu)?;
+
+        let count = batches
+            .first()
+            .and_then(|batch| batch.column(0).as_any().downcast_ref::<Int64Array>())
+            .map_or(0, |counts| counts.value(0));
+
+        Ok(u64::try_from(count).unwrap_or_default())
+    }
+
+    async fn delete_where(&self, predicate: &str) -> Result<u64> {
+        let _lock = self.update_mutex.lock().await;
+
+        let table_exists = self
+            .ctx
+            .table_exist(TableReference::bare(self.name.clone()))
+            .unwrap_or(false);
+        if !table_exists {
+            return Ok(0);
         }
+
+        let rows_before = self.count_rows().await?;
+
+        let sql = format!(
+            r#"CREATE OR REPLACE TABLE "{name}" AS SELECT * FROM "{name}" WHERE NOT ({predicate}) OR ({predicate}) IS NULL"#,
+            name = self.name,
+        );
+        tracing::trace!("Deleting data with SQL: {sql}");
+        self.ctx
+            .sql(&sql)
+            .await
+            .context(UnableToDeleteDataSnafu)?
+            .collect()
+            .await
+            .context(UnableToDeleteDataSnafu)?;
+
+        let rows_after = self.count_rows().await?;
+
+        Ok(rows_before.saturating_sub(rows_after))
     }
 }
 
@@ -58,6 +115,8 @@ impl DataPublisher for MemTableBackend {
                 return Ok(());
             }
 
+            let _lock = self.update_mutex.lock().await;
+
             let table_update = MemTableUpdate {
                 name: self.name.clone(),
                 data: data_update.data,
@@ -70,6 +129,18 @@ impl DataPublisher for MemTableBackend {
         })
     }
 
+    fn delete_expired_data(&self, dataset: Arc<Dataset>, cutoff: SystemTime) -> DeleteDataResult {
+        Box::pin(async move {
+            let Some(predicate) = retention::expired_rows_predicate(&dataset, cutoff, |secs| {
+                format!("to_timestamp_seconds({secs})")
+            }) else {
+                return Ok(0);
+            };
+
+            Ok(self.delete_where(&predicate).await?)
+        })
+    }
+
     fn name(&self) -> &str {
         "MemTable"
     }
@@ -89,7 +160,7 @@ impl MemTableUpdate {
 
     async fn update(&self) -> Result<()> {
         let temp_table_name = MemTableUpdate::temp_table_name(&self.name);
-        let sql_stmt = match self.update_type {
+        let sql_stmt = match &self.update_type {
             UpdateType::Overwrite => format!(
                 r#"CREATE OR REPLACE TABLE "{name}" AS SELECT * FROM "{temp_table_name}""#,
                 name = self.name,
@@ -108,6 +179,33 @@ impl MemTableUpdate {
                     temp_table_name = temp_table_name,
                 )
             }
+            UpdateType::Upsert { keys } => {
+                let table_created = self.create_table_if_not_exists()?;
+                if table_created {
+                    return Ok(());
+                }
+
+                format!(
+                    r#"CREATE OR REPLACE TABLE "{name}" AS SELECT * FROM "{name}" WHERE NOT EXISTS (SELECT 1 FROM "{temp_table_name}" AS changes WHERE {keys_match}) UNION ALL SELECT * FROM "{temp_table_name}""#,
+                    name = self.name,
+                    keys_match = self.keys_match(keys),
+                )
+            }
+            UpdateType::Delete { keys } => {
+                let table_exists = self
+                    .ctx
+                    .table_exist(TableReference::bare(self.name.clone()))
+                    .unwrap_or(false);
+                if !table_exists {
+                    return Ok(());
+                }
+
+                format!(
+                    r#"CREATE OR REPLACE TABLE "{name}" AS SELECT * FROM "{name}" WHERE NOT EXISTS (SELECT 1 FROM "{temp_table_name}" AS changes WHERE {keys_match})"#,
+                    name = self.name,
+                    keys_match = self.keys_match(keys),
+                )
+            }
         };
 
         // There is probably a better way to do this than registering a temp table
@@ -149,6 +247,14 @@ impl MemTableUpdate {
         Ok(())
     }
 
+    /// The predicate matching a row of the table to the row of the `changes` with the same keys.
+    fn keys_match(&self, keys: &[String]) -> String {
+        keys.iter()
+            .map(|key| format!(r#"changes."{key}" = "{name}"."{key}""#, name = self.name))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    }
+
     fn create_table_if_not_exists(&self) -> Result<bool> {
         let table_exists = self
             .ctx
@@ -183,3 +289,62 @@ impl Drop for MemTableUpdate {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::string_values;
+
+    use arrow::array::{Int32Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema};
+
+    fn batch(ids: Vec<i32>, values: Option<Vec<&str>>) -> RecordBatch {
+        let mut fields = vec![Field::new("id", DataType::Int32, false)];
+        let mut columns: Vec<Arc<dyn arrow::array::Array>> = vec![Arc::new(Int32Array::from(ids))];
+        if let Some(values) = values {
+            fields.push(Field::new("value", DataType::Utf8, false));
+            columns.push(Arc::new(StringArray::from(values)));
+        }
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+            .expect("Unable to create record batch")
+    }
+
+    #[tokio::test]
+    async fn test_upsert_and_delete() {
+        let ctx = Arc::new(SessionContext::new());
+        let name = "test_upsert_and_delete";
+        let backend = MemTableBackend::new(Arc::clone(&ctx), name);
+        let dataset = Arc::new(Dataset::new("test".to_string(), "test".to_string()));
+        let keys = vec!["id".to_string()];
+
+        let updates = [
+            (batch(vec![1, 2], Some(vec!["a", "b"])), UpdateType::Append),
+            (
+                batch(vec![2, 3], Some(vec!["c", "d"])),
+                UpdateType::Upsert { keys: keys.clone() },
+            ),
+            (batch(vec![1], None), UpdateType::Delete { keys }),
+        ];
+        for (data, update_type) in updates {
+            backend
+                .add_data(
+                    Arc::clone(&dataset),
+                    DataUpdate {
+                        data: vec![data],
+                        update_type,
+                    },
+                )
+                .await
+                .expect("Unable to add data");
+        }
+
+        let batches = ctx
+            .sql(&format!(r#"SELECT "value" FROM "{name}" ORDER BY "id""#))
+            .await
+            .expect("Unable to execute query")
+            .collect()
+            .await
+            .expect("Unable to collect results");
+        assert_eq!(string_values(&batches, 0), vec!["c", "d"]);
+    }
+}
@@ -1,7 +1,7 @@
-use std::{collections::HashMap, mem, sync::Arc};
+use std::{collections::HashMap, mem, sync::Arc, time::SystemTime};
 
 use arrow::record_batch::RecordBatch;
-use arrow_sql_gen::statement::{CreateTableBuilder, InsertBuilder};
+use arrow_sql_gen::statement::{CreateTableBuilder, DeleteBuilder, InsertBuilder};
 use bb8_postgres::{
     tokio_postgres::{types::ToSql, NoTls, Transaction},
     PostgresConnectionManager,
@@ -13,13 +13,15 @@ use db_connection_pool::{
 };
 use secrets::Secret;
 use snafu::{prelude::*, ResultExt};
-use spicepod::component::dataset::Dataset;
+use spicepod::component::dataset::{acceleration::Engine, Dataset};
 use sql_provider_datafusion::SqlTable;
 use tokio::sync::Mutex;
 
 use crate::{
-    datapublisher::{AddDataResult, DataPublisher},
+    databackend::{self, dml},
+    datapublisher::{AddDataResult, DataPublisher, DeleteDataResult, ExecuteDmlResult},
     dataupdate::{DataUpdate, UpdateType},
+    retention,
 };
 
 #[derive(Debug, Snafu)]
@@ -52,6 +54,22 @@ pub enum Error {
 
     #[snafu(display("Unable to downcast DbConnection to PostgresConnection"))]
     UnableToDowncastDbConnection {},
+
+    #[snafu(display("Unable to build the SQL statement: {source}"))]
+    UnableToBuildStatement {
+        source: arrow_sql_gen::statement::Error,
+    },
+
+    #[snafu(display("Unable to read the constraints of postgres table {table}: {source}"))]
+    UnableToReadConstraints {
+        table: String,
+        source: bb8_postgres::tokio_postgres::Error,
+    },
+
+    #[snafu(display("{source}"))]
+    MissingPrimaryKeyConstraint {
+        source: databackend::MissingPrimaryKeyConstraintError,
+    },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -68,7 +86,7 @@ pub struct PostgresBackend {
             + Sync,
     >,
     create_mutex: Mutex<()>,
-    _primary_keys: Option<Vec<String>>,
+    primary_keys: Option<Vec<String>>,
 }
 
 impl DataPublisher for PostgresBackend {
@@ -79,6 +97,7 @@ impl DataPublisher for PostgresBackend {
                 name,
                 data: data_update.data,
                 update_type: data_update.update_type,
+                primary_keys: self.primary_keys.clone().unwrap_or_default(),
                 pool: Arc::clone(&self.pool),
                 create_mutex: &self.create_mutex,
             };
@@ -90,6 +109,55 @@ impl DataPublisher for PostgresBackend {
         })
     }
 
+    fn delete_expired_data(&self, dataset: Arc<Dataset>, cutoff: SystemTime) -> DeleteDataResult {
+        let pool = Arc::clone(&self.pool);
+        let name = self.name.clone();
+        Box::pin(async move {
+            // `to_timestamp` returns a `timestamptz`, the same instant in any session time zone.
+            let Some(predicate) = retention::expired_rows_predicate(&dataset, cutoff, |secs| {
+                format!("to_timestamp({secs})")
+            }) else {
+                return Ok(0);
+            };
+
+            // The table is created lazily on the first update, so there is nothing to delete before it exists.
+            if !self.ctx.table_exist(name.as_str()).unwrap_or(false) {
+                return Ok(0);
+            }
+
+            let conn = pool.connect().await.context(DbConnectionPoolSnafu)?;
+            let Some(conn) = conn.as_async() else {
+                return Err(
+                    Box::new(Error::UnableToDowncastDbConnection {}) as Box<dyn std::error::Error>
+                );
+            };
+
+            let sql = format!(r#"DELETE FROM "{name}" WHERE {predicate}"#);
+            tracing::trace!("{sql}");
+            let deleted = conn.execute(&sql, &[]).await.context(DbConnectionSnafu)?;
+
+            Ok(deleted)
+        })
+    }
+
+    fn execute_dml(&self, _dataset: Arc<Dataset>, sql: String) -> ExecuteDmlResult {
+        Box::pin(async move {
+            let affected = dml::execute(
+                &self.ctx,
+                self.pool.as_ref(),
+                Engine::Postgres,
+                &self.name,
+                &sql,
+            )
+            .await?;
+            Ok(affected)
+        })
+    }
+
+    fn supports_dml(&self) -> bool {
+        true
+    }
+
     fn name(&self) -> &str {
         "Postgres"
     }
@@ -107,13 +175,67 @@ impl PostgresBackend {
         let pool = PostgresConnectionPool::new(params, secret)
             .await
             .context(DbConnectionPoolSnafu)?;
-        Ok(PostgresBackend {
+        let backend = PostgresBackend {
             ctx,
             name: name.to_string(),
             pool: Arc::new(pool),
             create_mutex: Mutex::new(()),
-            _primary_keys: primary_keys,
-        })
+            primary_keys,
+        };
+        backend.verify_primary_key_constraint().await?;
+
+        Ok(backend)
+    }
+
+    /// Checks that an existing table has a primary key or unique constraint on the configured primary keys.
+    ///
+    /// `ON CONFLICT` requires such a constraint, so a table created before `primary_key` was configured would
+    /// otherwise fail on every upsert.
+    async fn verify_primary_key_constraint(&self) -> Result<()> {
+        let Some(primary_keys) = self.primary_keys.clone().filter(|keys| !keys.is_empty()) else {
+            return Ok(());
+        };
+
+        let conn = self.pool.connect().await.context(DbConnectionPoolSnafu)?;
+        let Some(conn) = conn.as_any().downcast_ref::<PostgresConnection>() else {
+            return UnableToDowncastDbConnectionSnafu {}.fail();
+        };
+
+        // The table is created with the primary key on the first update, so a missing table is fine.
+        let sql = r"SELECT NOT EXISTS (
+              SELECT 1
+              FROM information_schema.tables
+              WHERE table_name = $1::text
+            ) OR EXISTS (
+              SELECT 1
+              FROM pg_index i
+              JOIN pg_class c ON c.oid = i.indrelid
+              CROSS JOIN LATERAL (
+                SELECT array_agg(a.attname::text) AS names
+                FROM pg_attribute a
+                WHERE a.attrelid = c.oid AND a.attnum = ANY(i.indkey)
+              ) index_columns
+              WHERE c.relname = $1::text
+              AND i.indisunique
+              AND i.indpred IS NULL
+              AND index_columns.names @> $2::text[]
+              AND index_columns.names <@ $2::text[]
+            )";
+        tracing::trace!("{sql}");
+
+        let row = conn
+            .conn
+            .query_one(sql, &[&self.name, &primary_keys])
+            .await
+            .context(UnableToReadConstraintsSnafu {
+                table: self.name.clone(),
+            })?;
+        let has_constraint: bool = row.get(0);
+
+        databackend::ensure_primary_key_constraint(has_constraint, &self.name, &primary_keys)
+            .context(MissingPrimaryKeyConstraintSnafu)?;
+
+        Ok(())
     }
 
     async fn initialize_datafusion(&self) -> Result<()> {
@@ -145,6 +267,7 @@ struct PostgresUpdate<'a> {
     name: String,
     data: Vec<RecordBatch>,
     update_type: UpdateType,
+    primary_keys: Vec<String>,
     pool: Arc<
         dyn DbConnectionPool<
                 bb8::PooledConnection<'static, PostgresConnectionManager<NoTls>>,
@@ -176,18 +299,30 @@ impl<'a> PostgresUpdate<'a> {
             return UnableToDowncastDbConnectionSnafu {}.fail();
         };
 
-        if !self.table_exists(conn).await {
-            self.create_table(&transaction).await?;
-        } else if self.update_type == UpdateType::Overwrite {
-            transaction
-                .execute(format!(r#"DELETE FROM "{}""#, self.name).as_str(), &[])
-                .await
-                .context(TransactionSnafu)?;
+        let table_exists = self.table_exists(conn).await;
+        match self.update_type.clone() {
+            // There are no rows to delete before the table is created.
+            UpdateType::Delete { keys } => {
+                if table_exists {
+                    self.delete_rows(&transaction, &keys).await?;
+                }
+            }
+            _ if !table_exists => self.create_table(&transaction).await?,
+            UpdateType::Overwrite => {
+                transaction
+                    .execute(format!(r#"DELETE FROM "{}""#, self.name).as_str(), &[])
+                    .await
+                    .context(TransactionSnafu)?;
+            }
+            UpdateType::Upsert { keys } => self.delete_rows(&transaction, &keys).await?,
+            UpdateType::Append => {}
         };
 
-        let data = mem::take(&mut self.data);
-        for batch in data {
-            self.insert_batch(&transaction, batch).await?;
+        if !matches!(self.update_type, UpdateType::Delete { .. }) {
+            let data = mem::take(&mut self.data);
+            for batch in data {
+                self.insert_batch(&transaction, batch).await?;
+            }
         }
 
         transaction.commit().await.context(TransactionSnafu)?;
@@ -205,8 +340,14 @@ impl<'a> PostgresUpdate<'a> {
         transaction: &Transaction<'_>,
         batch: RecordBatch,
     ) -> Result<()> {
-        let insert_table_builder = InsertBuilder::new(&self.name, vec![batch]);
-        let sql = insert_table_builder.build();
+        let mut insert_table_builder = InsertBuilder::new(&self.name, vec![batch]);
+        if !self.primary_keys.is_empty() {
+            insert_table_builder = insert_table_builder
+                .on_conflict_update(self.primary_keys.iter().map(String::as_str).collect());
+        }
+        let sql = insert_table_builder
+            .build()
+            .context(UnableToBuildStatementSnafu)?;
 
         transaction
             .execute(&sql, &[])
@@ -216,6 +357,24 @@ impl<'a> PostgresUpdate<'a> {
         Ok(())
     }
 
+    /// Deletes the rows that have the same `keys` as a row of the data.
+    async fn delete_rows(&self, transaction: &Transaction<'_>, keys: &[String]) -> Result<()> {
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        for batch in &self.data {
+            let sql = DeleteBuilder::new(&self.name, vec![batch.clone()], keys.clone())
+                .build()
+                .context(UnableToBuildStatementSnafu)?;
+            tracing::trace!("{sql}");
+
+            transaction
+                .execute(&sql, &[])
+                .await
+                .context(TransactionSnafu)?;
+        }
+
+        Ok(())
+    }
+
     async fn create_table(&mut self, transaction: &Transaction<'_>) -> Result<()> {
         let _lock = self.create_mutex.lock();
 
@@ -223,7 +382,8 @@ impl<'a> PostgresUpdate<'a> {
             return Ok(());
         };
 
-        let create_table_statement = CreateTableBuilder::new(batch.schema(), &self.name);
+        let create_table_statement = CreateTableBuilder::new(batch.schema(), &self.name)
+            .primary_keys(self.primary_keys.iter().map(String::as_str).collect());
         let sql = create_table_statement.build();
 
         transaction
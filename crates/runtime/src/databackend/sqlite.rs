@@ -1,7 +1,7 @@
-use std::{collections::HashMap, mem, sync::Arc};
+use std::{collections::HashMap, mem, sync::Arc, time::SystemTime};
 
 use arrow::record_batch::RecordBatch;
-use arrow_sql_gen::statement::{CreateTableBuilder, InsertBuilder};
+use arrow_sql_gen::statement::{CreateTableBuilder, DeleteBuilder, InsertBuilder};
 use datafusion::{execution::context::SessionContext, sql::TableReference};
 use db_connection_pool::{
     dbconnection::sqliteconn::SqliteConnection, sqlitepool::SqliteConnectionPool, DbConnectionPool,
@@ -9,13 +9,15 @@ use db_connection_pool::{
 };
 use rusqlite::{ToSql, Transaction};
 use snafu::{prelude::*, ResultExt};
-use spicepod::component::dataset::Dataset;
+use spicepod::component::dataset::{acceleration::Engine, Dataset};
 use sql_provider_datafusion::SqlTable;
 use tokio_rusqlite::Connection;
 
 use crate::{
-    datapublisher::{AddDataResult, DataPublisher},
+    databackend::{self, dml},
+    datapublisher::{AddDataResult, DataPublisher, DeleteDataResult, ExecuteDmlResult},
     dataupdate::{DataUpdate, UpdateType},
+    retention,
 };
 
 #[derive(Debug, Snafu)]
@@ -43,6 +45,17 @@ pub enum Error {
 
     #[snafu(display("Unable to downcast DbConnection to SqliteConnection"))]
     UnableToDowncastDbConnection {},
+
+    #[snafu(display("Unable to read the constraints of sqlite table {table}: {source}"))]
+    UnableToReadConstraints {
+        table: String,
+        source: tokio_rusqlite::Error,
+    },
+
+    #[snafu(display("{source}"))]
+    MissingPrimaryKeyConstraint {
+        source: databackend::MissingPrimaryKeyConstraintError,
+    },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -52,7 +65,7 @@ pub struct SqliteBackend {
     ctx: Arc<SessionContext>,
     name: String,
     pool: Arc<dyn DbConnectionPool<Connection, &'static (dyn ToSql + Sync)> + Send + Sync>,
-    _primary_keys: Option<Vec<String>>,
+    primary_keys: Option<Vec<String>>,
 }
 
 impl DataPublisher for SqliteBackend {
@@ -64,6 +77,7 @@ impl DataPublisher for SqliteBackend {
                 name,
                 data: data_update.data,
                 update_type: data_update.update_type,
+                primary_keys: self.primary_keys.clone().unwrap_or_default(),
                 pool,
             };
 
@@ -74,6 +88,54 @@ impl DataPublisher for SqliteBackend {
         })
     }
 
+    fn delete_expired_data(&self, dataset: Arc<Dataset>, cutoff: SystemTime) -> DeleteDataResult {
+        let pool = Arc::clone(&self.pool);
+        let name = self.name.clone();
+        Box::pin(async move {
+            let Some(predicate) = retention::expired_rows_predicate(&dataset, cutoff, |secs| {
+                format!("datetime({secs}, 'unixepoch')")
+            }) else {
+                return Ok(0);
+            };
+
+            // The table is created lazily on the first update, so there is nothing to delete before it exists.
+            if !self.ctx.table_exist(name.as_str()).unwrap_or(false) {
+                return Ok(0);
+            }
+
+            let conn = pool.connect().await.context(DbConnectionPoolSnafu)?;
+            let Some(conn) = conn.as_async() else {
+                return Err(
+                    Box::new(Error::UnableToDowncastDbConnection {}) as Box<dyn std::error::Error>
+                );
+            };
+
+            let sql = format!(r#"DELETE FROM "{name}" WHERE {predicate}"#);
+            tracing::trace!("{sql}");
+            let deleted = conn.execute(&sql, &[]).await.context(DbConnectionSnafu)?;
+
+            Ok(deleted)
+        })
+    }
+
+    fn execute_dml(&self, _dataset: Arc<Dataset>, sql: String) -> ExecuteDmlResult {
+        Box::pin(async move {
+            let affected = dml::execute(
+                &self.ctx,
+                self.pool.as_ref(),
+                Engine::Sqlite,
+                &self.name,
+                &sql,
+            )
+            .await?;
+            Ok(affected)
+        })
+    }
+
+    fn supports_dml(&self) -> bool {
+        true
+    }
+
     fn name(&self) -> &str {
         "Sqlite"
     }
@@ -91,12 +153,84 @@ impl SqliteBackend {
         let pool = SqliteConnectionPool::new(name, mode, params)
             .await
             .context(DbConnectionPoolSnafu)?;
-        Ok(SqliteBackend {
+        let backend = SqliteBackend {
             ctx,
             name: name.to_string(),
             pool: Arc::new(pool),
-            _primary_keys: primary_keys,
-        })
+            primary_keys,
+        };
+        backend.verify_primary_key_constraint().await?;
+
+        Ok(backend)
+    }
+
+    /// Checks that an existing table has a primary key or unique constraint on the configured primary keys.
+    ///
+    /// `ON CONFLICT` requires such a constraint, so a table created before `primary_key` was configured would
+    /// otherwise fail on every upsert.
+    async fn verify_primary_key_constraint(&self) -> Result<()> {
+        let Some(primary_keys) = self.primary_keys.clone().filter(|keys| !keys.is_empty()) else {
+            return Ok(());
+        };
+
+        let conn = self.pool.connect().await.context(DbConnectionPoolSnafu)?;
+        let Some(conn) = conn.as_any().downcast_ref::<SqliteConnection>() else {
+            return UnableToDowncastDbConnectionSnafu {}.fail();
+        };
+
+        let name = self.name.clone();
+        let mut expected = primary_keys.clone();
+        expected.sort();
+        let has_constraint = conn
+            .conn
+            .call(move |conn| {
+                let table_exists: bool = conn.query_row(
+                    "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+                    [&name],
+                    |row| row.get(0),
+                )?;
+                // The table is created with the primary key on the first update.
+                if !table_exists {
+                    return Ok(true);
+                }
+
+                let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1) WHERE pk > 0")?;
+                let mut columns = stmt
+                    .query_map([&name], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                columns.sort();
+                if columns == expected {
+                    return Ok(true);
+                }
+
+                let mut stmt = conn.prepare(
+                    r#"SELECT name FROM pragma_index_list(?1) WHERE "unique" = 1 AND partial = 0"#,
+                )?;
+                let indexes = stmt
+                    .query_map([&name], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                let mut stmt = conn.prepare("SELECT name FROM pragma_index_info(?1)")?;
+                for index in indexes {
+                    let mut columns = stmt
+                        .query_map([&index], |row| row.get::<_, String>(0))?
+                        .collect::<rusqlite::Result<Vec<_>>>()?;
+                    columns.sort();
+                    if columns == expected {
+                        return Ok(true);
+                    }
+                }
+
+                Ok(false)
+            })
+            .await
+            .context(UnableToReadConstraintsSnafu {
+                table: self.name.clone(),
+            })?;
+
+        databackend::ensure_primary_key_constraint(has_constraint, &self.name, &primary_keys)
+            .context(MissingPrimaryKeyConstraintSnafu)?;
+
+        Ok(())
     }
 
     async fn initialize_datafusion(&self) -> Result<()> {
@@ -128,6 +262,7 @@ struct SqliteUpdate {
     name: String,
     data: Vec<RecordBatch>,
     update_type: UpdateType,
+    primary_keys: Vec<String>,
     pool: Arc<dyn DbConnectionPool<Connection, &'static (dyn ToSql + Sync)> + Send + Sync>,
 }
 
@@ -153,15 +288,27 @@ impl SqliteUpdate {
             .call(move |conn| {
                 let transaction = conn.transaction()?;
 
-                if !table_exists {
-                    self.create_table(&transaction)?;
-                } else if self.update_type == UpdateType::Overwrite {
-                    transaction.execute(format!(r#"DELETE FROM "{}""#, self.name).as_str(), [])?;
+                match self.update_type.clone() {
+                    // There are no rows to delete before the table is created.
+                    UpdateType::Delete { keys } => {
+                        if table_exists {
+                            self.delete_rows(&transaction, &keys)?;
+                        }
+                    }
+                    _ if !table_exists => self.create_table(&transaction)?,
+                    UpdateType::Overwrite => {
+                        transaction
+                            .execute(format!(r#"DELETE FROM "{}""#, self.name).as_str(), [])?;
+                    }
+                    UpdateType::Upsert { keys } => self.delete_rows(&transaction, &keys)?,
+                    UpdateType::Append => {}
                 };
 
-                let data = mem::take(&mut self.data);
-                for batch in data {
-                    self.insert_batch(&transaction, batch)?;
+                if !matches!(self.update_type, UpdateType::Delete { .. }) {
+                    let data = mem::take(&mut self.data);
+                    for batch in data {
+                        self.insert_batch(&transaction, batch)?;
+                    }
                 }
 
                 transaction.commit()?;
@@ -179,20 +326,46 @@ impl SqliteUpdate {
         transaction: &Transaction<'_>,
         batch: RecordBatch,
     ) -> tokio_rusqlite::Result<()> {
-        let insert_table_builder = InsertBuilder::new(&self.name, vec![batch]);
-        let sql = insert_table_builder.build();
+        let mut insert_table_builder = InsertBuilder::new(&self.name, vec![batch]);
+        if !self.primary_keys.is_empty() {
+            insert_table_builder = insert_table_builder
+                .on_conflict_update(self.primary_keys.iter().map(String::as_str).collect());
+        }
+        let sql = insert_table_builder
+            .build()
+            .map_err(|e| tokio_rusqlite::Error::Other(Box::new(e)))?;
 
         transaction.execute(&sql, [])?;
 
         Ok(())
     }
 
+    /// Deletes the rows that have the same `keys` as a row of the data.
+    fn delete_rows(
+        &self,
+        transaction: &Transaction<'_>,
+        keys: &[String],
+    ) -> tokio_rusqlite::Result<()> {
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        for batch in &self.data {
+            let sql = DeleteBuilder::new(&self.name, vec![batch.clone()], keys.clone())
+                .build()
+                .map_err(|e| tokio_rusqlite::Error::Other(Box::new(e)))?;
+            tracing::trace!("{sql}");
+
+            transaction.execute(&sql, [])?;
+        }
+
+        Ok(())
+    }
+
     fn create_table(&mut self, transaction: &Transaction<'_>) -> tokio_rusqlite::Result<()> {
         let Some(batch) = self.data.pop() else {
             return Ok(());
         };
 
-        let create_table_statement = CreateTableBuilder::new(batch.schema(), &self.name);
+        let create_table_statement = CreateTableBuilder::new(batch.schema(), &self.name)
+            .primary_keys(self.primary_keys.iter().map(String::as_str).collect());
         let sql = create_table_statement.build();
 
         transaction.execute(&sql, [])?;
@@ -225,3 +398,68 @@ impl SqliteUpdate {
             .unwrap_or(false)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_existing_table_without_primary_key_is_rejected() {
+        let path =
+            std::env::temp_dir().join(format!("spice_sqlite_pk_test_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let params = Arc::new(Some(HashMap::from([(
+            "sqlite_file".to_string(),
+            path.to_string_lossy().to_string(),
+        )])));
+
+        let backend = SqliteBackend::new(
+            Arc::new(SessionContext::new()),
+            "pk_test",
+            Arc::clone(&params),
+            Mode::File,
+            None,
+        )
+        .await
+        .expect("backend should be created");
+        let conn = backend.pool.connect().await.expect("connection");
+        conn.as_async()
+            .expect("async connection")
+            .execute(r#"CREATE TABLE "pk_test" ("id" INTEGER, "name" TEXT)"#, &[])
+            .await
+            .expect("table should be created");
+
+        let result = SqliteBackend::new(
+            Arc::new(SessionContext::new()),
+            "pk_test",
+            Arc::clone(&params),
+            Mode::File,
+            Some(vec!["id".to_string()]),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(Error::MissingPrimaryKeyConstraint { .. })
+        ));
+
+        conn.as_async()
+            .expect("async connection")
+            .execute(
+                r#"CREATE UNIQUE INDEX "pk_test_id" ON "pk_test" ("id")"#,
+                &[],
+            )
+            .await
+            .expect("index should be created");
+        SqliteBackend::new(
+            Arc::new(SessionContext::new()),
+            "pk_test",
+            params,
+            Mode::File,
+            Some(vec!["id".to_string()]),
+        )
+        .await
+        .expect("a unique index satisfies the primary key");
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
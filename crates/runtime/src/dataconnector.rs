@@ -1,9 +1,18 @@
+use arrow::datatypes::DataType;
 use async_trait::async_trait;
 use datafusion::datasource::TableProvider;
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::SessionContext;
+use datafusion::scalar::ScalarValue;
+use datafusion::sql::sqlparser::{
+    ast::{visit_expressions, Expr},
+    dialect::Dialect,
+    parser::{Parser, ParserError},
+    tokenizer::Token,
+};
 use futures::stream;
 use object_store::ObjectStore;
 use snafu::prelude::*;
-use spicepod::component::dataset::acceleration::RefreshMode;
 use spicepod::component::dataset::Dataset;
 use std::collections::HashMap;
 use std::pin::Pin;
@@ -23,8 +32,10 @@ use crate::timing::TimeMeasurement;
 pub mod databricks;
 pub mod debug;
 pub mod dremio;
+pub mod file;
 pub mod flight;
 pub mod flightsql;
+pub mod materializedview;
 pub mod postgres;
 pub mod s3;
 pub mod spiceai;
@@ -40,11 +51,77 @@ pub enum Error {
     UnableToGetTableProvider {
         source: Box<dyn std::error::Error + Send + Sync>,
     },
+
+    #[snafu(display("The data connector for dataset {dataset} does not support filtered data"))]
+    FilteredDataNotSupported { dataset: String },
+
+    #[snafu(display("Unable to parse filter {filter}: {source}"))]
+    UnableToParseFilter { filter: String, source: ParserError },
+
+    #[snafu(display("Invalid filter {filter}: {reason}"))]
+    InvalidFilter { filter: String, reason: String },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 pub type AnyErrorResult = std::result::Result<(), Box<dyn std::error::Error>>;
 
+pub type DataError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The data fetched by a `DataConnector`, or the error that kept it from being fetched.
+pub type DataResult = std::result::Result<Vec<RecordBatch>, DataError>;
+
+/// A `DataUpdate` that was not produced because fetching its data failed.
+#[derive(Debug)]
+pub struct FailedDataUpdate {
+    pub update_type: UpdateType,
+    pub error: DataError,
+}
+
+pub type DataUpdateResult = std::result::Result<DataUpdate, FailedDataUpdate>;
+
+pub(crate) fn data_update(data: DataResult, update_type: UpdateType) -> DataUpdateResult {
+    match data {
+        Ok(data) => Ok(DataUpdate { data, update_type }),
+        Err(error) => Err(FailedDataUpdate { update_type, error }),
+    }
+}
+
+/// Parses `filter` as a single SQL predicate and renders it for `dialect`.
+///
+/// Filters are sent to the source inside a `WHERE` clause, so anything that is not one expression, like
+/// a second statement, or that reads other tables through a subquery is rejected.
+pub fn render_filter(filter: &str, dialect: &dyn Dialect) -> Result<String> {
+    let mut parser = Parser::new(dialect)
+        .try_with_sql(filter)
+        .context(UnableToParseFilterSnafu { filter })?;
+    let expr = parser
+        .parse_expr()
+        .context(UnableToParseFilterSnafu { filter })?;
+    ensure!(
+        parser.peek_token().token == Token::EOF,
+        InvalidFilterSnafu {
+            filter,
+            reason: "expected a single expression",
+        }
+    );
+
+    let subquery = visit_expressions(&expr, |expr| match expr {
+        Expr::Subquery(_) | Expr::Exists { .. } | Expr::InSubquery { .. } => {
+            std::ops::ControlFlow::Break(())
+        }
+        _ => std::ops::ControlFlow::Continue(()),
+    });
+    ensure!(
+        subquery.is_continue(),
+        InvalidFilterSnafu {
+            filter,
+            reason: "subqueries are not supported",
+        }
+    );
+
+    Ok(expr.to_string())
+}
+
 /// A `DataConnector` knows how to retrieve and modify data for a given dataset.
 ///
 /// Implementing `get_all_data` is required, but `stream_data_updates` & `supports_data_streaming` is optional.
@@ -53,10 +130,12 @@ pub type AnyErrorResult = std::result::Result<(), Box<dyn std::error::Error>>;
 ///
 /// ```rust
 /// DataUpdate {
-///    data: get_all_data(dataset),
+///    data: get_all_data(dataset)?,
 ///    update_type: UpdateType::Overwrite,
 /// }
 /// ```
+///
+/// A fetch that fails is reported as a failed refresh and leaves the acceleration as it is.
 #[async_trait]
 pub trait DataConnector: Send + Sync {
     /// Create a new `DataConnector` with the given `Secret`.
@@ -68,20 +147,39 @@ pub trait DataConnector: Send + Sync {
         Self: Sized;
 
     /// Returns true if the given dataset supports streaming by this `DataConnector`.
+    ///
+    /// Streamed updates replace the refreshes of the dataset's `refresh_mode`, so connectors that only
+    /// stream appends check for the `append` refresh mode here.
     fn supports_data_streaming(&self, _dataset: &Dataset) -> bool {
         false
     }
 
-    /// Returns a stream of `DataUpdates` for the given dataset.
-    fn stream_data_updates<'a>(&self, dataset: &Dataset) -> BoxStream<'a, DataUpdate> {
+    /// Returns a stream of `DataUpdates` for the given dataset, with the updates whose data could not be
+    /// fetched as errors.
+    fn stream_data_updates<'a>(&self, dataset: &Dataset) -> BoxStream<'a, DataUpdateResult> {
         panic!("stream_data_updates not implemented for {}", dataset.name)
     }
 
     /// Returns all data for the given dataset.
-    fn get_all_data(
+    fn get_all_data(&self, dataset: &Dataset) -> Pin<Box<dyn Future<Output = DataResult> + Send>>;
+
+    /// Returns true if this `DataConnector` can push a filter down with `get_filtered_data`.
+    fn supports_filtered_data(&self) -> bool {
+        false
+    }
+
+    /// Returns the data for the given dataset that matches `filter`, a SQL predicate.
+    fn get_filtered_data(
         &self,
         dataset: &Dataset,
-    ) -> Pin<Box<dyn Future<Output = Vec<RecordBatch>> + Send>>;
+        _filter: &str,
+    ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        let error = FilteredDataNotSupportedSnafu {
+            dataset: dataset.name.clone(),
+        }
+        .build();
+        Box::pin(async move { Err(error.into()) })
+    }
 
     fn get_data_publisher(&self) -> Option<Box<dyn DataPublisher>> {
         None
@@ -111,17 +209,10 @@ pub trait DataConnector: Send + Sync {
 }
 
 impl dyn DataConnector + '_ {
-    pub fn get_data<'a>(&'a self, dataset: &'a Dataset) -> BoxStream<'_, DataUpdate> {
-        let refresh_mode = dataset
-            .acceleration
-            .as_ref()
-            .map_or(RefreshMode::Full, |acc| {
-                acc.refresh_mode
-                    .as_ref()
-                    .map_or(RefreshMode::Full, Clone::clone)
-            });
-
-        if refresh_mode == RefreshMode::Append && self.supports_data_streaming(dataset) {
+    /// Returns a stream of `DataUpdates` for the given dataset, with the updates whose data could not be
+    /// fetched as errors.
+    pub fn get_data<'a>(&'a self, dataset: &'a Dataset) -> BoxStream<'_, DataUpdateResult> {
+        if self.supports_data_streaming(dataset) {
             return self.stream_data_updates(dataset);
         }
 
@@ -132,10 +223,7 @@ impl dyn DataConnector + '_ {
                 loop {
                     tracing::info!("Refreshing data for {}", dataset.name);
                     let timer = TimeMeasurement::new("load_dataset_duration_ms", vec![("dataset", dataset.name.clone())]);
-                    yield DataUpdate {
-                        data: self.get_all_data(dataset).await,
-                        update_type: UpdateType::Overwrite,
-                    };
+                    yield data_update(self.get_all_data(dataset).await, UpdateType::Overwrite);
                     drop(timer);
                     tokio::time::sleep(refresh_interval).await;
                 }
@@ -152,10 +240,176 @@ impl dyn DataConnector + '_ {
             let data = self.get_all_data(dataset).await;
             drop(timer);
 
-            DataUpdate {
-                data,
-                update_type: UpdateType::Overwrite,
-            }
+            data_update(data, UpdateType::Overwrite)
         }))
     }
+
+    /// Returns a stream of `DataUpdates` that only fetches the rows newer than what is already in the
+    /// accelerated table.
+    ///
+    /// The maximum value of the dataset's watermark column is read from the accelerated table in `ctx`
+    /// before every refresh. Falls back to `get_data` if incremental refreshes are not possible.
+    ///
+    /// With an acceleration `primary_key`, the rows at the watermark are fetched again and upserted, so rows
+    /// that arrive later with the same watermark value are not skipped. Without one only the rows past the
+    /// watermark are appended, so the watermark column must be strictly increasing.
+    pub fn get_incremental_data<'a>(
+        &'a self,
+        dataset: &'a Dataset,
+        ctx: Arc<SessionContext>,
+    ) -> BoxStream<'_, DataUpdateResult> {
+        // Streamed changes, i.e. from a Postgres replication slot, are more current than a watermark.
+        if self.supports_data_streaming(dataset) {
+            return self.get_data(dataset);
+        }
+
+        let Some(watermark_column) = dataset.watermark_column() else {
+            tracing::warn!(
+                "Dataset {} uses incremental refresh but has no watermark_column or time_column, falling back to full refreshes",
+                dataset.name
+            );
+            return self.get_data(dataset);
+        };
+
+        let Some(refresh_interval) = dataset.refresh_interval() else {
+            tracing::warn!(
+                "Dataset {} uses incremental refresh but has no refresh_interval, loading it once",
+                dataset.name
+            );
+            return self.get_data(dataset);
+        };
+
+        if !self.supports_filtered_data() {
+            tracing::warn!(
+                "The data connector for dataset {} does not support incremental refresh, falling back to full refreshes",
+                dataset.name
+            );
+            return self.get_data(dataset);
+        }
+
+        let primary_key = dataset
+            .acceleration
+            .as_ref()
+            .and_then(|acc| acc.primary_key.clone());
+        let (operator, update_type) = match primary_key {
+            Some(keys) => (">=", UpdateType::Upsert { keys }),
+            None => (">", UpdateType::Append),
+        };
+
+        Box::pin(stream! {
+            loop {
+                let timer = TimeMeasurement::new("load_dataset_duration_ms", vec![("dataset", dataset.name.clone())]);
+                match current_watermark(&ctx, &dataset.name, &watermark_column).await {
+                    Ok(Some(watermark)) => {
+                        tracing::info!("Refreshing data for {} from {watermark_column} {operator} {watermark}", dataset.name);
+                        let filter = format!(r#""{watermark_column}" {operator} {watermark}"#);
+                        yield data_update(self.get_filtered_data(dataset, &filter).await, update_type.clone());
+                    }
+                    Ok(None) => {
+                        tracing::info!("Refreshing data for {}", dataset.name);
+                        yield data_update(self.get_all_data(dataset).await, UpdateType::Overwrite);
+                    }
+                    // Overwriting the acceleration because its watermark couldn't be read would reload
+                    // everything, so this refresh is skipped instead.
+                    Err(e) => yield Err(FailedDataUpdate {
+                        update_type: update_type.clone(),
+                        error: e.into(),
+                    }),
+                }
+                drop(timer);
+                tokio::time::sleep(refresh_interval).await;
+            }
+        })
+    }
+}
+
+/// Returns the maximum value of `column` in the accelerated `table` as a SQL literal.
+///
+/// Returns `None` if the table hasn't been loaded yet or is empty, and an error if it can't be read.
+async fn current_watermark(
+    ctx: &SessionContext,
+    table: &str,
+    column: &str,
+) -> datafusion::error::Result<Option<String>> {
+    if !ctx.table_exist(table)? {
+        return Ok(None);
+    }
+
+    let batches = ctx
+        .sql(&format!(r#"SELECT MAX("{column}") FROM "{table}""#))
+        .await?
+        .collect()
+        .await?;
+    let Some(batch) = batches.first().filter(|batch| batch.num_rows() > 0) else {
+        return Ok(None);
+    };
+
+    let value = ScalarValue::try_from_array(batch.column(0), 0)?;
+    match watermark_literal(&value) {
+        Some(literal) => Ok(Some(literal)),
+        None if value.is_null() => Ok(None),
+        None => Err(DataFusionError::Execution(format!(
+            "Unable to use {value} of {table}.{column} as a watermark"
+        ))),
+    }
+}
+
+fn watermark_literal(value: &ScalarValue) -> Option<String> {
+    if value.is_null() {
+        return None;
+    }
+
+    let ScalarValue::Utf8(Some(literal)) = value.cast_to(&DataType::Utf8).ok()? else {
+        return None;
+    };
+
+    if value.data_type().is_numeric() {
+        Some(literal)
+    } else {
+        Some(format!("'{}'", literal.replace('\'', "''")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watermark_literal() {
+        assert_eq!(
+            watermark_literal(&ScalarValue::Int64(Some(42))),
+            Some("42".to_string())
+        );
+        assert_eq!(watermark_literal(&ScalarValue::Int64(None)), None);
+        assert_eq!(
+            watermark_literal(&ScalarValue::Utf8(Some("it's".to_string()))),
+            Some("'it''s'".to_string())
+        );
+        assert_eq!(
+            watermark_literal(&ScalarValue::TimestampSecond(Some(0), None)),
+            Some("'1970-01-01T00:00:00'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_render_filter() {
+        use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+
+        assert_eq!(
+            render_filter("id > 10 AND note = 'it''s'", &PostgreSqlDialect {})
+                .expect("filter should render"),
+            "id > 10 AND note = 'it''s'"
+        );
+        for filter in [
+            "id > 10; DROP TABLE trips",
+            "id > 10) OR (1 = 1",
+            "id IN (SELECT id FROM other)",
+            "EXISTS (SELECT 1)",
+        ] {
+            assert!(
+                render_filter(filter, &PostgreSqlDialect {}).is_err(),
+                "{filter} should be rejected"
+            );
+        }
+    }
 }
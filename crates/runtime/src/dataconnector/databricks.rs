@@ -1,3 +1,4 @@
+use arrow::record_batch::RecordBatch;
 use async_trait::async_trait;
 use datafusion::execution::context::SessionContext;
 use deltalake::aws::storage::s3_constants::AWS_S3_ALLOW_UNSAFE_RENAME;
@@ -14,9 +15,9 @@ use std::{collections::HashMap, future::Future};
 use spicepod::component::dataset::Dataset;
 
 use crate::datapublisher::{AddDataResult, DataPublisher};
-use crate::dataupdate::DataUpdate;
+use crate::dataupdate::{DataUpdate, UpdateType};
 
-use super::DataConnector;
+use super::{DataConnector, DataResult};
 
 #[derive(Clone)]
 pub struct Databricks {
@@ -42,39 +43,18 @@ impl DataConnector for Databricks {
         })
     }
 
-    fn get_all_data(
-        &self,
-        dataset: &Dataset,
-    ) -> Pin<Box<dyn Future<Output = Vec<arrow::record_batch::RecordBatch>> + Send>> {
+    fn get_all_data(&self, dataset: &Dataset) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
         let dataset = dataset.clone();
         let secret = Arc::clone(&self.secret);
         Box::pin(async move {
             let ctx = SessionContext::new();
 
-            let table_provider = match get_table_provider(&secret, &dataset).await {
-                Ok(provider) => provider,
-                Err(e) => {
-                    tracing::error!("Failed to get table provider: {}", e);
-                    return vec![];
-                }
-            };
-
-            let _ = ctx.register_table("temp_table", table_provider);
+            let table_provider = get_table_provider(&secret, &dataset).await?;
+            ctx.register_table("temp_table", table_provider)?;
 
             let sql = "SELECT * FROM temp_table;";
-
-            let df = match ctx.sql(sql).await {
-                Ok(df) => df,
-                Err(e) => {
-                    tracing::error!("Failed to execute query: {}", e);
-                    return vec![];
-                }
-            };
-
-            df.collect().await.unwrap_or_else(|e| {
-                tracing::error!("Failed to collect results: {}", e);
-                vec![]
-            })
+            let data = ctx.sql(sql).await?.collect().await?;
+            Ok(data)
         })
     }
 
@@ -99,10 +79,63 @@ impl DataPublisher for Databricks {
         Box::pin(async move {
             let delta_table = get_delta_table(&self.secret, &dataset).await?;
 
-            let _ = DeltaOps(delta_table)
-                .write(data_update.data)
-                .with_save_mode(SaveMode::Append)
-                .await?;
+            let (keys, is_delete) = match data_update.update_type {
+                UpdateType::Append => {
+                    let _ = DeltaOps(delta_table)
+                        .write(data_update.data)
+                        .with_save_mode(SaveMode::Append)
+                        .await?;
+                    return Ok(());
+                }
+                UpdateType::Overwrite => {
+                    let _ = DeltaOps(delta_table)
+                        .write(data_update.data)
+                        .with_save_mode(SaveMode::Overwrite)
+                        .await?;
+                    return Ok(());
+                }
+                UpdateType::Upsert { keys } => (keys, false),
+                UpdateType::Delete { keys } => (keys, true),
+            };
+
+            let Some(schema) = data_update.data.first().map(RecordBatch::schema) else {
+                return Ok(());
+            };
+            let source = SessionContext::new().read_batches(data_update.data)?;
+            let predicate = keys
+                .iter()
+                .map(|key| format!(r#"target."{key}" = source."{key}""#))
+                .collect::<Vec<_>>()
+                .join(" AND ");
+
+            let merge = DeltaOps(delta_table)
+                .merge(source, predicate)
+                .with_source_alias("source")
+                .with_target_alias("target");
+            let merge = if is_delete {
+                merge.when_matched_delete(|delete| delete)?
+            } else {
+                merge
+                    .when_matched_update(|mut update| {
+                        for field in schema.fields() {
+                            update = update.update(
+                                field.name().as_str(),
+                                format!(r#"source."{}""#, field.name()),
+                            );
+                        }
+                        update
+                    })?
+                    .when_not_matched_insert(|mut insert| {
+                        for field in schema.fields() {
+                            insert = insert.set(
+                                field.name().as_str(),
+                                format!(r#"source."{}""#, field.name()),
+                            );
+                        }
+                        insert
+                    })?
+            };
+            let _ = merge.await?;
 
             Ok(())
         })
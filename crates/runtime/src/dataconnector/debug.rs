@@ -4,7 +4,7 @@ use std::{sync::Arc, time::Duration};
 
 use secrets::Secret;
 
-use super::{DataConnector, DataUpdate, UpdateType};
+use super::{DataConnector, DataResult, DataUpdate, DataUpdateResult, UpdateType};
 use arrow::{
     array::{Int32Array, StringArray},
     datatypes::{DataType, Field, Schema},
@@ -12,6 +12,7 @@ use arrow::{
 };
 use async_stream::stream;
 use futures_core::stream::BoxStream;
+use spicepod::component::dataset::acceleration::RefreshMode;
 use spicepod::component::dataset::Dataset;
 
 #[allow(clippy::module_name_repetitions)]
@@ -25,34 +26,28 @@ impl DataConnector for DebugSource {
         Box::pin(async move { Ok(Self {}) })
     }
 
-    fn supports_data_streaming(&self, _dataset: &Dataset) -> bool {
-        true
+    fn supports_data_streaming(&self, dataset: &Dataset) -> bool {
+        dataset.refresh_mode() == RefreshMode::Append
     }
 
-    fn get_all_data(
-        &self,
-        _dataset: &Dataset,
-    ) -> Pin<Box<dyn Future<Output = Vec<RecordBatch>> + Send>> {
+    fn get_all_data(&self, _dataset: &Dataset) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
         Box::pin(async move {
             let schema = Arc::new(Schema::new(vec![
                 Field::new("a", DataType::Utf8, false),
                 Field::new("b", DataType::Int32, false),
             ]));
-            if let Ok(batch) = RecordBatch::try_new(
+            let batch = RecordBatch::try_new(
                 schema,
                 vec![
                     Arc::new(StringArray::from(vec!["a", "b", "c", "d"])),
                     Arc::new(Int32Array::from(vec![1, 10, 10, 100])),
                 ],
-            ) {
-                vec![batch]
-            } else {
-                vec![]
-            }
+            )?;
+            Ok(vec![batch])
         })
     }
 
-    fn stream_data_updates<'a>(&self, dataset: &Dataset) -> BoxStream<'a, DataUpdate> {
+    fn stream_data_updates<'a>(&self, dataset: &Dataset) -> BoxStream<'a, DataUpdateResult> {
         let sleep_duration = dataset.refresh_interval();
         let sleep_duration = match sleep_duration {
             Some(duration) => duration,
@@ -74,10 +69,10 @@ impl DataConnector for DebugSource {
                       Arc::new(Int32Array::from(vec![1, 10, 10, 100])),
                   ],
               ) {
-                yield DataUpdate {
+                yield Ok(DataUpdate {
                   update_type: UpdateType::Append,
                   data: vec![batch],
-                };
+                });
               };
           }
         })
@@ -9,7 +9,7 @@ use spicepod::component::dataset::Dataset;
 
 use secrets::Secret;
 
-use super::{flight::Flight, DataConnector};
+use super::{flight::Flight, DataConnector, DataResult};
 
 pub struct Dremio {
     flight: Flight,
@@ -48,13 +48,24 @@ impl DataConnector for Dremio {
         })
     }
 
-    fn get_all_data(
+    fn get_all_data(&self, dataset: &Dataset) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        let dremio_path = dataset.path();
+
+        self.flight.get_all_data(&dremio_path)
+    }
+
+    fn supports_filtered_data(&self) -> bool {
+        true
+    }
+
+    fn get_filtered_data(
         &self,
         dataset: &Dataset,
-    ) -> Pin<Box<dyn Future<Output = Vec<arrow::record_batch::RecordBatch>> + Send>> {
+        filter: &str,
+    ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
         let dremio_path = dataset.path();
 
-        self.flight.get_all_data(&dremio_path)
+        self.flight.get_filtered_data(&dremio_path, filter)
     }
 
     fn has_table_provider(&self) -> bool {
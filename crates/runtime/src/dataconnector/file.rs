@@ -0,0 +1,497 @@
+//! Reads Parquet, CSV, JSON and Arrow IPC files from the local filesystem, i.e. `from: file:/data/trips/`.
+//!
+//! The path can be a file, a directory or a glob such as `file:/data/trips/*.csv`. Supported params:
+//!
+//! - `file_format`: `parquet`, `csv`, `json` (newline-delimited) or `arrow`, by default from the
+//!   extension of the path, otherwise `parquet`.
+//! - `file_extension`: the extension of the files to read in a directory, by default that of the format.
+//! - `csv_has_header`: whether the first line of CSV files is a header, `true` by default.
+//! - `csv_delimiter`: the single character separating CSV values, `,` by default.
+//! - `schema_infer_max_records`: how many CSV or JSON rows to read to infer the schema, 1000 by default.
+//! - `hive_partitioning`: whether to read `key=value` directories as partition columns, `false` by default.
+
+use async_trait::async_trait;
+use datafusion::datasource::file_format::{
+    arrow::ArrowFormat, csv::CsvFormat, json::JsonFormat, parquet::ParquetFormat, FileFormat,
+};
+use datafusion::datasource::listing::{
+    ListingOptions, ListingTable, ListingTableConfig, ListingTableUrl,
+};
+use datafusion::datasource::TableProvider;
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::SessionContext;
+use secrets::Secret;
+use snafu::prelude::*;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::{collections::HashMap, future::Future};
+
+use arrow::datatypes::DataType;
+use arrow::record_batch::RecordBatch;
+use spicepod::component::dataset::Dataset;
+
+use super::{DataConnector, DataResult};
+
+const DEFAULT_SCHEMA_INFER_MAX_RECORDS: usize = 1000;
+
+const GLOB_CHARS: [char; 3] = ['*', '?', '['];
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unknown file format {file_format}, expected parquet, csv, json or arrow"))]
+    UnknownFileFormat { file_format: String },
+
+    #[snafu(display("Invalid value {value} for parameter {param}"))]
+    InvalidParameter { param: String, value: String },
+
+    #[snafu(display("Unable to read files from {path}: {source}"))]
+    UnableToReadFiles {
+        path: String,
+        source: DataFusionError,
+    },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Parquet,
+    Csv,
+    Json,
+    Arrow,
+}
+
+impl Format {
+    fn parse(file_format: &str) -> Option<Self> {
+        match file_format.to_lowercase().as_str() {
+            "parquet" => Some(Format::Parquet),
+            "csv" => Some(Format::Csv),
+            "json" | "ndjson" | "jsonl" => Some(Format::Json),
+            "arrow" | "ipc" | "feather" => Some(Format::Arrow),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Parquet => ".parquet",
+            Format::Csv => ".csv",
+            Format::Json => ".json",
+            Format::Arrow => ".arrow",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct File {
+    params: HashMap<String, String>,
+}
+
+impl File {
+    /// The path of the files of the dataset, accepting both `file:/data` and `file:///data`.
+    fn path(dataset: &Dataset) -> String {
+        let path = dataset.path();
+        match path.strip_prefix("//") {
+            Some(path) => path.to_string(),
+            None => path,
+        }
+    }
+
+    fn param(&self, param: &str) -> Option<&str> {
+        self.params.get(param).map(String::as_str)
+    }
+
+    fn bool_param(&self, param: &str, default: bool) -> Result<bool> {
+        match self.param(param) {
+            None => Ok(default),
+            Some(value) => value
+                .parse()
+                .ok()
+                .context(InvalidParameterSnafu { param, value }),
+        }
+    }
+
+    fn format(&self, path: &str) -> Result<Format> {
+        if let Some(file_format) = self.param("file_format") {
+            return Format::parse(file_format).context(UnknownFileFormatSnafu { file_format });
+        }
+
+        Ok(Path::new(path)
+            .extension()
+            .and_then(|extension| Format::parse(&extension.to_string_lossy()))
+            .unwrap_or(Format::Parquet))
+    }
+
+    fn file_format(&self, format: Format) -> Result<Arc<dyn FileFormat>> {
+        let schema_infer_max_records = match self.param("schema_infer_max_records") {
+            None => DEFAULT_SCHEMA_INFER_MAX_RECORDS,
+            Some(value) => value.parse().ok().context(InvalidParameterSnafu {
+                param: "schema_infer_max_records",
+                value,
+            })?,
+        };
+
+        Ok(match format {
+            Format::Parquet => Arc::new(ParquetFormat::default()),
+            Format::Csv => {
+                let delimiter = match self.param("csv_delimiter") {
+                    None => b',',
+                    Some(value) => match value.as_bytes() {
+                        [delimiter] => *delimiter,
+                        _ => {
+                            return InvalidParameterSnafu {
+                                param: "csv_delimiter",
+                                value,
+                            }
+                            .fail()
+                        }
+                    },
+                };
+                Arc::new(
+                    CsvFormat::default()
+                        .with_has_header(self.bool_param("csv_has_header", true)?)
+                        .with_delimiter(delimiter)
+                        .with_schema_infer_max_rec(Some(schema_infer_max_records)),
+                )
+            }
+            Format::Json => Arc::new(
+                JsonFormat::default().with_schema_infer_max_rec(Some(schema_infer_max_records)),
+            ),
+            Format::Arrow => Arc::new(ArrowFormat),
+        })
+    }
+
+    async fn listing_table(&self, dataset: &Dataset) -> Result<Arc<ListingTable>> {
+        let path = File::path(dataset);
+        let format = self.format(&path)?;
+
+        // Single files and globs are read whatever their extension, only directories are filtered by it.
+        let file_extension = match self.param("file_extension") {
+            Some(file_extension) => file_extension.to_string(),
+            None if Path::new(&path).is_file() || path.contains(GLOB_CHARS) => String::new(),
+            None => format.extension().to_string(),
+        };
+        let partition_columns = if self.bool_param("hive_partitioning", false)? {
+            hive_partition_columns(&path)
+        } else {
+            vec![]
+        };
+
+        let table_path =
+            ListingTableUrl::parse(&path).context(UnableToReadFilesSnafu { path: &path })?;
+        let options = ListingOptions::new(self.file_format(format)?)
+            .with_file_extension(file_extension)
+            .with_table_partition_cols(
+                partition_columns
+                    .into_iter()
+                    .map(|column| (column, DataType::Utf8))
+                    .collect(),
+            );
+
+        let ctx = SessionContext::new();
+        let schema = options
+            .infer_schema(&ctx.state(), &table_path)
+            .await
+            .context(UnableToReadFilesSnafu { path: &path })?;
+        let config = ListingTableConfig::new(table_path)
+            .with_listing_options(options)
+            .with_schema(schema);
+        let table =
+            ListingTable::try_new(config).context(UnableToReadFilesSnafu { path: &path })?;
+
+        Ok(Arc::new(table))
+    }
+
+    fn read_data(
+        &self,
+        dataset: &Dataset,
+        filter: Option<String>,
+    ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        let connector = self.clone();
+        let dataset = dataset.clone();
+        Box::pin(async move {
+            let table = connector.listing_table(&dataset).await?;
+
+            let ctx = SessionContext::new();
+            ctx.register_table("data", table)?;
+
+            let sql = match filter {
+                Some(filter) => format!("SELECT * FROM data WHERE {filter}"),
+                None => "SELECT * FROM data".to_string(),
+            };
+            let data = ctx.sql(&sql).await?.collect().await?;
+            Ok(data)
+        })
+    }
+}
+
+/// The names of the `key=value` directories under the path, descending into the first at each level.
+fn hive_partition_columns(path: &str) -> Vec<String> {
+    // The partitions of a glob are under the part of its path before the first wildcard.
+    let root = path.find(GLOB_CHARS).map_or(path, |index| &path[..index]);
+    let mut dir = Path::new(root).to_path_buf();
+    if !dir.is_dir() {
+        dir = match dir.parent() {
+            Some(parent) => parent.to_path_buf(),
+            None => return vec![],
+        };
+    }
+
+    let mut columns = vec![];
+    loop {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            break;
+        };
+        let mut partitions: Vec<_> = entries
+            .filter_map(std::result::Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let (column, _) = name.split_once('=')?;
+                Some((column.to_string(), entry.path()))
+            })
+            .collect();
+        partitions.sort();
+        let Some((column, partition_dir)) = partitions.into_iter().next() else {
+            break;
+        };
+
+        columns.push(column);
+        dir = partition_dir;
+    }
+
+    columns
+}
+
+#[async_trait]
+impl DataConnector for File {
+    fn new(
+        _secret: Option<Secret>,
+        params: Arc<Option<HashMap<String, String>>>,
+    ) -> Pin<Box<dyn Future<Output = super::Result<Self>> + Send>>
+    where
+        Self: Sized,
+    {
+        Box::pin(async move {
+            Ok(Self {
+                params: params.as_ref().clone().unwrap_or_default(),
+            })
+        })
+    }
+
+    fn get_all_data(&self, dataset: &Dataset) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        self.read_data(dataset, None)
+    }
+
+    fn supports_filtered_data(&self) -> bool {
+        true
+    }
+
+    fn get_filtered_data(
+        &self,
+        dataset: &Dataset,
+        filter: &str,
+    ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        self.read_data(dataset, Some(filter.to_string()))
+    }
+
+    fn has_table_provider(&self) -> bool {
+        true
+    }
+
+    async fn get_table_provider(
+        &self,
+        dataset: &Dataset,
+    ) -> std::result::Result<Arc<dyn TableProvider>, super::Error> {
+        let table = self
+            .listing_table(dataset)
+            .await
+            .map_err(|e| super::Error::UnableToGetTableProvider { source: e.into() })?;
+
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use arrow::array::Int64Array;
+
+    use super::*;
+    use crate::testutil::string_values;
+
+    /// A fresh directory for the files of a test, removed when dropped.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "spice_file_connector_{name}_{}",
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).expect("Unable to create test directory");
+            TestDir(dir)
+        }
+
+        fn write(&self, file: &str, contents: &str) {
+            let path = self.0.join(file);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).expect("Unable to create test directory");
+            }
+            std::fs::write(path, contents).expect("Unable to write test file");
+        }
+
+        fn dataset(&self, path: &str) -> Dataset {
+            Dataset::new(
+                format!("file:{}", self.0.join(path).display()),
+                "test".to_string(),
+            )
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    async fn connector(params: &[(&str, &str)]) -> File {
+        let params = params
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect();
+        File::new(None, Arc::new(Some(params)))
+            .await
+            .expect("Unable to create connector")
+    }
+
+    async fn query(table: Arc<dyn TableProvider>, sql: &str) -> Vec<RecordBatch> {
+        let ctx = SessionContext::new();
+        ctx.register_table("data", table)
+            .expect("Unable to register table");
+        ctx.sql(sql)
+            .await
+            .expect("Unable to plan query")
+            .collect()
+            .await
+            .expect("Unable to execute query")
+    }
+
+    #[tokio::test]
+    async fn test_csv_with_hive_partitions() {
+        let dir = TestDir::new("csv");
+        dir.write("trips/year=2023/month=12/part.csv", "id|city\n1|paris\n");
+        dir.write(
+            "trips/year=2024/month=01/part.csv",
+            "id|city\n2|berlin\n3|tokyo\n",
+        );
+        dir.write("trips/_SUCCESS", "");
+
+        let connector = connector(&[("csv_delimiter", "|"), ("hive_partitioning", "true")]).await;
+        let table = connector
+            .get_table_provider(&dir.dataset("trips/"))
+            .await
+            .expect("Unable to get table provider");
+
+        let batches = query(
+            table,
+            "SELECT city, year, month FROM data WHERE year = '2024' ORDER BY id",
+        )
+        .await;
+        assert_eq!(string_values(&batches, 0), vec!["berlin", "tokyo"]);
+        assert_eq!(string_values(&batches, 1), vec!["2024", "2024"]);
+        assert_eq!(string_values(&batches, 2), vec!["01", "01"]);
+    }
+
+    #[tokio::test]
+    async fn test_json_glob_get_all_data() {
+        let dir = TestDir::new("json");
+        dir.write("events/a.ndjson", "{\"id\": 1, \"kind\": \"click\"}\n");
+        dir.write("events/b.ndjson", "{\"id\": 2, \"kind\": \"view\"}\n");
+        dir.write("events/c.csv", "id,kind\n3,skipped\n");
+
+        let connector = connector(&[("file_format", "json")]).await;
+        let dataset = dir.dataset("events/*.ndjson");
+        let batches = connector
+            .get_all_data(&dataset)
+            .await
+            .expect("Unable to read data");
+        let rows: usize = batches.iter().map(RecordBatch::num_rows).sum();
+        assert_eq!(rows, 2);
+
+        let batches = connector
+            .get_filtered_data(&dataset, "id = 2")
+            .await
+            .expect("Unable to read data");
+        let ids: Vec<i64> = batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column_by_name("id")
+                    .and_then(|column| column.as_any().downcast_ref::<Int64Array>())
+                    .expect("Expected an integer id column")
+                    .values()
+                    .to_vec()
+            })
+            .collect();
+        assert_eq!(ids, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_parquet_and_arrow_files() {
+        let dir = TestDir::new("parquet");
+        dir.write("cities.csv", "name\nlisbon\noslo\n");
+        let csv = connector(&[]).await;
+        let batches = csv
+            .get_all_data(&dir.dataset("cities.csv"))
+            .await
+            .expect("Unable to read data");
+        assert_eq!(string_values(&batches, 0), vec!["lisbon", "oslo"]);
+
+        let ctx = SessionContext::new();
+        let df = ctx
+            .read_batches(batches.clone())
+            .expect("Unable to read batches");
+        df.write_parquet(
+            &dir.0.join("cities.parquet").to_string_lossy(),
+            datafusion::dataframe::DataFrameWriteOptions::new().with_single_file_output(true),
+            None,
+        )
+        .await
+        .expect("Unable to write parquet");
+
+        let parquet = connector(&[]).await;
+        let batches = parquet
+            .get_all_data(&dir.dataset("cities.parquet"))
+            .await
+            .expect("Unable to read data");
+        assert_eq!(string_values(&batches, 0), vec!["lisbon", "oslo"]);
+
+        let file =
+            std::fs::File::create(dir.0.join("cities.arrow")).expect("Unable to create file");
+        let mut writer = arrow::ipc::writer::FileWriter::try_new(file, &batches[0].schema())
+            .expect("Unable to create writer");
+        for batch in &batches {
+            writer.write(batch).expect("Unable to write batch");
+        }
+        writer.finish().expect("Unable to finish file");
+
+        let arrow = connector(&[]).await;
+        let batches = arrow
+            .get_all_data(&dir.dataset("cities.arrow"))
+            .await
+            .expect("Unable to read data");
+        assert_eq!(string_values(&batches, 0), vec!["lisbon", "oslo"]);
+
+        assert!(matches!(
+            connector(&[("file_format", "xlsx")])
+                .await
+                .listing_table(&dir.dataset("cities.csv"))
+                .await,
+            Err(Error::UnknownFileFormat { .. })
+        ));
+    }
+}
@@ -1,9 +1,10 @@
 use std::future::Future;
 use std::pin::Pin;
 
-use arrow::record_batch::RecordBatch;
 use flight_client::FlightClient;
-use futures::StreamExt;
+use futures::TryStreamExt;
+
+use super::DataResult;
 
 #[derive(Debug, Clone)]
 pub struct Flight {
@@ -24,36 +25,24 @@ impl Flight {
     pub(crate) fn get_all_data(
         &self,
         dataset_path: &str,
-    ) -> Pin<Box<dyn Future<Output = Vec<RecordBatch>> + Send>> {
-        let mut client = self.client.clone();
-        let dataset_path = dataset_path.to_owned();
-        Box::pin(async move {
-            let flight_record_batch_stream_result = client
-                .query(format!("SELECT * FROM {dataset_path}").as_str())
-                .await;
-
-            let mut flight_record_batch_stream = match flight_record_batch_stream_result {
-                Ok(stream) => stream,
-                Err(error) => {
-                    tracing::error!("Failed to query with flight client: {error}",);
-                    return vec![];
-                }
-            };
+    ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        self.query(format!("SELECT * FROM {dataset_path}"))
+    }
 
-            let mut result_data = vec![];
-            while let Some(batch) = flight_record_batch_stream.next().await {
-                match batch {
-                    Ok(batch) => {
-                        result_data.push(batch);
-                    }
-                    Err(error) => {
-                        tracing::error!("Failed to read batch from flight client: {error}",);
-                        return result_data;
-                    }
-                };
-            }
+    pub(crate) fn get_filtered_data(
+        &self,
+        dataset_path: &str,
+        filter: &str,
+    ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        self.query(format!("SELECT * FROM {dataset_path} WHERE {filter}"))
+    }
 
-            result_data
+    fn query(&self, sql: String) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        let mut client = self.client.clone();
+        Box::pin(async move {
+            let flight_record_batch_stream = client.query(sql.as_str()).await?;
+            let result_data: Vec<_> = flight_record_batch_stream.try_collect().await?;
+            Ok(result_data)
         })
     }
 }
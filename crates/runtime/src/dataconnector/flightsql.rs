@@ -5,6 +5,7 @@ use std::{future::Future, sync::Arc};
 use arrow_flight::sql::client::FlightSqlServiceClient;
 use arrow_flight::Ticket;
 use async_trait::async_trait;
+use datafusion::sql::sqlparser::dialect::GenericDialect;
 use spicepod::component::dataset::Dataset;
 use tonic::transport::Channel;
 
@@ -12,7 +13,7 @@ use flight_client::tls::new_tls_flight_channel;
 use flightsql_datafusion::FlightSQLTable;
 use secrets::Secret;
 
-use super::DataConnector;
+use super::{render_filter, DataConnector, DataResult};
 use arrow::error::ArrowError;
 use futures::stream::TryStreamExt;
 
@@ -22,10 +23,7 @@ pub struct FlightSQL {
 }
 
 impl FlightSQL {
-    async fn query(
-        client: FlightSqlServiceClient<Channel>,
-        query: String,
-    ) -> Result<Vec<arrow::record_batch::RecordBatch>, Box<dyn std::error::Error>> {
+    async fn query(client: FlightSqlServiceClient<Channel>, query: String) -> DataResult {
         let flight_info = client.clone().execute(query, None).await?;
 
         let mut batches = vec![];
@@ -37,13 +35,7 @@ impl FlightSQL {
                     let channel = new_tls_flight_channel(&ep.location[0].uri).await?;
                     FlightSqlServiceClient::new(channel)
                 };
-                match batch_from_ticket(&mut do_get_client, tkt.to_owned()).await {
-                    Ok(flight_data) => batches.extend(flight_data),
-                    Err(err) => {
-                        tracing::error!("Failed to read batch from flight client: {:?}", err);
-                        break;
-                    }
-                }
+                batches.extend(batch_from_ticket(&mut do_get_client, tkt.to_owned()).await?);
             };
         }
         Ok(batches)
@@ -84,22 +76,30 @@ impl DataConnector for FlightSQL {
         })
     }
 
-    fn get_all_data(
+    fn get_all_data(&self, dataset: &Dataset) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        let dataset_path = dataset.path().clone();
+        let client = self.client.clone();
+
+        Box::pin(Self::query(client, format!("SELECT * FROM {dataset_path}")))
+    }
+
+    fn supports_filtered_data(&self) -> bool {
+        true
+    }
+
+    fn get_filtered_data(
         &self,
         dataset: &Dataset,
-    ) -> Pin<Box<dyn Future<Output = Vec<arrow::record_batch::RecordBatch>> + Send>> {
-        let dataset_path = dataset.path().clone();
+        filter: &str,
+    ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        let filter = match render_filter(filter, &GenericDialect {}) {
+            Ok(filter) => filter,
+            Err(e) => return Box::pin(async move { Err(e.into()) }),
+        };
+        let sql = format!("SELECT * FROM {} WHERE {filter}", dataset.path());
         let client = self.client.clone();
 
-        Box::pin(async move {
-            match Self::query(client.clone(), format!("SELECT * FROM {dataset_path}")).await {
-                Ok(batches) => batches,
-                Err(e) => {
-                    tracing::error!("Failed to get data from flight client: {:?}", e);
-                    Vec::new()
-                }
-            }
-        })
+        Box::pin(Self::query(client, sql))
     }
 
     fn has_table_provider(&self) -> bool {
@@ -0,0 +1,408 @@
+//! Materialized views, which write the results of a view's SQL into its acceleration.
+//!
+//! A materialized view is recomputed on its `refresh_interval`. With `refresh_mode: append`, it is instead
+//! recomputed whenever a dataset it reads is updated, and only the rows derived from the rows appended to
+//! that dataset are computed if the view just filters, projects, inner joins and unions its tables.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use arrow::record_batch::RecordBatch;
+use async_stream::stream;
+use datafusion::common::tree_node::{Transformed, TreeNode, VisitRecursion};
+use datafusion::datasource::{provider_as_source, MemTable};
+use datafusion::error::{DataFusionError, Result as DataFusionResult};
+use datafusion::execution::context::SessionContext;
+use datafusion::logical_expr::{Expr, JoinType, LogicalPlan};
+use datafusion::sql::{OwnedTableReference, TableReference};
+use futures_core::stream::BoxStream;
+use secrets::Secret;
+use spicepod::component::dataset::acceleration::RefreshMode;
+use spicepod::component::dataset::Dataset;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use super::{data_update, DataConnector, DataResult, DataUpdateResult, Error, Result};
+use crate::datapublisher::{AddDataResult, DataPublisher, DeleteDataResult, ExecuteDmlResult};
+use crate::dataupdate::{DataUpdate, UpdateType};
+use crate::dependencygraph;
+
+/// An update written to the acceleration of a dataset.
+#[derive(Debug, Clone)]
+pub struct DatasetUpdate {
+    pub dataset: String,
+    pub data_update: DataUpdate,
+}
+
+/// Computes the data of a materialized view by running its SQL against the datasets of the runtime.
+pub struct MaterializedView {
+    ctx: Arc<SessionContext>,
+    updates: broadcast::Sender<DatasetUpdate>,
+}
+
+impl MaterializedView {
+    /// Creates a materialized view that reads the datasets in `ctx`, and is notified of their updates
+    /// through `updates`.
+    #[must_use]
+    pub fn new_with_context(
+        ctx: Arc<SessionContext>,
+        updates: broadcast::Sender<DatasetUpdate>,
+    ) -> Self {
+        MaterializedView { ctx, updates }
+    }
+}
+
+impl DataConnector for MaterializedView {
+    fn new(
+        _secret: Option<Secret>,
+        _params: Arc<Option<HashMap<String, String>>>,
+    ) -> Pin<Box<dyn Future<Output = Result<Self>> + Send>> {
+        Box::pin(async move {
+            Err(Error::UnableToCreateDataConnector {
+                source: "Materialized views are created by the runtime for accelerated views"
+                    .into(),
+            })
+        })
+    }
+
+    fn supports_data_streaming(&self, dataset: &Dataset) -> bool {
+        dataset.refresh_mode() == RefreshMode::Append
+    }
+
+    fn stream_data_updates<'a>(&self, dataset: &Dataset) -> BoxStream<'a, DataUpdateResult> {
+        let ctx = Arc::clone(&self.ctx);
+        // Subscribed before the first refresh, so no update made after it is missed.
+        let mut updates = self.updates.subscribe();
+        let dataset = dataset.clone();
+        let upstream = dependencygraph::view_relations(&dataset);
+
+        Box::pin(stream! {
+            yield data_update(query(&ctx, &dataset, None).await, UpdateType::Overwrite);
+
+            loop {
+                let update = match updates.recv().await {
+                    Ok(update) => update,
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(
+                            "Materialized view {} missed {skipped} updates of the datasets it reads, recomputing it",
+                            dataset.name
+                        );
+                        yield data_update(query(&ctx, &dataset, None).await, UpdateType::Overwrite);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if !upstream.contains(&update.dataset) {
+                    continue;
+                }
+
+                if update.data_update.update_type == UpdateType::Append {
+                    if let Some(data) = query_appended(&ctx, &dataset, &update).await {
+                        tracing::debug!(
+                            "Appending to materialized view {} the rows appended to {}",
+                            dataset.name,
+                            update.dataset
+                        );
+                        yield Ok(DataUpdate {
+                            data,
+                            update_type: UpdateType::Append,
+                        });
+                        continue;
+                    }
+                }
+
+                tracing::debug!(
+                    "Recomputing materialized view {} after {} was updated",
+                    dataset.name,
+                    update.dataset
+                );
+                yield data_update(query(&ctx, &dataset, None).await, UpdateType::Overwrite);
+            }
+        })
+    }
+
+    fn get_all_data(&self, dataset: &Dataset) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        let ctx = Arc::clone(&self.ctx);
+        let dataset = dataset.clone();
+        Box::pin(async move { query(&ctx, &dataset, None).await })
+    }
+
+    fn supports_filtered_data(&self) -> bool {
+        true
+    }
+
+    fn get_filtered_data(
+        &self,
+        dataset: &Dataset,
+        filter: &str,
+    ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        let ctx = Arc::clone(&self.ctx);
+        let dataset = dataset.clone();
+        let filter = filter.to_string();
+        Box::pin(async move { query(&ctx, &dataset, Some(&filter)).await })
+    }
+}
+
+/// Runs the SQL of the view `dataset`, keeping only the rows that match `filter` if there is one.
+async fn query(ctx: &SessionContext, dataset: &Dataset, filter: Option<&str>) -> DataResult {
+    let sql = view_sql(dataset)?;
+    let sql = match filter {
+        Some(filter) => format!(
+            "SELECT * FROM ({}) AS materialized_view WHERE {filter}",
+            sql.trim().trim_end_matches(';')
+        ),
+        None => sql,
+    };
+    let plan = ctx.state().create_logical_plan(&sql).await?;
+    let data = ctx.execute_logical_plan(plan).await?.collect().await?;
+    Ok(data)
+}
+
+/// Computes the rows the view `dataset` gains from the rows appended by `update`, or `None` if they
+/// can't be computed from the appended rows alone.
+async fn query_appended(
+    ctx: &SessionContext,
+    dataset: &Dataset,
+    update: &DatasetUpdate,
+) -> Option<Vec<RecordBatch>> {
+    let sql = view_sql(dataset).ok()?;
+    let plan = ctx.state().create_logical_plan(&sql).await.ok()?;
+    let plan = appended_plan(plan, &update.dataset, &update.data_update.data)?;
+
+    let result = async { ctx.execute_logical_plan(plan).await?.collect().await }.await;
+    match result {
+        Ok(data) => Some(data),
+        Err(e) => {
+            tracing::warn!(
+                "Failed to compute the rows appended to materialized view {}: {e}",
+                dataset.name
+            );
+            None
+        }
+    }
+}
+
+fn view_sql(dataset: &Dataset) -> DataFusionResult<String> {
+    dataset
+        .view_sql()
+        .map_err(|e| DataFusionError::External(Box::new(e)))?
+        .ok_or_else(|| DataFusionError::Plan(format!("Dataset {} is not a view", dataset.name)))
+}
+
+/// Rewrites `plan` to read only `appended` from `table`, if that gives the rows the view gains from them.
+fn appended_plan(plan: LogicalPlan, table: &str, appended: &[RecordBatch]) -> Option<LogicalPlan> {
+    let table = TableReference::from(table).to_owned_reference();
+    // Reading the table twice, i.e. in a self join, would miss the rows joining the appended rows to each other.
+    if !is_incremental(&plan) || table_scans(&plan, &table) != 1 {
+        return None;
+    }
+
+    plan.transform_up(&|plan| match plan {
+        LogicalPlan::TableScan(mut scan) if scan.table_name == table => {
+            let appended = MemTable::try_new(scan.source.schema(), vec![appended.to_vec()])?;
+            scan.source = provider_as_source(Arc::new(appended));
+            Ok(Transformed::Yes(LogicalPlan::TableScan(scan)))
+        }
+        plan => Ok(Transformed::No(plan)),
+    })
+    .ok()
+}
+
+/// Whether the rows a view gains from rows appended to one of its tables only depend on those rows,
+/// i.e. the view only filters, projects, inner joins and unions its tables.
+fn is_incremental(plan: &LogicalPlan) -> bool {
+    let mut incremental = true;
+    let _ = plan.apply(&mut |plan| {
+        incremental = match plan {
+            LogicalPlan::Projection(_)
+            | LogicalPlan::Filter(_)
+            | LogicalPlan::SubqueryAlias(_)
+            | LogicalPlan::TableScan(_)
+            | LogicalPlan::Union(_)
+            | LogicalPlan::CrossJoin(_)
+            | LogicalPlan::EmptyRelation(_)
+            | LogicalPlan::Values(_) => true,
+            LogicalPlan::Join(join) => join.join_type == JoinType::Inner,
+            _ => false,
+        } && !plan.expressions().iter().any(has_subquery);
+
+        Ok(if incremental {
+            VisitRecursion::Continue
+        } else {
+            VisitRecursion::Stop
+        })
+    });
+
+    incremental
+}
+
+fn has_subquery(expr: &Expr) -> bool {
+    let mut found = false;
+    let _ = expr.apply(&mut |expr| {
+        found = matches!(
+            expr,
+            Expr::Exists(_) | Expr::InSubquery(_) | Expr::ScalarSubquery(_)
+        );
+
+        Ok(if found {
+            VisitRecursion::Stop
+        } else {
+            VisitRecursion::Continue
+        })
+    });
+
+    found
+}
+
+fn table_scans(plan: &LogicalPlan, table: &OwnedTableReference) -> usize {
+    let mut scans = 0;
+    let _ = plan.apply(&mut |plan| {
+        if matches!(plan, LogicalPlan::TableScan(scan) if scan.table_name == *table) {
+            scans += 1;
+        }
+        Ok(VisitRecursion::Continue)
+    });
+
+    scans
+}
+
+/// Announces the updates written to an acceleration to the materialized views that read it.
+pub struct UpdateBroadcastingPublisher {
+    inner: Box<dyn DataPublisher>,
+    updates: broadcast::Sender<DatasetUpdate>,
+}
+
+impl UpdateBroadcastingPublisher {
+    #[must_use]
+    pub fn new(inner: Box<dyn DataPublisher>, updates: broadcast::Sender<DatasetUpdate>) -> Self {
+        UpdateBroadcastingPublisher { inner, updates }
+    }
+}
+
+impl DataPublisher for UpdateBroadcastingPublisher {
+    fn add_data(&self, dataset: Arc<Dataset>, data_update: DataUpdate) -> AddDataResult {
+        Box::pin(async move {
+            self.inner
+                .add_data(Arc::clone(&dataset), data_update.clone())
+                .await?;
+            // Sending only fails when no materialized view is listening.
+            let _ = self.updates.send(DatasetUpdate {
+                dataset: dataset.name.clone(),
+                data_update,
+            });
+            Ok(())
+        })
+    }
+
+    fn delete_expired_data(&self, dataset: Arc<Dataset>, cutoff: SystemTime) -> DeleteDataResult {
+        self.inner.delete_expired_data(dataset, cutoff)
+    }
+
+    fn execute_dml(&self, dataset: Arc<Dataset>, sql: String) -> ExecuteDmlResult {
+        self.inner.execute_dml(dataset, sql)
+    }
+
+    fn supports_dml(&self) -> bool {
+        self.inner.supports_dml()
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Int64Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema};
+
+    use super::*;
+
+    fn batch(ids: Vec<i64>, names: Vec<&str>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(ids)),
+                Arc::new(StringArray::from(names)),
+            ],
+        )
+        .expect("valid batch")
+    }
+
+    fn context() -> SessionContext {
+        let ctx = SessionContext::new();
+        for (table, data) in [
+            ("trips", batch(vec![1, 2], vec!["first", "second"])),
+            (
+                "zones",
+                batch(vec![1, 2, 3], vec!["north", "south", "east"]),
+            ),
+        ] {
+            let table_provider =
+                MemTable::try_new(data.schema(), vec![vec![data]]).expect("valid table");
+            ctx.register_table(table, Arc::new(table_provider))
+                .expect("table registered");
+        }
+        ctx
+    }
+
+    async fn plan(ctx: &SessionContext, sql: &str) -> LogicalPlan {
+        ctx.state()
+            .create_logical_plan(sql)
+            .await
+            .expect("valid plan")
+    }
+
+    #[tokio::test]
+    async fn test_is_incremental() {
+        let ctx = context();
+        for (sql, incremental) in [
+            (
+                "SELECT t.id, z.name FROM trips t JOIN zones z ON t.id = z.id WHERE t.id > 1",
+                true,
+            ),
+            ("SELECT id FROM trips UNION ALL SELECT id FROM zones", true),
+            ("SELECT id FROM trips UNION SELECT id FROM zones", false),
+            ("SELECT name, COUNT(*) FROM trips GROUP BY name", false),
+            (
+                "SELECT * FROM trips t LEFT JOIN zones z ON t.id = z.id",
+                false,
+            ),
+            (
+                "SELECT * FROM trips WHERE id NOT IN (SELECT id FROM zones)",
+                false,
+            ),
+        ] {
+            assert_eq!(is_incremental(&plan(&ctx, sql).await), incremental, "{sql}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_appended_plan() {
+        let ctx = context();
+        let sql =
+            "SELECT t.id, t.name AS trip, z.name AS zone FROM trips t JOIN zones z ON t.id = z.id";
+        let appended = [batch(vec![3, 4], vec!["third", "fourth"])];
+
+        let plan =
+            appended_plan(plan(&ctx, sql).await, "trips", &appended).expect("view is incremental");
+        let data = ctx
+            .execute_logical_plan(plan)
+            .await
+            .expect("valid plan")
+            .collect()
+            .await
+            .expect("view computed");
+
+        assert_eq!(data.iter().map(RecordBatch::num_rows).sum::<usize>(), 1);
+
+        let self_join = "SELECT * FROM trips a JOIN trips b ON a.id = b.id";
+        assert!(appended_plan(plan(&ctx, self_join).await, "trips", &appended).is_none());
+    }
+}
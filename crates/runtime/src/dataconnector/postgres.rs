@@ -1,13 +1,17 @@
 use arrow::array::RecordBatch;
+use async_stream::stream;
 use async_trait::async_trait;
 use bb8_postgres::tokio_postgres::types::ToSql;
 use bb8_postgres::tokio_postgres::NoTls;
 use bb8_postgres::PostgresConnectionManager;
 use datafusion::datasource::TableProvider;
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
 use datafusion::sql::TableReference;
+use db_connection_pool::dbconnection;
 use db_connection_pool::postgrespool::PostgresConnectionPool;
 use db_connection_pool::DbConnectionPool;
 use futures::TryStreamExt;
+use futures_core::stream::BoxStream;
 use secrets::Secret;
 use snafu::prelude::*;
 use spicepod::component::dataset::Dataset;
@@ -16,18 +20,40 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::{collections::HashMap, future::Future};
 
-use super::DataConnector;
+use super::render_filter;
 use super::Result;
+use super::UnableToCreateDataConnectorSnafu;
 use super::UnableToGetTableProviderSnafu;
+use super::{DataConnector, DataResult, DataUpdateResult, FailedDataUpdate};
+use crate::dataupdate::{DataUpdate, UpdateType};
+
+pub mod cdc;
+
+pub(crate) type PostgresPool = Arc<
+    dyn DbConnectionPool<
+            bb8::PooledConnection<'static, PostgresConnectionManager<NoTls>>,
+            &'static (dyn ToSql + Sync),
+        > + Send
+        + Sync,
+>;
 
 pub struct Postgres {
-    pool: Arc<
-        dyn DbConnectionPool<
-                bb8::PooledConnection<'static, PostgresConnectionManager<NoTls>>,
-                &'static (dyn ToSql + Sync),
-            > + Send
-            + Sync,
-    >,
+    pool: PostgresPool,
+    /// Where the changes to the dataset are streamed from, if change data capture is configured.
+    replication: Option<cdc::ReplicationSlot>,
+}
+
+impl Postgres {
+    fn query(pool: PostgresPool, sql: String) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        Box::pin(async move {
+            let conn = pool.connect().await?;
+            let record_batch_stream = dbconnection::query_arrow(conn, sql).await?;
+            let recs = record_batch_stream
+                .try_collect::<Vec<RecordBatch>>()
+                .await?;
+            Ok(recs)
+        })
+    }
 }
 
 #[async_trait]
@@ -40,66 +66,182 @@ impl DataConnector for Postgres {
         Self: Sized,
     {
         Box::pin(async move {
-            let pool: Arc<
-                dyn DbConnectionPool<
-                        bb8::PooledConnection<'static, PostgresConnectionManager<NoTls>>,
-                        &'static (dyn ToSql + Sync),
-                    > + Send
-                    + Sync,
-            > = Arc::new(
+            let replication = match params.as_ref() {
+                Some(params) => cdc::ReplicationSlot::from_params(params)
+                    .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
+                    .context(UnableToCreateDataConnectorSnafu)?,
+                None => None,
+            };
+
+            let pool: PostgresPool = Arc::new(
                 PostgresConnectionPool::new(params, secret)
                     .await
                     .context(UnableToGetTableProviderSnafu)?,
             );
 
-            Ok(Self { pool })
+            Ok(Self { pool, replication })
         })
     }
 
-    fn get_all_data(
-        &self,
-        dataset: &Dataset,
-    ) -> Pin<Box<dyn Future<Output = Vec<RecordBatch>> + Send>> {
-        let path = dataset.path().clone();
+    fn supports_data_streaming(&self, _dataset: &Dataset) -> bool {
+        self.replication.is_some()
+    }
+
+    /// Loads the table, then streams the changes made to it from the replication slot.
+    ///
+    /// The changes are acknowledged after the updates made from them are written, so they are written at
+    /// least once. Changes that can't be written as upserts or deletes reload the whole table instead.
+    fn stream_data_updates<'a>(&self, dataset: &Dataset) -> BoxStream<'a, DataUpdateResult> {
+        let table = dataset.path();
+        let Some(replication) = self.replication.clone() else {
+            let error = cdc::Error::NotConfigured { table };
+            return Box::pin(futures::stream::once(async move {
+                Err(FailedDataUpdate {
+                    update_type: UpdateType::Overwrite,
+                    error: error.into(),
+                })
+            }));
+        };
         let pool = Arc::clone(&self.pool);
-        Box::pin(async move {
-            let conn = match pool.connect().await {
-                Ok(conn) => conn,
-                Err(e) => {
-                    tracing::error!("Failed to connect to Postgres: {e}");
-                    return vec![];
-                }
-            };
+        let sql = format!("SELECT * FROM {table}");
+        let poll_interval = dataset
+            .refresh_interval()
+            .unwrap_or(cdc::DEFAULT_POLL_INTERVAL);
 
-            let Some(async_conn) = conn.as_async() else {
-                tracing::error!("Failed to convert postgres conn to async connection",);
-                return vec![];
-            };
+        Box::pin(stream! {
+            // Created before the table is loaded, so no change made after it is missed.
+            let mut failures = 0;
+            while let Err(e) = replication.create(&pool).await {
+                failures += 1;
+                if failures >= cdc::MAX_FAILURES {
+                    yield Err(FailedDataUpdate {
+                        update_type: UpdateType::Overwrite,
+                        error: e.into(),
+                    });
+                    return;
+                }
+                tracing::warn!("Failed to create the replication slot for {table}, retrying: {e}");
+                tokio::time::sleep(cdc::retry_delay(failures)).await;
+            }
 
-            let record_batch_stream = match async_conn
-                .query_arrow(format!("SELECT * FROM {path}").as_str(), &[])
-                .await
-            {
-                Ok(stream) => stream,
-                Err(e) => {
-                    tracing::error!("Failed to query Postgres: {e}");
-                    return vec![];
+            let mut schema = None;
+            // The table is loaded first, and again whenever its changes can't be written as upserts or deletes.
+            let mut reload = true;
+            let mut written_lsn: Option<String> = None;
+            failures = 0;
+            loop {
+                if reload {
+                    match Self::query(Arc::clone(&pool), sql.clone()).await {
+                        Ok(data) => {
+                            schema = data.first().map(RecordBatch::schema).or(schema);
+                            reload = false;
+                            failures = 0;
+                            yield Ok(DataUpdate {
+                                data,
+                                update_type: UpdateType::Overwrite,
+                            });
+                        }
+                        // The changes are only acknowledged once the table is loaded, so none are lost.
+                        Err(error) => {
+                            failures += 1;
+                            yield Err(FailedDataUpdate {
+                                update_type: UpdateType::Overwrite,
+                                error,
+                            });
+                            if failures >= cdc::MAX_FAILURES {
+                                return;
+                            }
+                        }
+                    }
+                }
+                if !reload {
+                    if let Some(lsn) = written_lsn.take() {
+                        if let Err(e) = replication.advance(&pool, &lsn).await {
+                            tracing::warn!("Failed to acknowledge the changes to {table}: {e}");
+                        }
+                    }
+                }
+                if failures > 0 {
+                    tokio::time::sleep(cdc::retry_delay(failures)).await;
+                } else {
+                    tokio::time::sleep(poll_interval).await;
+                }
+                if reload {
+                    continue;
                 }
-            };
 
-            let recs: Vec<RecordBatch> =
-                match record_batch_stream.try_collect::<Vec<RecordBatch>>().await {
-                    Ok(recs) => recs,
+                let changes = match replication.peek(&pool).await {
+                    Ok(changes) => {
+                        failures = 0;
+                        changes
+                    }
                     Err(e) => {
-                        tracing::error!("Failed to collect record batches from Postgres: {e}");
-                        return vec![];
+                        failures += 1;
+                        // Stopping the stream leaves the acceleration as it is, and it is only resumed
+                        // by loading the table again.
+                        if failures >= cdc::MAX_FAILURES {
+                            yield Err(FailedDataUpdate {
+                                update_type: UpdateType::Overwrite,
+                                error: e.into(),
+                            });
+                            return;
+                        }
+                        tracing::warn!("Failed to read the changes to {table}, retrying: {e}");
+                        continue;
                     }
                 };
+                let Some((last_lsn, _)) = changes.last() else {
+                    continue;
+                };
 
-            recs
+                let mut table_changes = cdc::TableChanges::new(&table, schema.clone());
+                let updates = changes
+                    .iter()
+                    .try_for_each(|(_, message)| table_changes.apply(cdc::decode(message)?))
+                    .and_then(|()| table_changes.take_updates());
+                match updates {
+                    Ok(Some(updates)) => {
+                        for data_update in updates {
+                            yield Ok(data_update);
+                        }
+                    }
+                    Ok(None) => reload = true,
+                    Err(e) => {
+                        tracing::warn!("Failed to apply the changes to {table}, reloading it: {e}");
+                        reload = true;
+                    }
+                }
+                written_lsn = Some(last_lsn.clone());
+            }
         })
     }
 
+    fn get_all_data(&self, dataset: &Dataset) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        Self::query(
+            Arc::clone(&self.pool),
+            format!("SELECT * FROM {}", dataset.path()),
+        )
+    }
+
+    fn supports_filtered_data(&self) -> bool {
+        true
+    }
+
+    fn get_filtered_data(
+        &self,
+        dataset: &Dataset,
+        filter: &str,
+    ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        let filter = match render_filter(filter, &PostgreSqlDialect {}) {
+            Ok(filter) => filter,
+            Err(e) => return Box::pin(async move { Err(e.into()) }),
+        };
+        Self::query(
+            Arc::clone(&self.pool),
+            format!("SELECT * FROM {} WHERE {filter}", dataset.path()),
+        )
+    }
+
     fn has_table_provider(&self) -> bool {
         true
     }
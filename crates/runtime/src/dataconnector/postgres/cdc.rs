@@ -0,0 +1,914 @@
+//! Change data capture from a Postgres logical replication slot.
+//!
+//! Change data capture is enabled for a dataset by its `pg_replication_slot` and `pg_publication`
+//! parameters, whatever its `refresh_mode`. The changes are read with `pg_logical_slot_peek_binary_changes`
+//! in the binary protocol of the `pgoutput` plugin (version 1), and only acknowledged once the
+//! `DataUpdate`s made from them were written. Inserts and updates become upserts and deletes become
+//! deletes, both by the replica identity of the table, which is its primary key by default.
+//!
+//! The slot is polled every `refresh_interval` (or `DEFAULT_POLL_INTERVAL`) over a regular connection
+//! rather than consumed through the streaming replication protocol, which `tokio-postgres` doesn't
+//! support. Changes therefore arrive up to one interval late and every poll runs a query, even when
+//! nothing changed, but the slot is read through the same connection pool as the table.
+//!
+//! Failures are retried with a growing delay. After `MAX_FAILURES` in a row the stream stops, and the
+//! dataset reports the last error.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow::array::{ArrayRef, RecordBatch, StringArray};
+use arrow::compute::{cast_with_options, CastOptions};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use bb8_postgres::tokio_postgres;
+use db_connection_pool::dbconnection::postgresconn::PostgresConnection;
+use snafu::prelude::*;
+
+use super::PostgresPool;
+use crate::dataupdate::{DataUpdate, UpdateType};
+
+/// How often the replication slot is read when the dataset has no `refresh_interval`.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The most changes read from the replication slot at once. Whole transactions are always read.
+const MAX_CHANGES: i32 = 10_000;
+
+/// How many times in a row creating the slot, loading the table or reading the changes may fail before
+/// the stream of changes stops.
+pub const MAX_FAILURES: u32 = 5;
+
+/// The delay before the first retry, doubled after each further failure.
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("The pgoutput message ended unexpectedly"))]
+    UnexpectedEnd,
+
+    #[snafu(display("Unknown pgoutput message type {tag}"))]
+    UnknownMessageType { tag: char },
+
+    #[snafu(display("Unknown pgoutput column value kind {kind}"))]
+    UnknownValueKind { kind: char },
+
+    #[snafu(display("The pgoutput message contains invalid UTF-8"))]
+    InvalidUtf8,
+
+    #[snafu(display("Relation {relation_id} was changed before it was described"))]
+    UnknownRelation { relation_id: u32 },
+
+    #[snafu(display("Unable to convert the changes of {table} to Arrow: {source}"))]
+    UnableToConvertChanges { table: String, source: ArrowError },
+
+    #[snafu(display("Change data capture is not configured for {table}"))]
+    NotConfigured { table: String },
+
+    #[snafu(display("Dataset parameter {param} is required for change data capture"))]
+    MissingParameter { param: String },
+
+    #[snafu(display("Unable to connect to Postgres: {source}"))]
+    UnableToConnect { source: db_connection_pool::Error },
+
+    #[snafu(display("Unable to downcast the connection to a Postgres connection"))]
+    UnableToDowncastConnection,
+
+    #[snafu(display("Unable to read the replication slot {slot}: {source}"))]
+    UnableToReadSlot {
+        slot: String,
+        source: tokio_postgres::Error,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// How long to wait before retrying after `failures` failures in a row.
+#[must_use]
+pub fn retry_delay(failures: u32) -> Duration {
+    RETRY_BACKOFF * 2u32.saturating_pow(failures.saturating_sub(1))
+}
+
+/// The logical replication slot and publication the changes of a dataset are read from.
+#[derive(Debug, Clone)]
+pub struct ReplicationSlot {
+    slot: String,
+    publication: String,
+}
+
+impl ReplicationSlot {
+    /// Reads the slot from the `pg_replication_slot` and `pg_publication` parameters, or returns `None`
+    /// if change data capture isn't configured.
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Option<Self>> {
+        let Some(slot) = params.get("pg_replication_slot") else {
+            return Ok(None);
+        };
+        let publication = params
+            .get("pg_publication")
+            .context(MissingParameterSnafu {
+                param: "pg_publication",
+            })?;
+
+        Ok(Some(ReplicationSlot {
+            slot: slot.clone(),
+            publication: publication.clone(),
+        }))
+    }
+
+    /// Creates the slot if it doesn't exist, after which it keeps every change until it is read.
+    pub async fn create(&self, pool: &PostgresPool) -> Result<()> {
+        let conn = pool.connect().await.context(UnableToConnectSnafu)?;
+        let conn = conn
+            .as_any()
+            .downcast_ref::<PostgresConnection>()
+            .context(UnableToDowncastConnectionSnafu)?;
+
+        conn.conn
+            .execute(
+                "SELECT pg_create_logical_replication_slot($1, 'pgoutput') \
+                 WHERE NOT EXISTS (SELECT 1 FROM pg_replication_slots WHERE slot_name = $1)",
+                &[&self.slot],
+            )
+            .await
+            .context(UnableToReadSlotSnafu { slot: &self.slot })?;
+
+        Ok(())
+    }
+
+    /// Reads the changes that haven't been acknowledged yet, as the LSN and `pgoutput` message of each.
+    pub async fn peek(&self, pool: &PostgresPool) -> Result<Vec<(String, Vec<u8>)>> {
+        let conn = pool.connect().await.context(UnableToConnectSnafu)?;
+        let conn = conn
+            .as_any()
+            .downcast_ref::<PostgresConnection>()
+            .context(UnableToDowncastConnectionSnafu)?;
+
+        let rows = conn
+            .conn
+            .query(
+                "SELECT lsn::text, data FROM pg_logical_slot_peek_binary_changes(\
+                 $1, NULL, $2, 'proto_version', '1', 'publication_names', $3)",
+                &[&self.slot, &MAX_CHANGES, &self.publication],
+            )
+            .await
+            .context(UnableToReadSlotSnafu { slot: &self.slot })?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get::<usize, String>(0), row.get::<usize, Vec<u8>>(1)))
+            .collect())
+    }
+
+    /// Acknowledges the changes up to `lsn`, which won't be read again.
+    pub async fn advance(&self, pool: &PostgresPool, lsn: &str) -> Result<()> {
+        let conn = pool.connect().await.context(UnableToConnectSnafu)?;
+        let conn = conn
+            .as_any()
+            .downcast_ref::<PostgresConnection>()
+            .context(UnableToDowncastConnectionSnafu)?;
+
+        conn.conn
+            .execute(
+                "SELECT pg_replication_slot_advance($1, $2::text::pg_lsn)",
+                &[&self.slot, &lsn],
+            )
+            .await
+            .context(UnableToReadSlotSnafu { slot: &self.slot })?;
+
+        Ok(())
+    }
+}
+
+/// A `pgoutput` message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Begin,
+    Commit,
+    Relation(Relation),
+    Insert {
+        relation_id: u32,
+        new: Vec<Value>,
+    },
+    Update {
+        relation_id: u32,
+        /// The previous replica identity of the row, only sent if it changed or the identity is `FULL`.
+        old: Option<Vec<Value>>,
+        new: Vec<Value>,
+    },
+    Delete {
+        relation_id: u32,
+        old: Vec<Value>,
+    },
+    Truncate {
+        relation_ids: Vec<u32>,
+    },
+    /// Origin, type and logical decoding messages, which don't change any rows.
+    Other,
+}
+
+/// The columns of a table, sent before the first change to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relation {
+    pub id: u32,
+    pub namespace: String,
+    pub name: String,
+    pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub type_oid: u32,
+    pub type_modifier: i32,
+    /// Whether the column is part of the replica identity of the table.
+    pub is_key: bool,
+}
+
+/// The value of a column in a changed row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    /// A TOASTed value that didn't change, which isn't sent.
+    UnchangedToast,
+    Text(String),
+}
+
+impl Relation {
+    fn key_indices(&self) -> Vec<usize> {
+        self.columns
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| column.is_key)
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+/// Decodes a single `pgoutput` message.
+pub fn decode(message: &[u8]) -> Result<Message> {
+    let mut reader = Reader { bytes: message };
+    let tag = reader.u8()?;
+    match tag {
+        b'B' => Ok(Message::Begin),
+        b'C' => Ok(Message::Commit),
+        b'O' | b'Y' | b'M' => Ok(Message::Other),
+        b'R' => {
+            let id = reader.u32()?;
+            let namespace = reader.string()?;
+            let name = reader.string()?;
+            let _replica_identity = reader.u8()?;
+            let column_count = reader.u16()?;
+            let mut columns = Vec::with_capacity(usize::from(column_count));
+            for _ in 0..column_count {
+                let flags = reader.u8()?;
+                columns.push(Column {
+                    name: reader.string()?,
+                    type_oid: reader.u32()?,
+                    type_modifier: reader.i32()?,
+                    is_key: flags & 1 == 1,
+                });
+            }
+
+            Ok(Message::Relation(Relation {
+                id,
+                namespace,
+                name,
+                columns,
+            }))
+        }
+        b'I' => {
+            let relation_id = reader.u32()?;
+            let _new = reader.u8()?;
+            Ok(Message::Insert {
+                relation_id,
+                new: reader.tuple()?,
+            })
+        }
+        b'U' => {
+            let relation_id = reader.u32()?;
+            let old = match reader.u8()? {
+                b'K' | b'O' => {
+                    let old = reader.tuple()?;
+                    let _new = reader.u8()?;
+                    Some(old)
+                }
+                _ => None,
+            };
+            Ok(Message::Update {
+                relation_id,
+                old,
+                new: reader.tuple()?,
+            })
+        }
+        b'D' => {
+            let relation_id = reader.u32()?;
+            let _old = reader.u8()?;
+            Ok(Message::Delete {
+                relation_id,
+                old: reader.tuple()?,
+            })
+        }
+        b'T' => {
+            let relation_count = reader.u32()?;
+            let _options = reader.u8()?;
+            let relation_ids = (0..relation_count)
+                .map(|_| reader.u32())
+                .collect::<Result<_>>()?;
+            Ok(Message::Truncate { relation_ids })
+        }
+        tag => UnknownMessageTypeSnafu { tag: tag as char }.fail(),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(self.bytes.len() >= len, UnexpectedEndSnafu);
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a null-terminated string.
+    fn string(&mut self) -> Result<String> {
+        let len = self
+            .bytes
+            .iter()
+            .position(|byte| *byte == 0)
+            .context(UnexpectedEndSnafu)?;
+        let string = self.take(len)?;
+        self.take(1)?;
+        String::from_utf8(string.to_vec()).map_err(|_| Error::InvalidUtf8)
+    }
+
+    fn tuple(&mut self) -> Result<Vec<Value>> {
+        let column_count = self.u16()?;
+        let mut values = Vec::with_capacity(usize::from(column_count));
+        for _ in 0..column_count {
+            let value = match self.u8()? {
+                b'n' => Value::Null,
+                b'u' => Value::UnchangedToast,
+                b't' => {
+                    let len = self.u32()? as usize;
+                    let text = self.take(len)?;
+                    Value::Text(String::from_utf8(text.to_vec()).map_err(|_| Error::InvalidUtf8)?)
+                }
+                kind => return UnknownValueKindSnafu { kind: kind as char }.fail(),
+            };
+            values.push(value);
+        }
+
+        Ok(values)
+    }
+}
+
+/// Collects the changes to one table into `DataUpdate`s, in the order they were made.
+///
+/// Consecutive changes of the same kind are written together, keeping only the last change to each row.
+pub struct TableChanges {
+    namespace: String,
+    name: String,
+    /// The schema of the accelerated table, which the changed values are converted to.
+    schema: Option<SchemaRef>,
+    relations: HashMap<u32, Relation>,
+    pending: Option<PendingChanges>,
+    updates: Vec<DataUpdate>,
+    needs_snapshot: bool,
+}
+
+struct PendingChanges {
+    relation_id: u32,
+    is_delete: bool,
+    rows: Vec<Vec<Option<String>>>,
+    row_by_key: HashMap<Vec<Option<String>>, usize>,
+}
+
+impl TableChanges {
+    /// Collects the changes to `table`, i.e. `public.trips` or `trips` for a table in the `public` schema.
+    #[must_use]
+    pub fn new(table: &str, schema: Option<SchemaRef>) -> Self {
+        let (namespace, name) = table.split_once('.').unwrap_or(("public", table));
+        TableChanges {
+            namespace: namespace.trim_matches('"').to_string(),
+            name: name.trim_matches('"').to_string(),
+            schema,
+            relations: HashMap::new(),
+            pending: None,
+            updates: Vec::new(),
+            needs_snapshot: false,
+        }
+    }
+
+    pub fn apply(&mut self, message: Message) -> Result<()> {
+        match message {
+            Message::Relation(relation) => {
+                if self
+                    .pending
+                    .as_ref()
+                    .is_some_and(|pending| pending.relation_id == relation.id)
+                {
+                    self.flush()?;
+                }
+                self.relations.insert(relation.id, relation);
+            }
+            Message::Insert { relation_id, new } => self.change(relation_id, false, new)?,
+            Message::Update {
+                relation_id,
+                old,
+                mut new,
+            } => {
+                if let Some(old) = old {
+                    // Unchanged TOASTed values are known from the old row if the replica identity is `FULL`.
+                    for (value, old_value) in new.iter_mut().zip(&old) {
+                        if *value == Value::UnchangedToast {
+                            *value = old_value.clone();
+                        }
+                    }
+
+                    let key_changed = self.relations.get(&relation_id).is_some_and(|relation| {
+                        relation
+                            .key_indices()
+                            .iter()
+                            .any(|i| old.get(*i) != new.get(*i))
+                    });
+                    if key_changed {
+                        self.change(relation_id, true, old)?;
+                    }
+                }
+                self.change(relation_id, false, new)?;
+            }
+            Message::Delete { relation_id, old } => self.change(relation_id, true, old)?,
+            Message::Truncate { relation_ids } => {
+                if relation_ids.iter().any(|id| {
+                    self.relations
+                        .get(id)
+                        .is_some_and(|relation| self.is_table(relation))
+                }) {
+                    self.needs_snapshot = true;
+                }
+            }
+            Message::Begin | Message::Commit | Message::Other => {}
+        }
+
+        Ok(())
+    }
+
+    /// Returns the updates made from the changes applied so far, or `None` if they can't be written as
+    /// updates and the table has to be reloaded instead.
+    pub fn take_updates(&mut self) -> Result<Option<Vec<DataUpdate>>> {
+        self.flush()?;
+        let updates = std::mem::take(&mut self.updates);
+        if std::mem::take(&mut self.needs_snapshot) {
+            return Ok(None);
+        }
+
+        Ok(Some(updates))
+    }
+
+    fn is_table(&self, relation: &Relation) -> bool {
+        relation.namespace == self.namespace && relation.name == self.name
+    }
+
+    fn change(&mut self, relation_id: u32, is_delete: bool, row: Vec<Value>) -> Result<()> {
+        let relation = self
+            .relations
+            .get(&relation_id)
+            .context(UnknownRelationSnafu { relation_id })?;
+        if !self.is_table(relation) || self.needs_snapshot {
+            return Ok(());
+        }
+
+        let key_indices = relation.key_indices();
+        if key_indices.is_empty() {
+            tracing::warn!(
+                "Table {}.{} has no replica identity, reloading it instead of applying its changes",
+                self.namespace,
+                self.name
+            );
+            self.needs_snapshot = true;
+            return Ok(());
+        }
+
+        let mut values = Vec::with_capacity(row.len());
+        for value in row {
+            match value {
+                Value::Null => values.push(None),
+                Value::Text(text) => values.push(Some(text)),
+                // Only the replica identity of deleted rows is used, which is never TOASTed.
+                Value::UnchangedToast if is_delete => values.push(None),
+                Value::UnchangedToast => {
+                    tracing::debug!(
+                        "An update to {}.{} left a TOASTed value unchanged, reloading the table",
+                        self.namespace,
+                        self.name
+                    );
+                    self.needs_snapshot = true;
+                    return Ok(());
+                }
+            }
+        }
+        let key: Vec<Option<String>> = key_indices
+            .iter()
+            .map(|i| values.get(*i).cloned().flatten())
+            .collect();
+
+        if self.pending.as_ref().is_some_and(|pending| {
+            pending.relation_id != relation_id || pending.is_delete != is_delete
+        }) {
+            self.flush()?;
+        }
+        let pending = self.pending.get_or_insert_with(|| PendingChanges {
+            relation_id,
+            is_delete,
+            rows: Vec::new(),
+            row_by_key: HashMap::new(),
+        });
+        if let Some(existing) = pending.row_by_key.get(&key) {
+            pending.rows[*existing] = values;
+        } else {
+            pending.row_by_key.insert(key, pending.rows.len());
+            pending.rows.push(values);
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let Some(pending) = self.pending.take() else {
+            return Ok(());
+        };
+        let relation = self
+            .relations
+            .get(&pending.relation_id)
+            .context(UnknownRelationSnafu {
+                relation_id: pending.relation_id,
+            })?;
+
+        let key_indices = relation.key_indices();
+        let column_indices: Vec<usize> = if pending.is_delete {
+            key_indices.clone()
+        } else {
+            (0..relation.columns.len()).collect()
+        };
+
+        let mut fields = Vec::with_capacity(column_indices.len());
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(column_indices.len());
+        for i in column_indices {
+            let column = &relation.columns[i];
+            let data_type = self
+                .schema
+                .as_ref()
+                .and_then(|schema| schema.field_with_name(&column.name).ok())
+                .map_or_else(
+                    || column_data_type(column),
+                    |field| field.data_type().clone(),
+                );
+
+            let text = StringArray::from(
+                pending
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        row.get(i)
+                            .cloned()
+                            .flatten()
+                            .map(|value| normalize_text(column.type_oid, value))
+                    })
+                    .collect::<Vec<_>>(),
+            );
+            let array = cast_with_options(
+                &text,
+                &data_type,
+                &CastOptions {
+                    safe: false,
+                    ..CastOptions::default()
+                },
+            )
+            .context(UnableToConvertChangesSnafu {
+                table: relation.name.clone(),
+            })?;
+
+            fields.push(Field::new(column.name.clone(), data_type, true));
+            columns.push(array);
+        }
+
+        let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).context(
+            UnableToConvertChangesSnafu {
+                table: relation.name.clone(),
+            },
+        )?;
+        let keys = key_indices
+            .iter()
+            .map(|i| relation.columns[*i].name.clone())
+            .collect();
+        self.updates.push(DataUpdate {
+            data: vec![batch],
+            update_type: if pending.is_delete {
+                UpdateType::Delete { keys }
+            } else {
+                UpdateType::Upsert { keys }
+            },
+        });
+
+        Ok(())
+    }
+}
+
+/// The Arrow type of a column, matching the types of the rows loaded with `SELECT`.
+fn column_data_type(column: &Column) -> DataType {
+    match column.type_oid {
+        16 => DataType::Boolean,
+        20 => DataType::Int64,
+        21 => DataType::Int16,
+        23 => DataType::Int32,
+        700 => DataType::Float32,
+        701 => DataType::Float64,
+        1082 => DataType::Date32,
+        1114 => DataType::Timestamp(TimeUnit::Millisecond, None),
+        1184 => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+        // The modifier of a `numeric(precision, scale)` column is `(precision << 16 | scale) + 4`.
+        1700 if column.type_modifier >= 4 => {
+            #[allow(clippy::cast_possible_truncation)]
+            let scale = ((column.type_modifier - 4) & 0xffff) as i8;
+            DataType::Decimal128(38, scale)
+        }
+        _ => DataType::Utf8,
+    }
+}
+
+/// Rewrites the text output of Postgres into a form Arrow can parse, i.e. `+00` offsets to `+00:00`.
+fn normalize_text(type_oid: u32, text: String) -> String {
+    let bytes = text.as_bytes();
+    let has_hour_offset = bytes.len() > 3
+        && matches!(bytes[bytes.len() - 3], b'+' | b'-')
+        && bytes[bytes.len() - 2..].iter().all(u8::is_ascii_digit);
+    if type_oid == 1184 && has_hour_offset {
+        format!("{text}:00")
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Int32Array, StringArray};
+
+    use super::*;
+
+    fn relation_message(id: u32, table: &str, columns: &[(&str, u32, bool)]) -> Vec<u8> {
+        let mut message = vec![b'R'];
+        message.extend(id.to_be_bytes());
+        message.extend(b"public\0");
+        message.extend(table.as_bytes());
+        message.extend([0, b'd']);
+        message.extend(
+            u16::try_from(columns.len())
+                .expect("few columns")
+                .to_be_bytes(),
+        );
+        for (name, type_oid, is_key) in columns {
+            message.push(u8::from(*is_key));
+            message.extend(name.as_bytes());
+            message.push(0);
+            message.extend(type_oid.to_be_bytes());
+            message.extend((-1_i32).to_be_bytes());
+        }
+        message
+    }
+
+    fn tuple(values: &[Option<&str>]) -> Vec<u8> {
+        let mut tuple = u16::try_from(values.len())
+            .expect("few values")
+            .to_be_bytes()
+            .to_vec();
+        for value in values {
+            match value {
+                Some(text) => {
+                    tuple.push(b't');
+                    tuple.extend(u32::try_from(text.len()).expect("short").to_be_bytes());
+                    tuple.extend(text.as_bytes());
+                }
+                None => tuple.push(b'n'),
+            }
+        }
+        tuple
+    }
+
+    fn change_message(tag: u8, id: u32, kind: u8, values: &[Option<&str>]) -> Vec<u8> {
+        let mut message = vec![tag];
+        message.extend(id.to_be_bytes());
+        message.push(kind);
+        message.extend(tuple(values));
+        message
+    }
+
+    fn column<T: 'static>(update: &DataUpdate, name: &str) -> T
+    where
+        T: Clone,
+    {
+        update.data[0]
+            .column_by_name(name)
+            .expect("column exists")
+            .as_any()
+            .downcast_ref::<T>()
+            .expect("column type")
+            .clone()
+    }
+
+    #[test]
+    fn test_decode() {
+        let relation = relation_message(7, "trips", &[("id", 23, true), ("name", 25, false)]);
+        let Message::Relation(relation) = decode(&relation).expect("valid message") else {
+            panic!("expected a relation");
+        };
+        assert_eq!(relation.name, "trips");
+        assert_eq!(relation.key_indices(), vec![0]);
+
+        let update = change_message(b'U', 7, b'N', &[Some("1"), None]);
+        assert_eq!(
+            decode(&update).expect("valid message"),
+            Message::Update {
+                relation_id: 7,
+                old: None,
+                new: vec![Value::Text("1".to_string()), Value::Null],
+            }
+        );
+
+        assert!(matches!(decode(&update[..8]), Err(Error::UnexpectedEnd)));
+        assert!(matches!(
+            decode(b"X"),
+            Err(Error::UnknownMessageType { tag: 'X' })
+        ));
+    }
+
+    #[test]
+    fn test_table_changes() {
+        let mut changes = TableChanges::new("trips", None);
+        let messages = [
+            vec![b'B'],
+            relation_message(7, "trips", &[("id", 23, true), ("name", 25, false)]),
+            relation_message(8, "zones", &[("id", 23, true)]),
+            change_message(b'I', 7, b'N', &[Some("1"), Some("first")]),
+            change_message(b'I', 8, b'N', &[Some("1")]),
+            change_message(b'I', 7, b'N', &[Some("2"), Some("second")]),
+            change_message(b'U', 7, b'N', &[Some("1"), Some("updated")]),
+            change_message(b'D', 7, b'K', &[Some("2"), None]),
+            vec![b'C'],
+        ];
+        for message in messages {
+            changes
+                .apply(decode(&message).expect("valid message"))
+                .expect("change applied");
+        }
+
+        let updates = changes
+            .take_updates()
+            .expect("valid changes")
+            .expect("no reload needed");
+        assert_eq!(updates.len(), 2);
+
+        let keys = vec!["id".to_string()];
+        assert_eq!(
+            updates[0].update_type,
+            UpdateType::Upsert { keys: keys.clone() }
+        );
+        assert_eq!(column::<Int32Array>(&updates[0], "id").values(), &[1, 2]);
+        assert_eq!(
+            column::<StringArray>(&updates[0], "name")
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![Some("updated"), Some("second")]
+        );
+
+        assert_eq!(updates[1].update_type, UpdateType::Delete { keys });
+        assert_eq!(updates[1].data[0].num_columns(), 1);
+        assert_eq!(column::<Int32Array>(&updates[1], "id").values(), &[2]);
+
+        let mut truncate = vec![b'T'];
+        truncate.extend(1_u32.to_be_bytes());
+        truncate.push(0);
+        truncate.extend(7_u32.to_be_bytes());
+        changes
+            .apply(decode(&truncate).expect("valid message"))
+            .expect("change applied");
+        assert!(changes.take_updates().expect("valid changes").is_none());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_replicate_nulls_and_timestamps_to_sqlite() {
+        use datafusion::execution::context::SessionContext;
+        use db_connection_pool::Mode;
+        use spicepod::component::dataset::Dataset;
+
+        use crate::{databackend::sqlite::SqliteBackend, datapublisher::DataPublisher};
+
+        let mut changes = TableChanges::new("trips", None);
+        let messages = [
+            relation_message(
+                7,
+                "trips",
+                &[
+                    ("id", 23, true),
+                    ("name", 25, false),
+                    ("started_at", 1184, false),
+                ],
+            ),
+            change_message(
+                b'I',
+                7,
+                b'N',
+                &[Some("1"), None, Some("2024-01-02 03:04:05.6+00")],
+            ),
+        ];
+        for message in messages {
+            changes
+                .apply(decode(&message).expect("valid message"))
+                .expect("change applied");
+        }
+        let updates = changes
+            .take_updates()
+            .expect("valid changes")
+            .expect("no reload needed");
+
+        let path =
+            std::env::temp_dir().join(format!("spice_cdc_sqlite_test_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let params = HashMap::from([(
+            "sqlite_file".to_string(),
+            path.to_string_lossy().to_string(),
+        )]);
+        let backend = SqliteBackend::new(
+            Arc::new(SessionContext::new()),
+            "trips",
+            Arc::new(Some(params)),
+            Mode::File,
+            Some(vec!["id".to_string()]),
+        )
+        .await
+        .expect("backend created");
+        let dataset = Arc::new(Dataset::new(
+            "postgres:trips".to_string(),
+            "trips".to_string(),
+        ));
+        for update in updates {
+            backend
+                .add_data(Arc::clone(&dataset), update)
+                .await
+                .expect("update written");
+        }
+
+        let conn = rusqlite::Connection::open(&path).expect("database opened");
+        let (id, name, started_at): (i64, Option<String>, String) = conn
+            .query_row(
+                r#"SELECT "id", "name", "started_at" FROM "trips""#,
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .expect("row replicated");
+        assert_eq!(id, 1);
+        assert_eq!(name, None);
+        assert!(
+            started_at.starts_with("2024-01-02 03:04:05"),
+            "unexpected timestamp {started_at}"
+        );
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_normalize_text() {
+        assert_eq!(
+            normalize_text(1184, "2024-01-02 03:04:05.6+00".to_string()),
+            "2024-01-02 03:04:05.6+00:00"
+        );
+        assert_eq!(
+            normalize_text(1184, "2024-01-02 03:04:05+05:30".to_string()),
+            "2024-01-02 03:04:05+05:30"
+        );
+        assert_eq!(normalize_text(25, "a+00".to_string()), "a+00");
+    }
+}
@@ -10,7 +10,7 @@ use url::Url;
 
 use spicepod::component::dataset::Dataset;
 
-use super::DataConnector;
+use super::{DataConnector, DataResult};
 use snafu::prelude::*;
 
 #[derive(Debug, Snafu)]
@@ -40,6 +40,32 @@ impl S3 {
             .as_ref()
             .and_then(|params| params.get(key).cloned())
     }
+
+    fn read_data(
+        &self,
+        dataset: &Dataset,
+        filter: Option<String>,
+    ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        let path = dataset.path();
+
+        let ctx = SessionContext::new();
+        let store = self.get_object_store(dataset);
+
+        Box::pin(async move {
+            let (url, store) = store?;
+            let _ = ctx.runtime_env().register_object_store(&url, store);
+
+            ctx.register_parquet("data", &format!("s3:{path}"), ParquetReadOptions::default())
+                .await?;
+
+            let sql = match filter {
+                Some(filter) => format!("SELECT * FROM data WHERE {filter}"),
+                None => "SELECT * FROM data".to_string(),
+            };
+            let data = ctx.sql(&sql).await?.collect().await?;
+            Ok(data)
+        })
+    }
 }
 
 #[async_trait]
@@ -115,36 +141,20 @@ impl DataConnector for S3 {
         false
     }
 
-    fn get_all_data(
-        &self,
-        dataset: &Dataset,
-    ) -> Pin<Box<dyn Future<Output = Vec<arrow::record_batch::RecordBatch>> + Send>> {
-        let path = dataset.path();
+    fn get_all_data(&self, dataset: &Dataset) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        self.read_data(dataset, None)
+    }
 
-        let ctx = SessionContext::new();
-        if let Ok((url, store)) = self.get_object_store(dataset) {
-            let _ = ctx.runtime_env().register_object_store(&url, store);
-        } else {
-            return Box::pin(async move { vec![] });
-        }
+    fn supports_filtered_data(&self) -> bool {
+        true
+    }
 
-        Box::pin(async move {
-            if let Ok(df) = ctx
-                .read_parquet(format!("s3:{path}"), ParquetReadOptions::default())
-                .await
-            {
-                match df.collect().await {
-                    Ok(batches) => {
-                        return batches;
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to collect record batches from S3: {e}");
-                        return vec![];
-                    }
-                }
-            }
-            vec![]
-        })
+    fn get_filtered_data(
+        &self,
+        dataset: &Dataset,
+        filter: &str,
+    ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        self.read_data(dataset, Some(filter.to_string()))
     }
 
     fn has_table_provider(&self) -> bool {
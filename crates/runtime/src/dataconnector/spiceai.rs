@@ -7,6 +7,7 @@ use futures::StreamExt;
 use futures_core::stream::BoxStream;
 use secrets::Secret;
 use snafu::prelude::*;
+use spicepod::component::dataset::acceleration::RefreshMode;
 use spicepod::component::dataset::Dataset;
 use std::borrow::Borrow;
 use std::pin::Pin;
@@ -19,7 +20,7 @@ use crate::dataupdate::{DataUpdate, UpdateType};
 use crate::info_spaced;
 use crate::tracers::SpacedTracer;
 
-use super::{flight::Flight, DataConnector};
+use super::{flight::Flight, DataConnector, DataResult, DataUpdateResult};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -77,12 +78,12 @@ impl DataConnector for SpiceAI {
         })
     }
 
-    fn supports_data_streaming(&self, _dataset: &Dataset) -> bool {
-        true
+    fn supports_data_streaming(&self, dataset: &Dataset) -> bool {
+        dataset.refresh_mode() == RefreshMode::Append
     }
 
     /// Returns a stream of `DataUpdates` for the given dataset.
-    fn stream_data_updates<'a>(&self, dataset: &Dataset) -> BoxStream<'a, DataUpdate> {
+    fn stream_data_updates<'a>(&self, dataset: &Dataset) -> BoxStream<'a, DataUpdateResult> {
         let flight = &self.flight.client;
         let mut flight = flight.clone();
         let spice_dataset_path = Self::spice_dataset_path(dataset);
@@ -98,10 +99,10 @@ impl DataConnector for SpiceAI {
             match stream.next().await {
               Some(Ok(decoded_data)) => match decoded_data.payload {
                   DecodedPayload::RecordBatch(batch) => {
-                      yield DataUpdate {
+                      yield Ok(DataUpdate {
                         data: vec![batch],
                         update_type: UpdateType::Append,
-                      };
+                      });
                   }
                   _ => {
                       continue;
@@ -120,12 +121,22 @@ impl DataConnector for SpiceAI {
         })
     }
 
-    fn get_all_data(
+    fn get_all_data(&self, dataset: &Dataset) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+        let spice_dataset_path = Self::spice_dataset_path(dataset);
+        self.flight.get_all_data(&spice_dataset_path)
+    }
+
+    fn supports_filtered_data(&self) -> bool {
+        true
+    }
+
+    fn get_filtered_data(
         &self,
         dataset: &Dataset,
-    ) -> Pin<Box<dyn Future<Output = Vec<arrow::record_batch::RecordBatch>> + Send>> {
+        filter: &str,
+    ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
         let spice_dataset_path = Self::spice_dataset_path(dataset);
-        self.flight.get_all_data(&spice_dataset_path)
+        self.flight.get_filtered_data(&spice_dataset_path, filter)
     }
 
     fn get_data_publisher(&self) -> Option<Box<dyn DataPublisher>> {
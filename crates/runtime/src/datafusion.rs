@@ -1,29 +1,47 @@
 use std::borrow::Borrow;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
+use crate::accesscontrol::AccessPolicy;
+use crate::config::QueryConfig;
 use crate::databackend::{self, DataBackendBuilder};
+use crate::dataconnector::materializedview::{
+    DatasetUpdate, MaterializedView, UpdateBroadcastingPublisher,
+};
 use crate::dataconnector::DataConnector;
 use crate::datapublisher::DataPublisher;
-use datafusion::datasource::ViewTable;
+use crate::query::cache::{CacheInvalidatingPublisher, CacheKey};
+use crate::query::history::QueryHistoryTable;
+use crate::query::{limits, QueryCache, QueryHistory, QueryLimits};
+use crate::refresh::{self, DatasetRefresher};
+use crate::reload::{StagedAcceleration, StagedTable};
+use crate::retention;
+use crate::status::{DatasetState, RuntimeStatus};
+use datafusion::catalog::schema::{MemorySchemaProvider, SchemaProvider};
+use datafusion::catalog::{CatalogProvider, MemoryCatalogProvider};
+use datafusion::datasource::{TableProvider, ViewTable};
 use datafusion::error::DataFusionError;
 use datafusion::execution::context::SessionConfig;
 use datafusion::execution::{context::SessionContext, options::ParquetReadOptions};
-use datafusion::sql::parser;
+use datafusion::logical_expr::LogicalPlan;
 use datafusion::sql::parser::DFParser;
 use datafusion::sql::sqlparser;
-use datafusion::sql::sqlparser::ast::{self, SetExpr, TableFactor};
 use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
-use futures::StreamExt;
 use snafu::prelude::*;
 use spicepod::component::dataset::Dataset;
-use tokio::sync::RwLock;
-use tokio::time::sleep;
-use tokio::{spawn, task};
+use tokio::sync::{broadcast, RwLock};
+use tokio::task;
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// The catalog and schema of the tables that describe the runtime itself, i.e. `spice.runtime.query_history`.
+pub const SPICE_CATALOG: &str = "spice";
+pub const RUNTIME_SCHEMA: &str = "runtime";
+
+/// The most updates kept for a materialized view that hasn't caught up with them yet.
+const DATASET_UPDATES_CAPACITY: usize = 64;
+
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("Unable to register parquet file {file}: {source}"))]
@@ -84,6 +102,23 @@ pub enum Error {
     ExpectedSqlView,
 
     InvalidObjectStore,
+
+    #[snafu(display("Unable to register the runtime table {name}: {source}"))]
+    UnableToRegisterRuntimeTable {
+        source: DataFusionError,
+        name: String,
+    },
+
+    #[snafu(display("Unable to swap in the new acceleration of {name}: {source}"))]
+    UnableToSwapAcceleration {
+        source: DataFusionError,
+        name: String,
+    },
+
+    #[snafu(display("Unable to create the query runtime environment: {source}"))]
+    UnableToCreateRuntimeEnv {
+        source: DataFusionError,
+    },
 }
 
 type PublisherList = Arc<RwLock<Vec<Arc<Box<dyn DataPublisher>>>>>;
@@ -93,19 +128,138 @@ type DatasetAndPublishers = (Arc<Dataset>, PublisherList);
 pub struct DataFusion {
     pub ctx: Arc<SessionContext>,
     connectors_tasks: HashMap<String, task::JoinHandle<()>>,
+    retention_tasks: HashMap<String, task::JoinHandle<()>>,
+    refreshers: HashMap<String, DatasetRefresher>,
     data_publishers: HashMap<String, DatasetAndPublishers>,
+    access_policy: AccessPolicy,
+    query_limits: QueryLimits,
+    query_cache: Option<Arc<QueryCache>>,
+    query_history: Arc<QueryHistory>,
+    status: Arc<RuntimeStatus>,
+    /// The updates written to accelerations, read by the materialized views.
+    dataset_updates: broadcast::Sender<DatasetUpdate>,
 }
 
 impl DataFusion {
     #[must_use]
     pub fn new() -> Self {
-        let mut df_config = SessionConfig::new().with_information_schema(true);
-        df_config.options_mut().sql_parser.dialect = "PostgreSQL".to_string();
         DataFusion {
-            ctx: Arc::new(SessionContext::new_with_config(df_config)),
+            ctx: Arc::new(SessionContext::new_with_config(Self::session_config())),
+            connectors_tasks: HashMap::new(),
+            retention_tasks: HashMap::new(),
+            refreshers: HashMap::new(),
+            data_publishers: HashMap::new(),
+            access_policy: AccessPolicy::default(),
+            query_limits: QueryLimits::default(),
+            query_cache: None,
+            query_history: Arc::new(QueryHistory::new(Duration::ZERO)),
+            status: Arc::new(RuntimeStatus::new()),
+            dataset_updates: broadcast::channel(DATASET_UPDATES_CAPACITY).0,
+        }
+    }
+
+    /// Creates a `DataFusion` whose queries run under the memory pool and limits of `config`.
+    pub fn new_with_config(config: &QueryConfig) -> Result<Self> {
+        let runtime_env = limits::runtime_env(config).context(UnableToCreateRuntimeEnvSnafu)?;
+        let query_history = Arc::new(QueryHistory::new(config.history_retention));
+        let df = DataFusion {
+            ctx: Arc::new(SessionContext::new_with_config_rt(
+                Self::session_config(),
+                runtime_env,
+            )),
             connectors_tasks: HashMap::new(),
+            retention_tasks: HashMap::new(),
+            refreshers: HashMap::new(),
             data_publishers: HashMap::new(),
+            access_policy: AccessPolicy::default(),
+            query_limits: QueryLimits::new(config),
+            query_cache: config
+                .cache_max_size
+                .map(|max_size| Arc::new(QueryCache::new(max_size))),
+            query_history: Arc::clone(&query_history),
+            status: Arc::new(RuntimeStatus::new()),
+            dataset_updates: broadcast::channel(DATASET_UPDATES_CAPACITY).0,
+        };
+
+        if query_history.is_enabled() {
+            df.register_runtime_table(
+                "query_history",
+                Arc::new(QueryHistoryTable::new(query_history)),
+            )?;
         }
+
+        Ok(df)
+    }
+
+    /// Registers a table describing the runtime in the `spice.runtime` schema.
+    pub fn register_runtime_table(&self, name: &str, table: Arc<dyn TableProvider>) -> Result<()> {
+        let catalog = self.ctx.catalog(SPICE_CATALOG).unwrap_or_else(|| {
+            let catalog: Arc<dyn CatalogProvider> = Arc::new(MemoryCatalogProvider::new());
+            self.ctx
+                .register_catalog(SPICE_CATALOG, Arc::clone(&catalog));
+            catalog
+        });
+        let schema = match catalog.schema(RUNTIME_SCHEMA) {
+            Some(schema) => schema,
+            None => {
+                let schema: Arc<dyn SchemaProvider> = Arc::new(MemorySchemaProvider::new());
+                catalog
+                    .register_schema(RUNTIME_SCHEMA, Arc::clone(&schema))
+                    .context(UnableToRegisterRuntimeTableSnafu { name })?;
+                schema
+            }
+        };
+
+        schema
+            .register_table(name.to_string(), table)
+            .context(UnableToRegisterRuntimeTableSnafu { name })?;
+
+        Ok(())
+    }
+
+    /// The record of the queries that completed recently.
+    #[must_use]
+    pub fn query_history(&self) -> Arc<QueryHistory> {
+        Arc::clone(&self.query_history)
+    }
+
+    /// The load state and recent refreshes of the datasets.
+    #[must_use]
+    pub fn status(&self) -> Arc<RuntimeStatus> {
+        Arc::clone(&self.status)
+    }
+
+    fn session_config() -> SessionConfig {
+        let mut df_config = SessionConfig::new().with_information_schema(true);
+        df_config.options_mut().sql_parser.dialect = "PostgreSQL".to_string();
+        df_config
+    }
+
+    /// The limits queries run under unless a client requests stricter ones.
+    #[must_use]
+    pub fn query_limits(&self) -> QueryLimits {
+        self.query_limits
+    }
+
+    /// The key the results of `plan` are cached under, or `None` if the query cache is disabled or
+    /// the query reads datasets that aren't accelerated.
+    pub fn query_cache_key(
+        &self,
+        plan: &LogicalPlan,
+    ) -> std::result::Result<Option<CacheKey>, DataFusionError> {
+        match &self.query_cache {
+            Some(cache) => cache.key(plan, |table| self.has_publishers(table)),
+            None => Ok(None),
+        }
+    }
+
+    #[must_use]
+    pub fn access_policy(&self) -> &AccessPolicy {
+        &self.access_policy
+    }
+
+    pub fn set_access_policy(&mut self, access_policy: AccessPolicy) {
+        self.access_policy = access_policy;
     }
 
     pub async fn register_parquet(&self, table_name: &str, path: &str) -> Result<()> {
@@ -140,7 +294,17 @@ impl DataFusion {
         dataset: impl Borrow<Dataset>,
         secrets_provider: Arc<RwLock<secrets::SecretsProvider>>,
     ) -> Result<Box<dyn DataPublisher>> {
-        let dataset = dataset.borrow();
+        self.new_accelerated_backend_in(Arc::clone(&self.ctx), dataset.borrow(), secrets_provider)
+            .await
+    }
+
+    /// Creates the acceleration of `dataset` in `ctx` rather than in the context queries run in.
+    async fn new_accelerated_backend_in(
+        &self,
+        ctx: Arc<SessionContext>,
+        dataset: &Dataset,
+        secrets_provider: Arc<RwLock<secrets::SecretsProvider>>,
+    ) -> Result<Box<dyn DataPublisher>> {
         let table_name = dataset.name.to_string();
         let acceleration =
             dataset
@@ -156,17 +320,33 @@ impl DataFusion {
         let secret_key = dataset.engine_secret().unwrap_or_default();
         let backend_secret = secrets_provider.read().await.get_secret(&secret_key).await;
 
-        let data_backend: Box<dyn DataPublisher> =
-            DataBackendBuilder::new(Arc::clone(&self.ctx), table_name)
-                .engine(acceleration.engine())
-                .mode(acceleration.mode())
-                .params(params)
-                .secret(backend_secret)
-                .build()
-                .await
-                .context(DatasetConfigurationSnafu)?;
-
-        Ok(data_backend)
+        let data_backend: Box<dyn DataPublisher> = DataBackendBuilder::new(ctx, table_name)
+            .engine(acceleration.engine())
+            .mode(acceleration.mode())
+            .params(params)
+            .primary_keys(acceleration.primary_key.clone())
+            .secret(backend_secret)
+            .build()
+            .await
+            .context(DatasetConfigurationSnafu)?;
+        let data_backend: Box<dyn DataPublisher> = Box::new(UpdateBroadcastingPublisher::new(
+            data_backend,
+            self.dataset_updates.clone(),
+        ));
+
+        match &self.query_cache {
+            Some(cache) => Ok(Box::new(CacheInvalidatingPublisher::new(
+                data_backend,
+                Arc::clone(cache),
+            ))),
+            None => Ok(data_backend),
+        }
+    }
+
+    /// Creates the data connector of an accelerated view, which reads the datasets queries run against.
+    #[must_use]
+    pub fn materialized_view(&self) -> MaterializedView {
+        MaterializedView::new_with_context(Arc::clone(&self.ctx), self.dataset_updates.clone())
     }
 
     #[must_use]
@@ -180,6 +360,18 @@ impl DataFusion {
         self.data_publishers.contains_key(dataset)
     }
 
+    /// Returns the names of the tables registered in the default catalog and schema.
+    #[must_use]
+    pub fn get_table_names(&self) -> Vec<String> {
+        let state = self.ctx.state();
+        let options = &state.config_options().catalog;
+        self.ctx
+            .catalog(&options.default_catalog)
+            .and_then(|catalog| catalog.schema(&options.default_schema))
+            .map(|schema| schema.table_names())
+            .unwrap_or_default()
+    }
+
     pub async fn get_arrow_schema(&self, dataset: &str) -> Result<arrow::datatypes::Schema> {
         let data_frame = self
             .ctx
@@ -202,28 +394,160 @@ impl DataFusion {
             return TableAlreadyExistsSnafu.fail();
         }
 
-        let task_handle = task::spawn(async move {
-            let dataset = Arc::new(dataset);
-            let mut stream = data_connector.get_data(&dataset);
-            loop {
-                let future_result = stream.next().await;
-                match future_result {
-                    Some(data_update) => {
-                        match publisher.add_data(Arc::clone(&dataset), data_update).await {
-                            Ok(()) => (),
-                            Err(e) => tracing::error!("Error adding data: {e}"),
-                        }
-                    }
-                    None => break,
-                };
-            }
-        });
+        let refresher = DatasetRefresher::new(
+            Arc::new(dataset),
+            Arc::from(data_connector),
+            publisher,
+            Arc::clone(&self.status),
+        );
+        self.status.set_state(&table_name, DatasetState::Refreshing);
+        self.refreshers
+            .insert(table_name.clone(), refresher.clone());
+        let (task_handle, _) = refresher.spawn(Arc::clone(&self.ctx));
 
         self.connectors_tasks.insert(table_name, task_handle);
 
         Ok(())
     }
 
+    /// Replaces the data connector of an accelerated dataset and restarts its refreshes and retention
+    /// with the settings of `dataset`, keeping the accelerated table and its data.
+    pub fn replace_data_connector(
+        &mut self,
+        dataset: Dataset,
+        data_connector: Box<dyn DataConnector>,
+    ) -> refresh::Result<()> {
+        let publisher = Arc::clone(self.dataset_refresher(&dataset.name)?.publisher());
+        let table_name = dataset.name.clone();
+
+        self.attach_retention(dataset.clone(), Arc::clone(&publisher));
+        let refresher = DatasetRefresher::new(
+            Arc::new(dataset),
+            Arc::from(data_connector),
+            publisher,
+            Arc::clone(&self.status),
+        );
+        self.refreshers
+            .insert(table_name.clone(), refresher.clone());
+        let (task_handle, _) = refresher.spawn(Arc::clone(&self.ctx));
+        if let Some(previous_task) = self.connectors_tasks.insert(table_name, task_handle) {
+            previous_task.abort();
+        }
+
+        Ok(())
+    }
+
+    /// Starts loading a new acceleration for `dataset` in the background, in its own `SessionContext`
+    /// so the current acceleration keeps serving queries until `swap_acceleration` is called.
+    pub async fn stage_acceleration(
+        &self,
+        dataset: &Dataset,
+        data_connector: Box<dyn DataConnector>,
+        secrets_provider: Arc<RwLock<secrets::SecretsProvider>>,
+    ) -> Result<StagedAcceleration> {
+        let ctx = Arc::new(SessionContext::new_with_config_rt(
+            self.ctx.copied_config(),
+            self.ctx.runtime_env(),
+        ));
+        let backend = self
+            .new_accelerated_backend_in(Arc::clone(&ctx), dataset, secrets_provider)
+            .await?;
+
+        let refresher = DatasetRefresher::new(
+            Arc::new(dataset.clone()),
+            Arc::from(data_connector),
+            Arc::new(backend),
+            Arc::clone(&self.status),
+        );
+        let (task_handle, first_refresh) = refresher.clone().spawn(Arc::clone(&ctx));
+
+        Ok(StagedAcceleration::new(
+            ctx,
+            refresher,
+            task_handle,
+            first_refresh,
+        ))
+    }
+
+    /// Replaces the accelerated table of a dataset with the one loaded by `staged`.
+    ///
+    /// Queries are planned under a read lock of `DataFusion`, so they see either the old or the new table.
+    pub async fn swap_acceleration(&mut self, mut staged: StagedAcceleration) -> Result<()> {
+        let dataset = Arc::clone(staged.refresher.dataset());
+        let table_name = dataset.name.clone();
+        let schema = staged
+            .ctx
+            .table_provider(table_name.as_str())
+            .await
+            .context(UnableToSwapAccelerationSnafu {
+                name: table_name.clone(),
+            })?
+            .schema();
+
+        if self.ctx.table_exist(table_name.as_str()).unwrap_or(false) {
+            self.ctx.deregister_table(table_name.as_str()).context(
+                UnableToSwapAccelerationSnafu {
+                    name: table_name.clone(),
+                },
+            )?;
+        }
+        self.ctx
+            .register_table(
+                table_name.as_str(),
+                Arc::new(StagedTable::new(
+                    Arc::clone(&staged.ctx),
+                    table_name.clone(),
+                    schema,
+                )),
+            )
+            .context(UnableToSwapAccelerationSnafu {
+                name: table_name.clone(),
+            })?;
+
+        self.attach_retention(
+            dataset.as_ref().clone(),
+            Arc::clone(staged.refresher.publisher()),
+        );
+        self.refreshers
+            .insert(table_name.clone(), staged.refresher.clone());
+        if let Some(task_handle) = staged.task.take() {
+            if let Some(previous_task) = self
+                .connectors_tasks
+                .insert(table_name.clone(), task_handle)
+            {
+                previous_task.abort();
+            }
+        }
+
+        if let Some(cache) = &self.query_cache {
+            cache.invalidate(&table_name);
+        }
+
+        Ok(())
+    }
+
+    /// The refresher of the accelerated dataset `dataset_name`, to refresh it on demand.
+    pub fn dataset_refresher(&self, dataset_name: &str) -> refresh::Result<DatasetRefresher> {
+        self.refreshers
+            .get(dataset_name)
+            .cloned()
+            .context(refresh::DatasetNotAcceleratedSnafu {
+                dataset: dataset_name,
+            })
+    }
+
+    /// Starts evicting rows from the accelerated table of `dataset` according to its retention policy.
+    pub fn attach_retention(&mut self, dataset: Dataset, backend: Arc<Box<dyn DataPublisher>>) {
+        let table_name = dataset.name.clone();
+        let previous_task = match retention::spawn_retention_task(Arc::new(dataset), backend) {
+            Some(task_handle) => self.retention_tasks.insert(table_name, task_handle),
+            None => self.retention_tasks.remove(&table_name),
+        };
+        if let Some(previous_task) = previous_task {
+            previous_task.abort();
+        }
+    }
+
     #[must_use]
     pub fn table_exists(&self, dataset_name: &str) -> bool {
         self.ctx.table_exist(dataset_name).unwrap_or(false)
@@ -247,10 +571,20 @@ impl DataFusion {
             }
         }
 
+        if let Some(retention_task) = self.retention_tasks.remove(dataset_name) {
+            retention_task.abort();
+        }
+
+        self.refreshers.remove(dataset_name);
+
         if self.data_publishers.contains_key(dataset_name) {
             self.data_publishers.remove(dataset_name);
         }
 
+        if let Some(cache) = &self.query_cache {
+            cache.invalidate(dataset_name);
+        }
+
         Ok(())
     }
 
@@ -291,7 +625,10 @@ impl DataFusion {
         }
     }
 
-    pub fn attach_view(&self, dataset: impl Borrow<Dataset>) -> Result<()> {
+    /// Registers the view of `dataset`, planned against the tables it reads when it is attached.
+    ///
+    /// Failing to plan the view is returned rather than recorded, so the runtime retries loading it.
+    pub async fn attach_view(&self, dataset: impl Borrow<Dataset>) -> Result<()> {
         let dataset = dataset.borrow();
         let table_exists = self.ctx.table_exist(dataset.name.as_str()).unwrap_or(false);
         if table_exists {
@@ -301,7 +638,7 @@ impl DataFusion {
         let Some(sql) = dataset.view_sql().context(InvalidSQLViewSnafu)? else {
             return ExpectedSqlViewSnafu.fail();
         };
-        let statements = DFParser::parse_sql_with_dialect(sql.as_str(), &PostgreSqlDialect {})
+        let mut statements = DFParser::parse_sql_with_dialect(sql.as_str(), &PostgreSqlDialect {})
             .context(UnableToParseSqlSnafu)?;
         if statements.len() != 1 {
             return UnableToCreateViewSnafu {
@@ -313,118 +650,36 @@ impl DataFusion {
             }
             .fail();
         }
+        let Some(statement) = statements.pop_front() else {
+            return ExpectedSqlViewSnafu.fail();
+        };
 
-        let ctx = self.ctx.clone();
-        let table_name = dataset.name.clone();
-        spawn(async move {
-            // Tables are currently lazily created (i.e. not created until first data is received) so that we know the table schema.
-            // This means that we can't create a view on top of a table until the first data is received for all dependent tables and therefore
-            // the tables are created. To handle this, wait until all tables are created.
-            let dependent_table_names = DataFusion::get_dependent_table_names(&statements[0]);
-            for dependent_table_name in dependent_table_names {
-                let mut attempts = 0;
-                loop {
-                    if !ctx.table_exist(&dependent_table_name).unwrap_or(false) {
-                        if attempts % 10 == 0 {
-                            tracing::warn!("Dependent table {dependent_table_name} for view {table_name} does not exist, retrying...");
-                        }
-                        attempts += 1;
-                        sleep(Duration::from_secs(1)).await;
-                        continue;
-                    }
-                    break;
-                }
+        // Tables are lazily created once their first data is received, so the runtime attaches a view only
+        // once the datasets it depends on are loaded.
+        let plan = self
+            .ctx
+            .state()
+            .statement_to_plan(statement)
+            .await
+            .map_err(|e| Error::UnableToCreateView {
+                reason: e.to_string(),
+            })?;
+        let view = ViewTable::try_new(plan, Some(sql.to_string())).map_err(|e| {
+            Error::UnableToCreateView {
+                reason: e.to_string(),
             }
+        })?;
+        self.ctx
+            .register_table(dataset.name.as_str(), Arc::new(view))
+            .map_err(|e| Error::UnableToCreateView {
+                reason: e.to_string(),
+            })?;
 
-            let plan = match ctx.state().statement_to_plan(statements[0].clone()).await {
-                Ok(plan) => plan,
-                Err(e) => {
-                    tracing::error!("Failed to create view: {e}");
-                    return;
-                }
-            };
-
-            let view = match ViewTable::try_new(plan, Some(sql.to_string())) {
-                Ok(view) => view,
-                Err(e) => {
-                    tracing::error!("Failed to create view: {e}");
-                    return;
-                }
-            };
-            if let Err(e) = ctx.register_table(table_name.as_str(), Arc::new(view)) {
-                tracing::error!("Failed to create view: {e}");
-            };
-
-            tracing::info!("Created view {table_name}");
-        });
+        tracing::info!("Created view {}", dataset.name);
+        self.status.set_state(&dataset.name, DatasetState::Ready);
 
         Ok(())
     }
-
-    fn get_dependent_table_names(statement: &parser::Statement) -> Vec<String> {
-        let mut table_names = Vec::new();
-        let mut cte_names = HashSet::new();
-
-        if let parser::Statement::Statement(statement) = statement.clone() {
-            if let ast::Statement::Query(statement) = *statement {
-                // Collect names of CTEs
-                if let Some(with) = statement.with {
-                    for table in with.cte_tables {
-                        cte_names.insert(table.alias.name.to_string());
-                        let cte_table_names =
-                            DataFusion::get_dependent_table_names(&parser::Statement::Statement(
-                                Box::new(ast::Statement::Query(table.query)),
-                            ));
-                        // Extend table_names with names found in CTEs if they reference actual tables
-                        table_names.extend(cte_table_names);
-                    }
-                }
-                // Process the main query body
-                if let SetExpr::Select(select_statement) = *statement.body {
-                    for from in select_statement.from {
-                        let mut relations = vec![];
-                        relations.push(from.relation.clone());
-                        for join in from.joins {
-                            relations.push(join.relation.clone());
-                        }
-
-                        for relation in relations {
-                            match relation {
-                                TableFactor::Table {
-                                    name,
-                                    alias: _,
-                                    args: _,
-                                    with_hints: _,
-                                    version: _,
-                                    partitions: _,
-                                } => {
-                                    table_names.push(name.to_string());
-                                }
-                                TableFactor::Derived {
-                                    lateral: _,
-                                    subquery,
-                                    alias: _,
-                                } => {
-                                    table_names.extend(DataFusion::get_dependent_table_names(
-                                        &parser::Statement::Statement(Box::new(
-                                            ast::Statement::Query(subquery),
-                                        )),
-                                    ));
-                                }
-                                _ => (),
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        // Filter out CTEs and temporary views (aliases of subqueries)
-        table_names
-            .into_iter()
-            .filter(|name| !cte_names.contains(name))
-            .collect()
-    }
 }
 
 impl Drop for DataFusion {
@@ -434,6 +689,12 @@ impl Drop for DataFusion {
         }
 
         self.connectors_tasks.clear();
+
+        for task in self.retention_tasks.values() {
+            task.abort();
+        }
+
+        self.retention_tasks.clear();
     }
 }
 
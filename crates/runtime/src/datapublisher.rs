@@ -1,6 +1,7 @@
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use spicepod::component::dataset::Dataset;
 
@@ -9,8 +10,38 @@ use crate::dataupdate::DataUpdate;
 pub type AddDataResult<'a> =
     Pin<Box<dyn Future<Output = Result<(), Box<dyn std::error::Error>>> + Send + 'a>>;
 
+/// Resolves to the number of rows that were deleted.
+pub type DeleteDataResult<'a> =
+    Pin<Box<dyn Future<Output = Result<u64, Box<dyn std::error::Error>>> + Send + 'a>>;
+
+/// Resolves to the number of rows that were inserted, updated or deleted.
+pub type ExecuteDmlResult<'a> = DeleteDataResult<'a>;
+
 pub trait DataPublisher: Send + Sync {
     fn add_data(&self, dataset: Arc<Dataset>, data_update: DataUpdate) -> AddDataResult;
 
+    /// Deletes the rows of the dataset whose `time_column` is older than `cutoff`.
+    ///
+    /// Used to enforce the acceleration retention policy.
+    fn delete_expired_data(&self, _dataset: Arc<Dataset>, _cutoff: SystemTime) -> DeleteDataResult {
+        let name = self.name().to_string();
+        Box::pin(
+            async move { Err(format!("{name} does not support deleting expired data").into()) },
+        )
+    }
+
+    /// Executes an `UPDATE` or `DELETE` statement directly against the accelerated table.
+    fn execute_dml(&self, _dataset: Arc<Dataset>, _sql: String) -> ExecuteDmlResult {
+        let name = self.name().to_string();
+        Box::pin(
+            async move { Err(format!("{name} does not support executing SQL statements").into()) },
+        )
+    }
+
+    /// Whether `execute_dml` is implemented.
+    fn supports_dml(&self) -> bool {
+        false
+    }
+
     fn name(&self) -> &str;
 }
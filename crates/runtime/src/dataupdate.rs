@@ -4,6 +4,16 @@ use arrow::record_batch::RecordBatch;
 pub enum UpdateType {
     Append,
     Overwrite,
+    /// Replaces the rows that have the same values in the `keys` columns as a row of the data, and
+    /// appends the rest.
+    Upsert {
+        keys: Vec<String>,
+    },
+    /// Deletes the rows that have the same values in the `keys` columns as a row of the data, which
+    /// only needs to contain the `keys` columns.
+    Delete {
+        keys: Vec<String>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -12,5 +22,6 @@ pub struct DataUpdate {
     /// The type of update to perform.
     /// If UpdateType::Append, the runtime will append the data to the existing dataset.
     /// If UpdateType::Overwrite, the runtime will overwrite the existing data with the new data.
+    /// If UpdateType::Upsert or UpdateType::Delete, the runtime will replace or delete the rows matching the data by its keys.
     pub update_type: UpdateType,
 }
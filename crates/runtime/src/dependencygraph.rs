@@ -0,0 +1,360 @@
+//! The dependencies between the datasets of an app, from their `dependsOn` and the tables their view SQL
+//! reads, used to load datasets after the datasets they depend on.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    ops::ControlFlow,
+};
+
+use datafusion::sql::sqlparser::{
+    ast::{Ident, ObjectName, Query, Visit, Visitor},
+    dialect::PostgreSqlDialect,
+    parser::Parser,
+};
+use snafu::prelude::*;
+use spicepod::component::dataset::Dataset;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Dataset {dataset} depends on {dependency}, which is not a dataset"))]
+    UnknownDependency { dataset: String, dependency: String },
+
+    #[snafu(display("Datasets depend on each other in a cycle: {}", cycle.join(" -> ")))]
+    DependencyCycle { cycle: Vec<String> },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Default)]
+pub struct DependencyGraph {
+    datasets: BTreeMap<String, Dataset>,
+    /// The datasets each dataset depends on.
+    dependencies: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl DependencyGraph {
+    pub fn new(datasets: &[Dataset]) -> Result<Self> {
+        let names: HashSet<&str> = datasets.iter().map(|ds| ds.name.as_str()).collect();
+
+        let mut dependencies = BTreeMap::new();
+        for ds in datasets {
+            let mut ds_dependencies = BTreeSet::new();
+            for dependency in &ds.depends_on {
+                ensure!(
+                    names.contains(dependency.as_str()),
+                    UnknownDependencySnafu {
+                        dataset: ds.name.clone(),
+                        dependency: dependency.clone(),
+                    }
+                );
+                ds_dependencies.insert(dependency.clone());
+            }
+
+            // Views may also read tables that aren't datasets, i.e. the `spice.runtime` tables.
+            ds_dependencies.extend(
+                view_relations(ds)
+                    .into_iter()
+                    .filter(|relation| relation != &ds.name && names.contains(relation.as_str())),
+            );
+            dependencies.insert(ds.name.clone(), ds_dependencies);
+        }
+
+        let graph = DependencyGraph {
+            datasets: datasets
+                .iter()
+                .map(|ds| (ds.name.clone(), ds.clone()))
+                .collect(),
+            dependencies,
+        };
+        graph.load_order()?;
+
+        Ok(graph)
+    }
+
+    /// The datasets `dataset` depends on directly.
+    #[must_use]
+    pub fn dependencies(&self, dataset: &str) -> Vec<String> {
+        self.dependencies
+            .get(dataset)
+            .map(|dependencies| dependencies.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The datasets that depend on `dataset`, directly or not, in the order they can be loaded in.
+    #[must_use]
+    pub fn dependents(&self, dataset: &str) -> Vec<&Dataset> {
+        let mut dependents = HashSet::new();
+        let mut pending = vec![dataset];
+        while let Some(name) = pending.pop() {
+            for (dependent, dependencies) in &self.dependencies {
+                if dependencies.contains(name) && dependents.insert(dependent.as_str()) {
+                    pending.push(dependent);
+                }
+            }
+        }
+
+        self.load_order()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|ds| dependents.contains(ds.name.as_str()))
+            .collect()
+    }
+
+    /// The datasets in an order where every dataset comes after the datasets it depends on.
+    pub fn load_order(&self) -> Result<Vec<&Dataset>> {
+        let mut order = Vec::with_capacity(self.dependencies.len());
+        let mut loaded = HashSet::new();
+        while order.len() < self.dependencies.len() {
+            let ready: Vec<&String> = self
+                .dependencies
+                .iter()
+                .filter(|(name, dependencies)| {
+                    !loaded.contains(name.as_str())
+                        && dependencies
+                            .iter()
+                            .all(|dependency| loaded.contains(dependency.as_str()))
+                })
+                .map(|(name, _)| name)
+                .collect();
+            if ready.is_empty() {
+                return DependencyCycleSnafu {
+                    cycle: self.find_cycle(&loaded),
+                }
+                .fail();
+            }
+
+            for name in ready {
+                loaded.insert(name.as_str());
+                if let Some(ds) = self.datasets.get(name) {
+                    order.push(ds);
+                }
+            }
+        }
+
+        Ok(order)
+    }
+
+    /// Follows the dependencies of the datasets that couldn't be loaded until one repeats.
+    fn find_cycle(&self, loaded: &HashSet<&str>) -> Vec<String> {
+        let Some(mut current) = self
+            .dependencies
+            .keys()
+            .find(|name| !loaded.contains(name.as_str()))
+        else {
+            return vec![];
+        };
+
+        let mut path: Vec<&String> = vec![];
+        while !path.contains(&current) {
+            path.push(current);
+            let next = self.dependencies.get(current).and_then(|dependencies| {
+                dependencies
+                    .iter()
+                    .find(|dependency| !loaded.contains(dependency.as_str()))
+            });
+            match next {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+
+        let start = path.iter().position(|name| *name == current).unwrap_or(0);
+        let mut cycle: Vec<String> = path[start..].iter().map(|name| (*name).clone()).collect();
+        cycle.push(current.clone());
+        cycle
+    }
+}
+
+/// The names of the tables the view SQL of `ds` reads, including in subqueries, unions and joins.
+///
+/// The relations are read from the SQL rather than from the `TableScan`s of its `LogicalPlan`, because the
+/// graph decides the order datasets are registered in: planning the view resolves every table it reads,
+/// and fails for the ones that aren't registered yet. Names are normalized like the planner resolves them,
+/// so the relations are the tables the plan would scan, except for CTEs, which the collector tracks itself.
+pub(crate) fn view_relations(ds: &Dataset) -> BTreeSet<String> {
+    let Ok(Some(sql)) = ds.view_sql() else {
+        return BTreeSet::new();
+    };
+    let Ok(statements) = Parser::parse_sql(&PostgreSqlDialect {}, &sql) else {
+        return BTreeSet::new();
+    };
+
+    let mut collector = RelationCollector::default();
+    for statement in &statements {
+        let _ = statement.visit(&mut collector);
+    }
+
+    collector.relations
+}
+
+/// Unquoted identifiers are case insensitive, so `Trips` reads the `trips` dataset.
+fn normalize(ident: &Ident) -> String {
+    match ident.quote_style {
+        Some(_) => ident.value.clone(),
+        None => ident.value.to_lowercase(),
+    }
+}
+
+#[derive(Default)]
+struct RelationCollector {
+    relations: BTreeSet<String>,
+    /// The names of the CTEs visible to the query being visited, one set per enclosing query.
+    cte_scopes: Vec<HashSet<String>>,
+}
+
+impl RelationCollector {
+    fn is_cte(&self, relation: &ObjectName) -> bool {
+        match relation.0.as_slice() {
+            [name] => self
+                .cte_scopes
+                .iter()
+                .any(|scope| scope.contains(&normalize(name))),
+            _ => false,
+        }
+    }
+}
+
+impl Visitor for RelationCollector {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<Self::Break> {
+        let mut scope = HashSet::new();
+        if let Some(with) = &query.with {
+            // A CTE only sees the CTEs defined before it, and itself if recursive, so in
+            // `WITH trips AS (SELECT * FROM trips)` the CTE reads the `trips` table.
+            for cte in &with.cte_tables {
+                if with.recursive {
+                    scope.insert(normalize(&cte.alias.name));
+                }
+                let mut cte_scopes = self.cte_scopes.clone();
+                cte_scopes.push(scope.clone());
+                let mut collector = RelationCollector {
+                    relations: BTreeSet::new(),
+                    cte_scopes,
+                };
+                let _ = cte.query.visit(&mut collector);
+                self.relations.extend(collector.relations);
+                scope.insert(normalize(&cte.alias.name));
+            }
+        }
+        // The CTEs are visited again with every CTE in scope, which only finds relations already collected.
+        self.cte_scopes.push(scope);
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_query(&mut self, _query: &Query) -> ControlFlow<Self::Break> {
+        self.cte_scopes.pop();
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_relation(&mut self, relation: &ObjectName) -> ControlFlow<Self::Break> {
+        if self.is_cte(relation) {
+            return ControlFlow::Continue(());
+        }
+
+        // Quoted names may contain dots, i.e. `"eth.blocks"`, so they match the unquoted `eth.blocks`.
+        let name = relation
+            .0
+            .iter()
+            .map(normalize)
+            .collect::<Vec<_>>()
+            .join(".");
+        self.relations.insert(name);
+        ControlFlow::Continue(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spicepod::component::WithDependsOn;
+
+    use super::*;
+
+    fn view(name: &str, sql: &str) -> Dataset {
+        serde_yaml::from_str(&format!("name: {name}\nsql: {sql:?}")).expect("valid dataset")
+    }
+
+    fn names(datasets: &[&Dataset]) -> Vec<String> {
+        datasets.iter().map(|ds| ds.name.clone()).collect()
+    }
+
+    #[test]
+    fn test_view_relations() {
+        let ds = view(
+            "summary",
+            r#"WITH recent AS (SELECT * FROM trips WHERE ts > now() - interval '1 day')
+               SELECT * FROM recent JOIN (zones z JOIN "eth.blocks" b ON z.id = b.id) ON true
+               WHERE recent.id IN (SELECT id FROM riders)
+               UNION ALL SELECT * FROM eth.transactions"#,
+        );
+
+        assert_eq!(
+            view_relations(&ds).into_iter().collect::<Vec<_>>(),
+            vec!["eth.blocks", "eth.transactions", "riders", "trips", "zones"]
+        );
+
+        // CTE names only hide tables within the query that defines them.
+        let ds = view(
+            "summary",
+            r#"SELECT * FROM (WITH zones AS (SELECT * FROM trips) SELECT * FROM zones) t
+               JOIN zones ON true"#,
+        );
+        assert_eq!(
+            view_relations(&ds).into_iter().collect::<Vec<_>>(),
+            vec!["trips", "zones"]
+        );
+
+        let ds = view(
+            "summary",
+            "WITH trips AS (SELECT * FROM trips WHERE fare > 0) SELECT * FROM trips",
+        );
+        assert_eq!(
+            view_relations(&ds).into_iter().collect::<Vec<_>>(),
+            vec!["trips"]
+        );
+
+        let ds = view("summary", r#"SELECT * FROM Trips JOIN "Zones" ON true"#);
+        assert_eq!(
+            view_relations(&ds).into_iter().collect::<Vec<_>>(),
+            vec!["Zones", "trips"]
+        );
+    }
+
+    #[test]
+    fn test_load_order() {
+        let trips = Dataset::new("postgres:trips".to_string(), "trips".to_string());
+        let zones = Dataset::new("postgres:zones".to_string(), "zones".to_string());
+        let summary = view("summary", "SELECT * FROM trips JOIN zones ON true");
+        let report = Dataset::new("spiceai:report".to_string(), "report".to_string())
+            .depends_on(&["summary".to_string()]);
+
+        let graph = DependencyGraph::new(&[report, summary, zones, trips]).expect("valid graph");
+        assert_eq!(
+            names(&graph.load_order().expect("no cycle")),
+            vec!["trips", "zones", "summary", "report"]
+        );
+        assert_eq!(names(&graph.dependents("zones")), vec!["summary", "report"]);
+        assert_eq!(graph.dependencies("summary"), vec!["trips", "zones"]);
+    }
+
+    #[test]
+    fn test_dependency_errors() {
+        let a = view("a", "SELECT * FROM c");
+        let b = view("b", "SELECT * FROM a");
+        let c =
+            Dataset::new("spiceai:c".to_string(), "c".to_string()).depends_on(&["b".to_string()]);
+        let d = Dataset::new("spiceai:d".to_string(), "d".to_string());
+
+        let Err(Error::DependencyCycle { cycle }) = DependencyGraph::new(&[a, b, c, d.clone()])
+        else {
+            panic!("expected a dependency cycle");
+        };
+        assert_eq!(cycle, vec!["a", "c", "b", "a"]);
+
+        let d = d.depends_on(&["missing".to_string()]);
+        assert!(matches!(
+            DependencyGraph::new(&[d]),
+            Err(Error::UnknownDependency { .. })
+        ));
+    }
+}
@@ -1,6 +1,12 @@
+use crate::auth::{Authenticator, Principal};
 use crate::datafusion::DataFusion;
 use crate::dataupdate::DataUpdate;
 use crate::measure_scope_ms;
+use crate::query::{
+    cache, limits, Protocol, QueryError, QueryErrorCode, QueryLimits, QueryTracker,
+};
+use crate::tls::TlsIdentity;
+use app::App;
 use arrow::array::RecordBatch;
 use arrow::datatypes::Schema;
 use arrow::ipc::writer::{DictionaryTracker, IpcDataGenerator};
@@ -10,7 +16,8 @@ use arrow_ipc::writer::IpcWriteOptions;
 use bytes::Bytes;
 use datafusion::error::DataFusionError;
 use datafusion::execution::SendableRecordBatchStream;
-use datafusion::sql::sqlparser::parser::ParserError;
+use datafusion::logical_expr::LogicalPlan;
+use flightsql::prepared_statement_query::PreparedStatement;
 use futures::stream::{self, BoxStream, StreamExt};
 use futures::{Stream, TryStreamExt};
 use snafu::prelude::*;
@@ -18,6 +25,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::broadcast::Sender;
 use tokio::sync::RwLock;
+use tonic::metadata::MetadataMap;
 use tonic::transport::Server;
 use tonic::{Request, Response, Status, Streaming};
 
@@ -27,7 +35,9 @@ mod do_get;
 mod do_put;
 mod flightsql;
 mod get_flight_info;
+mod get_schema;
 mod handshake;
+mod list_flights;
 
 use arrow_flight::{
     flight_service_server::{FlightService, FlightServiceServer},
@@ -38,6 +48,9 @@ use arrow_flight::{
 pub struct Service {
     datafusion: Arc<RwLock<DataFusion>>,
     channel_map: Arc<RwLock<HashMap<String, Arc<Sender<DataUpdate>>>>>,
+    prepared_statements: Arc<RwLock<HashMap<Bytes, PreparedStatement>>>,
+    authenticator: Option<Arc<Authenticator>>,
+    app: Arc<RwLock<Option<App>>>,
 }
 
 #[tonic::async_trait]
@@ -52,68 +65,75 @@ impl FlightService for Service {
 
     async fn handshake(
         &self,
-        _request: Request<Streaming<HandshakeRequest>>,
+        request: Request<Streaming<HandshakeRequest>>,
     ) -> Result<Response<Self::HandshakeStream>, Status> {
         metrics::counter!("flight_handshake_requests").increment(1);
-        handshake::handle()
+        handshake::handle(self, request).await
     }
 
     async fn list_flights(
         &self,
-        _request: Request<Criteria>,
+        mut request: Request<Criteria>,
     ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        measure_scope_ms!("flight_list_flights_request_duration_ms");
         metrics::counter!("flight_list_flights_requests").increment(1);
-        tracing::trace!("list_flights - unimplemented");
-        Err(Status::unimplemented("Not yet implemented"))
+        self.authenticate(&mut request)?;
+        list_flights::handle(self, request).await
     }
 
     async fn get_flight_info(
         &self,
-        request: Request<FlightDescriptor>,
+        mut request: Request<FlightDescriptor>,
     ) -> Result<Response<FlightInfo>, Status> {
         measure_scope_ms!("flight_get_flight_info_request_duration_ms");
         metrics::counter!("flight_get_flight_info_requests").increment(1);
+        self.authenticate(&mut request)?;
         get_flight_info::handle(self, request).await
     }
 
     async fn get_schema(
         &self,
-        _request: Request<FlightDescriptor>,
+        mut request: Request<FlightDescriptor>,
     ) -> Result<Response<SchemaResult>, Status> {
+        measure_scope_ms!("flight_get_schema_request_duration_ms");
         metrics::counter!("flight_get_schema_requests").increment(1);
-        tracing::trace!("get_schema - unimplemented");
-        Err(Status::unimplemented("Not yet implemented"))
+        self.authenticate(&mut request)?;
+        get_schema::handle(self, request).await
     }
 
     async fn do_get(
         &self,
-        request: Request<Ticket>,
+        mut request: Request<Ticket>,
     ) -> Result<Response<Self::DoGetStream>, Status> {
         metrics::counter!("flight_do_get_requests").increment(1);
+        self.authenticate(&mut request)?;
         do_get::handle(self, request).await
     }
 
     async fn do_put(
         &self,
-        request: Request<Streaming<FlightData>>,
+        mut request: Request<Streaming<FlightData>>,
     ) -> Result<Response<Self::DoPutStream>, Status> {
         metrics::counter!("flight_do_put_requests").increment(1);
+        self.authenticate(&mut request)?;
         do_put::handle(self, request).await
     }
 
     async fn do_exchange(
         &self,
-        request: Request<Streaming<FlightData>>,
+        mut request: Request<Streaming<FlightData>>,
     ) -> Result<Response<Self::DoExchangeStream>, Status> {
         metrics::counter!("flight_do_exchange_requests").increment(1);
+        self.authenticate(&mut request)?;
         do_exchange::handle(self, request).await
     }
 
     async fn do_action(
         &self,
-        request: Request<Action>,
+        mut request: Request<Action>,
     ) -> Result<Response<Self::DoActionStream>, Status> {
         metrics::counter!("flight_do_action_requests").increment(1);
+        self.authenticate(&mut request)?;
         actions::do_action(self, request).await
     }
 
@@ -127,18 +147,49 @@ impl FlightService for Service {
 }
 
 impl Service {
+    /// Validates the `authorization` header of the request if authentication is enabled,
+    /// and adds the authenticated `Principal` to the request extensions.
+    fn authenticate<T>(&self, request: &mut Request<T>) -> Result<(), Status> {
+        let Some(authenticator) = &self.authenticator else {
+            return Ok(());
+        };
+
+        let principal = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| authenticator.authenticate_header(value));
+
+        match principal {
+            Some(principal) => {
+                request.extensions_mut().insert(principal);
+                Ok(())
+            }
+            None => {
+                metrics::counter!("flight_unauthenticated_requests").increment(1);
+                Err(Status::unauthenticated("Invalid or missing credentials"))
+            }
+        }
+    }
+
+    /// Returns the schema of the results of `sql` without executing it.
     async fn get_arrow_schema(
         datafusion: Arc<RwLock<DataFusion>>,
         sql: String,
+        principal: Option<&Principal>,
     ) -> Result<Schema, Status> {
-        let df = datafusion
-            .read()
-            .await
+        let datafusion = datafusion.read().await;
+        let plan = datafusion
             .ctx
-            .sql(&sql)
+            .state()
+            .create_logical_plan(&sql)
             .await
             .map_err(to_tonic_err)?;
-        Ok(df.schema().into())
+        datafusion
+            .access_policy()
+            .check_plan(principal, &plan)
+            .map_err(|e| Status::permission_denied(e.to_string()))?;
+        Ok(plan.schema().as_ref().into())
     }
 
     fn serialize_schema(schema: &Schema) -> Result<Bytes, Status> {
@@ -153,21 +204,74 @@ impl Service {
     async fn sql_to_flight_stream(
         datafusion: Arc<RwLock<DataFusion>>,
         sql: String,
+        query_request: &QueryRequest,
     ) -> Result<BoxStream<'static, Result<FlightData, Status>>, Status> {
-        let df = datafusion
-            .read()
-            .await
-            .ctx
-            .sql(&sql)
-            .await
-            .map_err(handle_datafusion_error)?;
+        let (plan, tracker) = {
+            let df = datafusion.read().await;
+            let tracker = query_request.track(&df, &sql);
+            // Only plan the query here; `plan_to_flight_stream` checks access before executing it.
+            match df.ctx.state().create_logical_plan(&sql).await {
+                Ok(plan) => (plan, tracker),
+                Err(e) => {
+                    tracker.fail(&e);
+                    return Err(handle_datafusion_error(e));
+                }
+            }
+        };
+
+        Self::plan_to_flight_stream(datafusion, plan, query_request, tracker).await
+    }
+
+    async fn plan_to_flight_stream(
+        datafusion: Arc<RwLock<DataFusion>>,
+        plan: LogicalPlan,
+        query_request: &QueryRequest,
+        tracker: QueryTracker,
+    ) -> Result<BoxStream<'static, Result<FlightData, Status>>, Status> {
+        let (df, limits, cache_key) = {
+            let datafusion = datafusion.read().await;
+            if let Err(e) = datafusion
+                .access_policy()
+                .check_plan(query_request.principal.as_ref(), &plan)
+            {
+                tracker.fail(&e);
+                return Err(Status::permission_denied(e.to_string()));
+            }
+            let df = match datafusion.ctx.execute_logical_plan(plan).await {
+                Ok(df) => df,
+                Err(e) => {
+                    tracker.fail(&e);
+                    return Err(handle_datafusion_error(e));
+                }
+            };
+            let cache_key = match datafusion.query_cache_key(df.logical_plan()) {
+                Ok(cache_key) => cache_key,
+                Err(e) => {
+                    tracker.fail(&e);
+                    return Err(handle_datafusion_error(e));
+                }
+            };
+            (
+                df,
+                datafusion
+                    .query_limits()
+                    .with_overrides(query_request.limit_overrides),
+                cache_key,
+            )
+        };
         let schema = df.schema().clone().into();
         let options = datafusion::arrow::ipc::writer::IpcWriteOptions::default();
         let schema_as_ipc = SchemaAsIpc::new(&schema, &options);
         let schema_flight_data = FlightData::from(schema_as_ipc);
 
         let batches_stream: SendableRecordBatchStream =
-            df.execute_stream().await.map_err(to_tonic_err)?;
+            match cache::execute_stream(df, cache_key).await {
+                Ok(batches_stream) => tracker.track_stream(limits.limit_stream(batches_stream)),
+                Err(e) => {
+                    tracker.fail(&e);
+                    return Err(handle_datafusion_error(e));
+                }
+            };
 
         let batches_stream = batches_stream
             .then(move |batch_result| {
@@ -187,7 +291,7 @@ impl Service {
                             flights.push(flight_batch.into());
                             Ok(flights)
                         }
-                        Err(e) => Err(Status::internal(e.to_string())),
+                        Err(e) => Err(handle_datafusion_error(e)),
                     }
                 }
             })
@@ -224,25 +328,80 @@ where
 
 #[allow(clippy::needless_pass_by_value)]
 fn handle_datafusion_error(e: DataFusionError) -> Status {
-    match e {
-        DataFusionError::Plan(err_msg) | DataFusionError::Execution(err_msg) => {
-            Status::invalid_argument(err_msg)
-        }
-        DataFusionError::SQL(sql_err, _) => match sql_err {
-            ParserError::RecursionLimitExceeded => {
-                Status::invalid_argument("Recursion limit exceeded")
-            }
-            ParserError::ParserError(err_msg) | ParserError::TokenizerError(err_msg) => {
-                Status::invalid_argument(err_msg)
-            }
-        },
-        DataFusionError::SchemaError(schema_err, _) => {
-            Status::invalid_argument(format!("{schema_err}"))
-        }
-        _ => to_tonic_err(e),
+    let error = QueryError::from(&e);
+    let code = if error.is_client_error() {
+        tonic::Code::InvalidArgument
+    } else if error.code == QueryErrorCode::ResourceExhausted {
+        tonic::Code::ResourceExhausted
+    } else if error.code == QueryErrorCode::Timeout {
+        tonic::Code::DeadlineExceeded
+    } else {
+        tonic::Code::Internal
+    };
+
+    // The JSON error is attached as the details so clients get the same diagnostics as over HTTP
+    Status::with_details(code, error.message.clone(), error.to_json().into())
+}
+
+/// Who sent a Flight query, and the limits they requested for it.
+pub(crate) struct QueryRequest {
+    principal: Option<Principal>,
+    user_agent: Option<String>,
+    limit_overrides: QueryLimits,
+}
+
+impl QueryRequest {
+    fn from_request<T>(request: &Request<T>) -> Result<Self, Status> {
+        Ok(QueryRequest {
+            principal: request.extensions().get::<Principal>().cloned(),
+            user_agent: request
+                .metadata()
+                .get("user-agent")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            limit_overrides: query_limit_overrides(request.metadata())?,
+        })
+    }
+
+    /// Starts recording `sql` in the query history.
+    fn track(&self, datafusion: &DataFusion, sql: &str) -> QueryTracker {
+        datafusion.query_history().start(
+            sql,
+            Protocol::Flight,
+            self.principal
+                .as_ref()
+                .map(|principal| principal.name.as_str()),
+            self.user_agent.as_deref(),
+        )
     }
 }
 
+/// The stricter limits a client requested for its query with the `x-spice-query-timeout` and
+/// `x-spice-max-result-size` headers.
+fn query_limit_overrides(metadata: &MetadataMap) -> Result<QueryLimits, Status> {
+    let header = |name: &str| {
+        metadata
+            .get(name)
+            .map(|value| {
+                value
+                    .to_str()
+                    .map_err(|_| Status::invalid_argument(format!("Invalid {name} header")))
+            })
+            .transpose()
+    };
+
+    Ok(QueryLimits {
+        timeout: header("x-spice-query-timeout")?
+            .map(limits::parse_duration)
+            .transpose()
+            .map_err(Status::invalid_argument)?,
+        max_result_size: header("x-spice-max-result-size")?
+            .map(limits::parse_byte_size)
+            .transpose()
+            .map_err(Status::invalid_argument)?,
+    })
+}
+
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("Unable to register parquet file: {source}"))]
@@ -255,21 +414,41 @@ pub enum Error {
 
     #[snafu(display("Unable to start Flight server: {source}"))]
     UnableToStartFlightServer { source: tonic::transport::Error },
+
+    #[snafu(display("Unable to configure TLS for the Flight server: {source}"))]
+    UnableToConfigureTls { source: tonic::transport::Error },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
-pub async fn start(bind_address: std::net::SocketAddr, df: Arc<RwLock<DataFusion>>) -> Result<()> {
+pub async fn start(
+    bind_address: std::net::SocketAddr,
+    app: Arc<RwLock<Option<App>>>,
+    df: Arc<RwLock<DataFusion>>,
+    authenticator: Option<Arc<Authenticator>>,
+    tls: Option<TlsIdentity>,
+) -> Result<()> {
     let service = Service {
         datafusion: df.clone(),
         channel_map: Arc::new(RwLock::new(HashMap::new())),
+        prepared_statements: Arc::new(RwLock::new(HashMap::new())),
+        authenticator,
+        app,
     };
     let svc = FlightServiceServer::new(service);
 
-    tracing::info!("Spice Runtime Flight listening on {bind_address}");
+    let mut server = Server::builder();
+    if let Some(tls) = &tls {
+        server = server
+            .tls_config(tls.tonic_config())
+            .context(UnableToConfigureTlsSnafu)?;
+        tracing::info!("Spice Runtime Flight listening on {bind_address} (TLS)");
+    } else {
+        tracing::info!("Spice Runtime Flight listening on {bind_address}");
+    }
     metrics::counter!("spiced_runtime_flight_server_start").increment(1);
 
-    Server::builder()
+    server
         .add_service(svc)
         .serve(bind_address)
         .await
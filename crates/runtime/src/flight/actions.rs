@@ -1,10 +1,13 @@
 use std::fmt::{self, Display, Formatter};
 
 use prost::Message;
+use serde::Deserialize;
 use tonic::{Request, Response, Status};
 
 use crate::{
+    auth::Principal,
     flight::{flightsql::prepared_statement_query, to_tonic_err, Service},
+    refresh::{RefreshRequest, RefreshResponse},
     timing::{TimeMeasurement, TimedStream},
 };
 
@@ -17,6 +20,7 @@ use arrow_flight::{
 enum ActionType {
     CreatePreparedStatement,
     ClosePreparedStatement,
+    RefreshDataset,
     Unknown,
 }
 
@@ -25,6 +29,7 @@ impl ActionType {
         match s {
             "CreatePreparedStatement" => ActionType::CreatePreparedStatement,
             "ClosePreparedStatement" => ActionType::ClosePreparedStatement,
+            "RefreshDataset" => ActionType::RefreshDataset,
             _ => ActionType::Unknown,
         }
     }
@@ -33,6 +38,7 @@ impl ActionType {
         match self {
             ActionType::CreatePreparedStatement => "CreatePreparedStatement",
             ActionType::ClosePreparedStatement => "ClosePreparedStatement",
+            ActionType::RefreshDataset => "RefreshDataset",
             ActionType::Unknown => "Unknown",
         }
     }
@@ -60,9 +66,17 @@ pub(crate) fn list() -> Response<<Service as FlightService>::ListActionsStream>
             Response Message: N/A"
             .into(),
     };
+    let refresh_dataset_action_type = FlightActionType {
+        r#type: ActionType::RefreshDataset.to_string(),
+        description: "Refreshes the acceleration of a dataset from its data connector.\n
+            Request Message: JSON {\"dataset\": string, \"filter\": optional SQL predicate}\n
+            Response Message: JSON {\"dataset\", \"status\", \"duration_ms\", \"rows\", \"error\"}"
+            .into(),
+    };
     let actions: Vec<Result<FlightActionType, Status>> = vec![
         Ok(create_prepared_statement_action_type),
         Ok(close_prepared_statement_action_type),
+        Ok(refresh_dataset_action_type),
     ];
 
     let output = TimedStream::new(futures::stream::iter(actions), || {
@@ -95,17 +109,44 @@ pub(crate) async fn do_action(
                         "Unable to unpack ActionCreatePreparedStatementRequest.",
                     )
                 })?;
-            let stmt =
-                prepared_statement_query::do_action_create_prepared_statement(flight_svc, cmd)
-                    .await?;
+            let principal = request.extensions().get::<Principal>().cloned();
+            let stmt = prepared_statement_query::do_action_create_prepared_statement(
+                flight_svc,
+                principal.as_ref(),
+                cmd,
+            )
+            .await?;
             futures::stream::iter(vec![Ok(arrow_flight::Result {
                 body: stmt.as_any().encode_to_vec().into(),
             })])
         }
         ActionType::ClosePreparedStatement => {
             tracing::trace!("do_action: ClosePreparedStatement");
+            let any = Any::decode(&*request.get_ref().body).map_err(to_tonic_err)?;
+
+            let cmd: sql::ActionClosePreparedStatementRequest =
+                any.unpack().map_err(to_tonic_err)?.ok_or_else(|| {
+                    Status::invalid_argument(
+                        "Unable to unpack ActionClosePreparedStatementRequest.",
+                    )
+                })?;
+            let principal = request.extensions().get::<Principal>().cloned();
+            prepared_statement_query::do_action_close_prepared_statement(
+                flight_svc,
+                principal.as_ref(),
+                cmd,
+            )
+            .await?;
             futures::stream::iter(vec![Ok(arrow_flight::Result::default())])
         }
+        ActionType::RefreshDataset => {
+            tracing::trace!("do_action: RefreshDataset");
+            let principal = request.extensions().get::<Principal>().cloned();
+            let action: RefreshDatasetAction = serde_json::from_slice(&request.get_ref().body)
+                .map_err(|e| Status::invalid_argument(e.to_string()))?;
+            let body = refresh_dataset(flight_svc, principal.as_ref(), action).await?;
+            futures::stream::iter(vec![Ok(arrow_flight::Result { body: body.into() })])
+        }
         ActionType::Unknown => return Err(Status::invalid_argument("Unknown action type")),
     };
 
@@ -114,3 +155,43 @@ pub(crate) async fn do_action(
         move || start,
     ))))
 }
+
+#[derive(Deserialize)]
+struct RefreshDatasetAction {
+    dataset: String,
+
+    #[serde(flatten)]
+    request: RefreshRequest,
+}
+
+/// Refreshes a dataset and returns the outcome as JSON, attached as the details of the error if the
+/// refresh failed.
+async fn refresh_dataset(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    action: RefreshDatasetAction,
+) -> Result<Vec<u8>, Status> {
+    let refresher = {
+        let df = flight_svc.datafusion.read().await;
+        df.access_policy()
+            .check_write(principal, &action.dataset)
+            .map_err(|e| Status::permission_denied(e.to_string()))?;
+        df.dataset_refresher(&action.dataset)
+            .map_err(|e| Status::not_found(e.to_string()))?
+    };
+
+    let record = refresher
+        .refresh(action.request.filter.as_deref())
+        .await
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+    let body = serde_json::to_vec(&RefreshResponse::from(&record)).map_err(to_tonic_err)?;
+
+    match record.error {
+        Some(error) => Err(Status::with_details(
+            tonic::Code::Internal,
+            error,
+            body.into(),
+        )),
+        None => Ok(body),
+    }
+}
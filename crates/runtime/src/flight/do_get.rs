@@ -10,23 +10,25 @@ use tonic::{Request, Response, Status};
 
 use crate::timing::{TimeMeasurement, TimedStream};
 
-use super::{flightsql, to_tonic_err, Service};
+use super::{flightsql, to_tonic_err, QueryRequest, Service};
 
 pub(crate) async fn handle(
     flight_svc: &Service,
     request: Request<Ticket>,
 ) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
+    let query_request = QueryRequest::from_request(&request)?;
+
     let msg: Any = match Message::decode(&*request.get_ref().ticket) {
         Ok(msg) => msg,
-        Err(_) => return do_get_simple(flight_svc, request).await,
+        Err(_) => return do_get_simple(flight_svc, &query_request, request).await,
     };
 
     match Command::try_from(msg).map_err(to_tonic_err)? {
         Command::CommandStatementQuery(command) => {
-            flightsql::statement_query::do_get(flight_svc, command).await
+            flightsql::statement_query::do_get(flight_svc, &query_request, command).await
         }
         Command::CommandPreparedStatementQuery(command) => {
-            flightsql::prepared_statement_query::do_get(flight_svc, command).await
+            flightsql::prepared_statement_query::do_get(flight_svc, &query_request, command).await
         }
         Command::CommandGetCatalogs(command) => {
             flightsql::get_catalogs::do_get(flight_svc, command).await
@@ -37,13 +39,28 @@ pub(crate) async fn handle(
         Command::CommandGetTables(command) => {
             flightsql::get_tables::do_get(flight_svc, command).await
         }
+        Command::CommandGetTableTypes(command) => flightsql::get_table_types::do_get(&command),
+        Command::CommandGetPrimaryKeys(command) => {
+            flightsql::get_primary_keys::do_get(flight_svc, command).await
+        }
+        Command::CommandGetExportedKeys(_) => {
+            flightsql::get_foreign_keys::do_get("CommandGetExportedKeys")
+        }
+        Command::CommandGetImportedKeys(_) => {
+            flightsql::get_foreign_keys::do_get("CommandGetImportedKeys")
+        }
+        Command::CommandGetCrossReference(_) => {
+            flightsql::get_foreign_keys::do_get("CommandGetCrossReference")
+        }
         Command::CommandGetSqlInfo(command) => flightsql::get_sql_info::do_get(command),
+        Command::CommandGetXdbcTypeInfo(command) => flightsql::get_xdbc_type_info::do_get(command),
         _ => Err(Status::unimplemented("Not yet implemented")),
     }
 }
 
 async fn do_get_simple(
     flight_svc: &Service,
+    query_request: &QueryRequest,
     request: Request<Ticket>,
 ) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
     let datafusion = Arc::clone(&flight_svc.datafusion);
@@ -52,7 +69,8 @@ async fn do_get_simple(
     match std::str::from_utf8(&ticket.ticket) {
         Ok(sql) => {
             let start = TimeMeasurement::new("flight_do_get_simple_duration_ms", vec![]);
-            let output = Service::sql_to_flight_stream(datafusion, sql.to_owned()).await?;
+            let output =
+                Service::sql_to_flight_stream(datafusion, sql.to_owned(), query_request).await?;
 
             let timed_output = TimedStream::new(output, move || start);
 
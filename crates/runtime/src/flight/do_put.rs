@@ -1,17 +1,26 @@
 use std::{collections::HashMap, sync::Arc};
 
-use arrow_flight::{flight_service_server::FlightService, FlightData, PutResult};
+use arrow::array::RecordBatch;
+
+use arrow_flight::{
+    flight_service_server::FlightService,
+    sql::{Any, Command},
+    FlightData, PutResult,
+};
 use arrow_ipc::convert::try_schema_from_flatbuffer_bytes;
 use futures::stream;
+use prost::Message;
+use serde::Deserialize;
 use tokio::sync::{broadcast::Sender, RwLock};
 use tonic::{Request, Response, Status, Streaming};
 
 use crate::{
+    auth::Principal,
     dataupdate::{DataUpdate, UpdateType},
     timing::{TimeMeasurement, TimedStream},
 };
 
-use super::Service;
+use super::{flightsql, Service};
 
 async fn get_sender_channel(
     channel_map: Arc<RwLock<HashMap<String, Arc<Sender<DataUpdate>>>>>,
@@ -28,11 +37,95 @@ async fn get_sender_channel(
     }
 }
 
+fn decode_command(cmd: &[u8]) -> Option<Command> {
+    if cmd.is_empty() {
+        return None;
+    }
+    let any = Any::decode(cmd).ok()?;
+    Command::try_from(any).ok()
+}
+
+/// The `app_metadata` of a `FlightData` message, which sets how its batch is written into the dataset,
+/// i.e. `{"update_type": "upsert", "keys": ["id"]}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "update_type", rename_all = "lowercase", deny_unknown_fields)]
+enum PutMetadata {
+    Append,
+    Overwrite,
+    Upsert { keys: Vec<String> },
+    Delete { keys: Vec<String> },
+}
+
+/// Reads the update type from the `app_metadata` of a message, or returns `default` if it has none.
+fn update_type_from_metadata(
+    app_metadata: &[u8],
+    default: &UpdateType,
+) -> Result<UpdateType, Status> {
+    if app_metadata.is_empty() {
+        return Ok(default.clone());
+    }
+
+    let metadata: PutMetadata = serde_json::from_slice(app_metadata)
+        .map_err(|e| Status::invalid_argument(format!("Invalid app_metadata: {e}")))?;
+    match metadata {
+        PutMetadata::Append => Ok(UpdateType::Append),
+        PutMetadata::Overwrite => Ok(UpdateType::Overwrite),
+        PutMetadata::Upsert { keys } | PutMetadata::Delete { keys } if keys.is_empty() => Err(
+            Status::invalid_argument("Upserts and deletes need at least one key column"),
+        ),
+        PutMetadata::Upsert { keys } => Ok(UpdateType::Upsert { keys }),
+        PutMetadata::Delete { keys } => Ok(UpdateType::Delete { keys }),
+    }
+}
+
+/// Publishes the batches as an append to the dataset, returning the number of rows published.
+pub(crate) async fn publish_append(
+    flight_svc: &Service,
+    dataset_name: &str,
+    batches: Vec<RecordBatch>,
+) -> Result<u64, Status> {
+    let (dataset, data_publishers) = {
+        let df = flight_svc.datafusion.read().await;
+        let Some((dataset, data_publishers)) = df.get_publishers(dataset_name) else {
+            return Err(Status::invalid_argument(format!(
+                "No publishers registered for path: {dataset_name}",
+            )));
+        };
+        (Arc::clone(dataset), Arc::clone(data_publishers))
+    };
+
+    let record_count = batches.iter().map(|batch| batch.num_rows() as u64).sum();
+    let data_update = DataUpdate {
+        data: batches,
+        update_type: UpdateType::Append,
+    };
+
+    if let Some(channel) = get_sender_channel(
+        Arc::clone(&flight_svc.channel_map),
+        dataset_name.to_string(),
+    )
+    .await
+    {
+        let _ = channel.send(data_update.clone());
+    };
+
+    let data_publishers = data_publishers.read().await;
+    for publisher in data_publishers.iter() {
+        publisher
+            .add_data(Arc::clone(&dataset), data_update.clone())
+            .await
+            .map_err(|e| Status::internal(format!("Failed to add data: {e}")))?;
+    }
+
+    Ok(record_count)
+}
+
 pub(crate) async fn handle(
     flight_svc: &Service,
     request: Request<Streaming<FlightData>>,
 ) -> Result<Response<<Service as FlightService>::DoPutStream>, Status> {
     let mut duration_metric = TimeMeasurement::new("flight_do_put_duration_ms", vec![]);
+    let principal = request.extensions().get::<Principal>().cloned();
     let mut streaming_flight = request.into_inner();
 
     let Ok(Some(message)) = streaming_flight.message().await else {
@@ -41,6 +134,37 @@ pub(crate) async fn handle(
     let Some(fd) = &message.flight_descriptor else {
         return Err(Status::invalid_argument("No flight descriptor provided"));
     };
+    match decode_command(&fd.cmd) {
+        Some(Command::CommandPreparedStatementQuery(command)) => {
+            return flightsql::prepared_statement_query::do_put(
+                flight_svc,
+                principal.as_ref(),
+                command,
+                message,
+                streaming_flight,
+            )
+            .await;
+        }
+        Some(Command::CommandStatementUpdate(command)) => {
+            return flightsql::statement_update::do_put(flight_svc, principal.as_ref(), command)
+                .await;
+        }
+        Some(Command::Unknown(any)) => {
+            if let Some(command) =
+                flightsql::statement_ingest::CommandStatementIngest::try_from_any(&any)
+            {
+                return flightsql::statement_ingest::do_put(
+                    flight_svc,
+                    principal.as_ref(),
+                    command,
+                    message,
+                    streaming_flight,
+                )
+                .await;
+            }
+        }
+        _ => {}
+    }
     if fd.path.is_empty() {
         return Err(Status::invalid_argument("No path provided"));
     };
@@ -51,6 +175,10 @@ pub(crate) async fn handle(
 
     let df = flight_svc.datafusion.read().await;
 
+    df.access_policy()
+        .check_write(principal.as_ref(), &path)
+        .map_err(|e| Status::permission_denied(e.to_string()))?;
+
     let Some(publishers) = df.get_publishers(&path) else {
         return Err(Status::invalid_argument(format!(
             "No publishers registered for path: {path}",
@@ -59,6 +187,10 @@ pub(crate) async fn handle(
     let dataset = Arc::clone(&publishers.0);
     let data_publishers = Arc::clone(&publishers.1);
 
+    // The metadata of the first message sets how every batch is written, unless a batch has its own.
+    let default_update_type =
+        update_type_from_metadata(&message.app_metadata, &UpdateType::Append)?;
+
     let schema = try_schema_from_flatbuffer_bytes(&message.data_header)
         .map_err(|e| Status::internal(format!("Failed to get schema from data header: {e}")))?;
     let schema = Arc::new(schema);
@@ -86,6 +218,7 @@ pub(crate) async fn handle(
         let data_publishers = Arc::clone(&data_publishers);
         let path = path.clone();
         let channel_map = Arc::clone(&channel_map);
+        let default_update_type = default_update_type.clone();
         async move {
             match flight.message().await {
                 Ok(Some(message)) => {
@@ -102,9 +235,16 @@ pub(crate) async fn handle(
                     };
                     tracing::trace!("Received batch with {} rows", new_batch.num_rows());
 
+                    let update_type = match update_type_from_metadata(
+                        &message.app_metadata,
+                        &default_update_type,
+                    ) {
+                        Ok(update_type) => update_type,
+                        Err(err) => return Some((Err(err), flight)),
+                    };
                     let data_update = DataUpdate {
                         data: vec![new_batch],
-                        update_type: UpdateType::Append,
+                        update_type,
                     };
 
                     if let Some(channel) = get_sender_channel(channel_map, path).await {
@@ -140,3 +280,46 @@ pub(crate) async fn handle(
 
     Ok(Response::new(Box::pin(timed_stream)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_type_from_metadata() {
+        let default = UpdateType::Append;
+        assert_eq!(
+            update_type_from_metadata(b"", &default).expect("valid metadata"),
+            UpdateType::Append
+        );
+        assert_eq!(
+            update_type_from_metadata(br#"{"update_type": "overwrite"}"#, &default)
+                .expect("valid metadata"),
+            UpdateType::Overwrite
+        );
+        assert_eq!(
+            update_type_from_metadata(br#"{"update_type": "upsert", "keys": ["id"]}"#, &default)
+                .expect("valid metadata"),
+            UpdateType::Upsert {
+                keys: vec!["id".to_string()]
+            }
+        );
+        assert_eq!(
+            update_type_from_metadata(
+                br#"{"update_type": "delete", "keys": ["a", "b"]}"#,
+                &default
+            )
+            .expect("valid metadata"),
+            UpdateType::Delete {
+                keys: vec!["a".to_string(), "b".to_string()]
+            }
+        );
+
+        assert!(
+            update_type_from_metadata(br#"{"update_type": "delete", "keys": []}"#, &default)
+                .is_err()
+        );
+        assert!(update_type_from_metadata(br#"{"update_type": "merge"}"#, &default).is_err());
+        assert!(update_type_from_metadata(b"not json", &default).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+//! Datasets don't declare foreign keys, so the exported keys, imported keys and cross reference
+//! commands always return an empty result with the schema clients expect.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::RecordBatch,
+    datatypes::{DataType, Field, Schema},
+};
+use arrow_flight::{
+    flight_service_server::FlightService, FlightDescriptor, FlightEndpoint, FlightInfo, Ticket,
+};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    flight::{record_batches_to_flight_stream, Service},
+    timing::{TimeMeasurement, TimedStream},
+};
+
+/// Get a `FlightInfo` for listing the foreign keys of `CommandGetExportedKeys`, `CommandGetImportedKeys`
+/// or `CommandGetCrossReference`, whose results are fetched with `ticket`.
+pub(crate) fn get_flight_info(
+    ticket: Vec<u8>,
+    request: Request<FlightDescriptor>,
+) -> Response<FlightInfo> {
+    tracing::trace!("get_flight_info_foreign_keys");
+    let fd = request.into_inner();
+
+    let endpoint = FlightEndpoint::new().with_ticket(Ticket {
+        ticket: ticket.into(),
+    });
+
+    let info = FlightInfo::new()
+        .with_endpoint(endpoint)
+        .with_descriptor(fd);
+
+    Response::new(info)
+}
+
+pub(crate) fn do_get(
+    command: &str,
+) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
+    let start = TimeMeasurement::new(
+        "flight_do_get_get_foreign_keys_duration_ms",
+        vec![("command", command.to_string())],
+    );
+    tracing::trace!("do_get_foreign_keys: {command}");
+
+    let record_batch = RecordBatch::new_empty(Arc::new(foreign_keys_schema()));
+
+    Ok(Response::new(Box::pin(TimedStream::new(
+        record_batches_to_flight_stream(vec![record_batch]),
+        move || start,
+    ))
+        as <Service as FlightService>::DoGetStream))
+}
+
+fn foreign_keys_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("pk_catalog_name", DataType::Utf8, true),
+        Field::new("pk_db_schema_name", DataType::Utf8, true),
+        Field::new("pk_table_name", DataType::Utf8, false),
+        Field::new("pk_column_name", DataType::Utf8, false),
+        Field::new("fk_catalog_name", DataType::Utf8, true),
+        Field::new("fk_db_schema_name", DataType::Utf8, true),
+        Field::new("fk_table_name", DataType::Utf8, false),
+        Field::new("fk_column_name", DataType::Utf8, false),
+        Field::new("key_sequence", DataType::Int32, false),
+        Field::new("fk_key_name", DataType::Utf8, true),
+        Field::new("pk_key_name", DataType::Utf8, true),
+        Field::new("update_rule", DataType::UInt8, false),
+        Field::new("delete_rule", DataType::UInt8, false),
+    ])
+}
@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use arrow::{
+    array::{Int32Builder, RecordBatch, StringBuilder},
+    datatypes::{DataType, Field, Schema},
+};
+use arrow_flight::{
+    flight_service_server::FlightService,
+    sql::{self, ProstMessageExt},
+    FlightDescriptor, FlightEndpoint, FlightInfo, Ticket,
+};
+use prost::Message;
+use tonic::{Request, Response, Status};
+
+use crate::{
+    flight::{record_batches_to_flight_stream, to_tonic_err, Service},
+    timing::{TimeMeasurement, TimedStream},
+};
+
+/// Get a `FlightInfo` for listing the primary key of a table.
+pub(crate) fn get_flight_info(
+    query: &sql::CommandGetPrimaryKeys,
+    request: Request<FlightDescriptor>,
+) -> Response<FlightInfo> {
+    tracing::trace!("get_flight_info_primary_keys");
+    let fd = request.into_inner();
+
+    let endpoint = FlightEndpoint::new().with_ticket(Ticket {
+        ticket: query.as_any().encode_to_vec().into(),
+    });
+
+    let info = FlightInfo::new()
+        .with_endpoint(endpoint)
+        .with_descriptor(fd);
+
+    Response::new(info)
+}
+
+/// Lists the columns of the `primary_key` declared in the acceleration of the dataset.
+pub(crate) async fn do_get(
+    flight_svc: &Service,
+    query: sql::CommandGetPrimaryKeys,
+) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
+    let start = TimeMeasurement::new("flight_do_get_get_primary_keys_duration_ms", vec![]);
+    tracing::trace!("do_get_primary_keys: {query:?}");
+
+    let mut catalog_names = StringBuilder::new();
+    let mut schema_names = StringBuilder::new();
+    let mut table_names = StringBuilder::new();
+    let mut column_names = StringBuilder::new();
+    let mut key_names = StringBuilder::new();
+    let mut key_sequences = Int32Builder::new();
+
+    let (catalog_name, schema_name) = {
+        let df = flight_svc.datafusion.read().await;
+        let state = df.ctx.state();
+        let options = &state.config_options().catalog;
+        (
+            options.default_catalog.clone(),
+            options.default_schema.clone(),
+        )
+    };
+
+    // Datasets are registered in the default catalog and schema
+    let in_default_schema = query.catalog.as_ref().map_or(true, |c| *c == catalog_name)
+        && query.db_schema.as_ref().map_or(true, |s| *s == schema_name);
+
+    let primary_key = if in_default_schema {
+        flight_svc.app.read().await.as_ref().and_then(|app| {
+            app.datasets
+                .iter()
+                .find(|dataset| dataset.name == query.table)
+                .and_then(|dataset| dataset.acceleration.as_ref())
+                .and_then(|acceleration| acceleration.primary_key.clone())
+        })
+    } else {
+        None
+    };
+
+    for (sequence, column_name) in (1..).zip(primary_key.unwrap_or_default()) {
+        catalog_names.append_value(&catalog_name);
+        schema_names.append_value(&schema_name);
+        table_names.append_value(&query.table);
+        column_names.append_value(column_name);
+        key_names.append_null();
+        key_sequences.append_value(sequence);
+    }
+
+    let schema = Schema::new(vec![
+        Field::new("catalog_name", DataType::Utf8, true),
+        Field::new("db_schema_name", DataType::Utf8, true),
+        Field::new("table_name", DataType::Utf8, false),
+        Field::new("column_name", DataType::Utf8, false),
+        Field::new("key_name", DataType::Utf8, true),
+        Field::new("key_sequence", DataType::Int32, false),
+    ]);
+    let record_batch = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(catalog_names.finish()),
+            Arc::new(schema_names.finish()),
+            Arc::new(table_names.finish()),
+            Arc::new(column_names.finish()),
+            Arc::new(key_names.finish()),
+            Arc::new(key_sequences.finish()),
+        ],
+    )
+    .map_err(to_tonic_err)?;
+
+    Ok(Response::new(Box::pin(TimedStream::new(
+        record_batches_to_flight_stream(vec![record_batch]),
+        move || start,
+    ))
+        as <Service as FlightService>::DoGetStream))
+}
@@ -0,0 +1,128 @@
+use arrow_flight::{
+    encode::FlightDataEncoderBuilder,
+    flight_service_server::FlightService,
+    sql::{
+        self,
+        metadata::{XdbcTypeInfo, XdbcTypeInfoData, XdbcTypeInfoDataBuilder},
+        Nullable, ProstMessageExt, Searchable, XdbcDataType,
+    },
+    FlightDescriptor, FlightEndpoint, FlightInfo, Ticket,
+};
+use futures::{stream, StreamExt, TryStreamExt};
+use prost::Message;
+use tonic::{Request, Response, Status};
+
+use crate::{
+    flight::{to_tonic_err, Service},
+    timing::{TimeMeasurement, TimedStream},
+};
+
+/// Get a `FlightInfo` for retrieving the XDBC type info.
+pub(crate) fn get_flight_info(
+    query: &sql::CommandGetXdbcTypeInfo,
+    request: Request<FlightDescriptor>,
+) -> Result<Response<FlightInfo>, Status> {
+    tracing::trace!("get_flight_info_xdbc_type_info: query={query:?}");
+    let data = xdbc_type_info_data()?;
+    let record_batch = query
+        .clone()
+        .into_builder(&data)
+        .build()
+        .map_err(to_tonic_err)?;
+
+    let fd = request.into_inner();
+
+    let ticket = Ticket {
+        ticket: query.as_any().encode_to_vec().into(),
+    };
+
+    let endpoint = FlightEndpoint::new().with_ticket(ticket);
+
+    Ok(Response::new(
+        FlightInfo::new()
+            .with_endpoint(endpoint)
+            .with_descriptor(fd)
+            .try_with_schema(&record_batch.schema())
+            .map_err(to_tonic_err)?,
+    ))
+}
+
+/// Get a `FlightDataStream` describing the SQL types supported by the runtime,
+/// optionally filtered to a single `data_type`.
+pub(crate) fn do_get(
+    query: sql::CommandGetXdbcTypeInfo,
+) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
+    tracing::trace!("do_get_xdbc_type_info: {query:?}");
+    let start = TimeMeasurement::new("flight_do_get_get_xdbc_type_info_duration_ms", vec![]);
+    let data = xdbc_type_info_data()?;
+    let record_batch = query.into_builder(&data).build().map_err(to_tonic_err)?;
+
+    let batches_stream = stream::iter(vec![Ok(record_batch)]);
+
+    let flight_data_stream = FlightDataEncoderBuilder::new().build(batches_stream);
+
+    Ok(Response::new(
+        TimedStream::new(flight_data_stream.map_err(to_tonic_err), move || start).boxed(),
+    ))
+}
+
+/// The SQL types DataFusion plans, described as `(type name, XDBC type, column size, literal quote)`.
+const SUPPORTED_TYPES: &[(&str, XdbcDataType, Option<i32>, Option<&str>)] = &[
+    ("BOOLEAN", XdbcDataType::XdbcBit, Some(1), None),
+    ("TINYINT", XdbcDataType::XdbcTinyint, Some(3), None),
+    ("SMALLINT", XdbcDataType::XdbcSmallint, Some(5), None),
+    ("INTEGER", XdbcDataType::XdbcInteger, Some(10), None),
+    ("BIGINT", XdbcDataType::XdbcBigint, Some(19), None),
+    ("REAL", XdbcDataType::XdbcReal, Some(7), None),
+    ("DOUBLE", XdbcDataType::XdbcDouble, Some(15), None),
+    ("DECIMAL", XdbcDataType::XdbcDecimal, Some(38), None),
+    ("VARCHAR", XdbcDataType::XdbcVarchar, None, Some("'")),
+    ("BYTEA", XdbcDataType::XdbcVarbinary, None, None),
+    ("DATE", XdbcDataType::XdbcDate, Some(10), Some("'")),
+    ("TIME", XdbcDataType::XdbcTime, Some(18), Some("'")),
+    (
+        "TIMESTAMP",
+        XdbcDataType::XdbcTimestamp,
+        Some(29),
+        Some("'"),
+    ),
+];
+
+fn xdbc_type_info_data() -> Result<XdbcTypeInfoData, Status> {
+    let mut builder = XdbcTypeInfoDataBuilder::new();
+    for (type_name, data_type, column_size, quote) in SUPPORTED_TYPES {
+        let is_numeric = !matches!(
+            data_type,
+            XdbcDataType::XdbcBit
+                | XdbcDataType::XdbcVarchar
+                | XdbcDataType::XdbcVarbinary
+                | XdbcDataType::XdbcDate
+                | XdbcDataType::XdbcTime
+                | XdbcDataType::XdbcTimestamp
+        );
+        builder.append(XdbcTypeInfo {
+            type_name: (*type_name).to_string(),
+            data_type: *data_type,
+            column_size: *column_size,
+            literal_prefix: quote.map(str::to_string),
+            literal_suffix: quote.map(str::to_string),
+            create_params: (*data_type == XdbcDataType::XdbcDecimal)
+                .then(|| vec!["precision".to_string(), "scale".to_string()]),
+            nullable: Nullable::NullabilityNullable,
+            case_sensitive: *data_type == XdbcDataType::XdbcVarchar,
+            searchable: Searchable::Full,
+            unsigned_attribute: is_numeric.then_some(false),
+            fixed_prec_scale: false,
+            auto_increment: is_numeric.then_some(false),
+            local_type_name: Some((*type_name).to_string()),
+            minimum_scale: None,
+            maximum_scale: (*data_type == XdbcDataType::XdbcDecimal).then_some(38),
+            sql_data_type: *data_type,
+            datetime_subcode: None,
+            num_prec_radix: is_numeric.then_some(10),
+            interval_precision: None,
+        });
+    }
+
+    builder.build().map_err(to_tonic_err)
+}
@@ -1,36 +1,152 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use arrow::datatypes::{DataType, Field, Schema};
 use arrow_flight::{
+    decode::FlightRecordBatchStream,
+    error::FlightError,
     flight_service_server::FlightService,
     sql::{self, ProstMessageExt},
-    FlightDescriptor, FlightEndpoint, FlightInfo, Ticket,
+    FlightData, FlightDescriptor, FlightEndpoint, FlightInfo, PutResult, Ticket,
 };
+use bytes::Bytes;
+use datafusion::{logical_expr::LogicalPlan, scalar::ScalarValue};
+use futures::{stream, StreamExt, TryStreamExt};
 use prost::Message;
-use tonic::{Request, Response, Status};
+use tonic::{Request, Response, Status, Streaming};
+use uuid::Uuid;
 
 use crate::{
-    flight::{to_tonic_err, Service},
+    auth::Principal,
+    flight::{handle_datafusion_error, to_tonic_err, QueryRequest, Service},
     timing::{TimeMeasurement, TimedStream},
 };
 
+/// How long a prepared statement is kept after it was last used, if the client doesn't close it.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// The most prepared statements a principal can have open at once.
+const MAX_STATEMENTS_PER_PRINCIPAL: usize = 1_000;
+
+/// A statement created by `CreatePreparedStatement`, kept until the client closes it or it is idle
+/// for `IDLE_TIMEOUT`.
+pub(crate) struct PreparedStatement {
+    /// The name of the principal that created the statement, the only one that can use it.
+    owner: Option<String>,
+    last_used: Instant,
+    sql: String,
+    plan: LogicalPlan,
+    dataset_schema: Schema,
+    parameter_schema: Schema,
+    /// The values bound to the `$1`, `$2`, ... placeholders by the last `DoPut`.
+    parameters: Option<Vec<ScalarValue>>,
+}
+
+impl PreparedStatement {
+    fn is_expired(&self) -> bool {
+        self.last_used.elapsed() > IDLE_TIMEOUT
+    }
+
+    /// Whether `principal` can use the statement, which is never the case once it expired.
+    fn is_usable_by(&self, principal: Option<&Principal>) -> bool {
+        !self.is_expired() && self.owner.as_deref() == principal.map(|p| p.name.as_str())
+    }
+
+    /// The plan with the bound parameter values substituted for its placeholders.
+    fn bound_plan(&self) -> Result<LogicalPlan, Status> {
+        match &self.parameters {
+            Some(parameters) => self
+                .plan
+                .clone()
+                .with_param_values(parameters.clone())
+                .map_err(handle_datafusion_error),
+            None if self.parameter_schema.fields().is_empty() => Ok(self.plan.clone()),
+            None => Err(Status::invalid_argument(
+                "Prepared statement parameters must be bound before execution",
+            )),
+        }
+    }
+}
+
 /// Create a prepared statement from given SQL statement.
 pub(crate) async fn do_action_create_prepared_statement(
     flight_svc: &Service,
+    principal: Option<&Principal>,
     statement: sql::ActionCreatePreparedStatementRequest,
 ) -> Result<sql::ActionCreatePreparedStatementResult, Status> {
     tracing::trace!("do_action_create_prepared_statement: {statement:?}");
-    let arrow_schema =
-        Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), statement.query.clone())
-            .await
-            .map_err(to_tonic_err)?;
-
-    let schema_bytes = Service::serialize_schema(&arrow_schema)?;
-
-    Ok(sql::ActionCreatePreparedStatementResult {
-        prepared_statement_handle: statement.query.into(),
-        dataset_schema: schema_bytes,
-        ..Default::default()
-    })
+    let plan = flight_svc
+        .datafusion
+        .read()
+        .await
+        .ctx
+        .state()
+        .create_logical_plan(&statement.query)
+        .await
+        .map_err(handle_datafusion_error)?;
+
+    let dataset_schema: Schema = plan.schema().as_ref().into();
+    let parameter_schema = parameter_schema(&plan)?;
+
+    let handle = Bytes::from(Uuid::new_v4().to_string());
+    let result = sql::ActionCreatePreparedStatementResult {
+        prepared_statement_handle: handle.clone(),
+        dataset_schema: Service::serialize_schema(&dataset_schema)?,
+        parameter_schema: Service::serialize_schema(&parameter_schema)?,
+    };
+
+    let mut prepared_statements = flight_svc.prepared_statements.write().await;
+    prepared_statements.retain(|_, statement| !statement.is_expired());
+    let owner = principal.map(|principal| principal.name.clone());
+    let open = prepared_statements
+        .values()
+        .filter(|statement| statement.owner == owner)
+        .count();
+    if open >= MAX_STATEMENTS_PER_PRINCIPAL {
+        return Err(Status::resource_exhausted(format!(
+            "Too many open prepared statements, the limit is {MAX_STATEMENTS_PER_PRINCIPAL}"
+        )));
+    }
+
+    prepared_statements.insert(
+        handle,
+        PreparedStatement {
+            owner,
+            last_used: Instant::now(),
+            sql: statement.query,
+            plan,
+            dataset_schema,
+            parameter_schema,
+            parameters: None,
+        },
+    );
+    #[allow(clippy::cast_precision_loss)]
+    metrics::gauge!("flight_prepared_statements").set(prepared_statements.len() as f64);
+
+    Ok(result)
+}
+
+/// Frees the resources held by a prepared statement.
+pub(crate) async fn do_action_close_prepared_statement(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    statement: sql::ActionClosePreparedStatementRequest,
+) -> Result<(), Status> {
+    tracing::trace!("do_action_close_prepared_statement: {statement:?}");
+    let mut prepared_statements = flight_svc.prepared_statements.write().await;
+    if !prepared_statements
+        .get(&statement.prepared_statement_handle)
+        .is_some_and(|statement| statement.is_usable_by(principal))
+    {
+        return Err(unknown_handle());
+    }
+    prepared_statements.remove(&statement.prepared_statement_handle);
+    metrics::gauge!("flight_prepared_statements").decrement(1.0);
+
+    Ok(())
 }
 
 pub(crate) async fn get_flight_info(
@@ -40,20 +156,19 @@ pub(crate) async fn get_flight_info(
 ) -> Result<Response<FlightInfo>, Status> {
     tracing::trace!("get_flight_info: {handle:?}");
 
-    let sql = match std::str::from_utf8(&handle.prepared_statement_handle) {
-        Ok(sql) => sql.to_string(),
-        Err(e) => {
-            return Err(Status::invalid_argument(format!(
-                "Invalid prepared statement handle: {e}"
-            )))
-        }
-    };
+    let principal = request.extensions().get::<Principal>().cloned();
+    let mut prepared_statements = flight_svc.prepared_statements.write().await;
+    let statement = usable_statement(
+        &mut prepared_statements,
+        &handle.prepared_statement_handle,
+        principal.as_ref(),
+    )?;
 
-    let arrow_schema = Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), sql)
-        .await
-        .map_err(to_tonic_err)?;
-
-    tracing::trace!("get_flight_info_prepared_statement: arrow_schema={arrow_schema:?}");
+    tracing::trace!(
+        "get_flight_info_prepared_statement: sql={}, arrow_schema={:?}",
+        statement.sql,
+        statement.dataset_schema
+    );
 
     let fd = request.into_inner();
 
@@ -63,7 +178,7 @@ pub(crate) async fn get_flight_info(
 
     let info = FlightInfo::new()
         .with_endpoint(endpoint)
-        .try_with_schema(&arrow_schema)
+        .try_with_schema(&statement.dataset_schema)
         .map_err(to_tonic_err)?
         .with_descriptor(fd);
 
@@ -72,22 +187,139 @@ pub(crate) async fn get_flight_info(
 
 pub(crate) async fn do_get(
     flight_svc: &Service,
+    query_request: &QueryRequest,
     query: sql::CommandPreparedStatementQuery,
 ) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
-    let datafusion = Arc::clone(&flight_svc.datafusion);
     tracing::trace!("do_get: {query:?}");
-    match std::str::from_utf8(&query.prepared_statement_handle) {
-        Ok(sql) => {
-            let start =
-                TimeMeasurement::new("flight_do_get_prepared_statement_query_duration_ms", vec![]);
-            let output = Service::sql_to_flight_stream(datafusion, sql.to_owned()).await?;
-            let timed_output = TimedStream::new(output, move || start);
-            Ok(Response::new(
-                Box::pin(timed_output) as <Service as FlightService>::DoGetStream
+    let (plan, tracker) = {
+        let mut prepared_statements = flight_svc.prepared_statements.write().await;
+        let statement = usable_statement(
+            &mut prepared_statements,
+            &query.prepared_statement_handle,
+            query_request.principal.as_ref(),
+        )?;
+        let tracker = query_request.track(&*flight_svc.datafusion.read().await, &statement.sql);
+        match statement.bound_plan() {
+            Ok(plan) => (plan, tracker),
+            Err(e) => {
+                tracker.fail(&e.message());
+                return Err(e);
+            }
+        }
+    };
+
+    let start = TimeMeasurement::new("flight_do_get_prepared_statement_query_duration_ms", vec![]);
+    let output = Service::plan_to_flight_stream(
+        Arc::clone(&flight_svc.datafusion),
+        plan,
+        query_request,
+        tracker,
+    )
+    .await?;
+    let timed_output = TimedStream::new(output, move || start);
+    Ok(Response::new(
+        Box::pin(timed_output) as <Service as FlightService>::DoGetStream
+    ))
+}
+
+/// Binds the values of the single row of parameters sent by the client to the prepared statement.
+pub(crate) async fn do_put(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    query: sql::CommandPreparedStatementQuery,
+    message: FlightData,
+    streaming_flight: Streaming<FlightData>,
+) -> Result<Response<<Service as FlightService>::DoPutStream>, Status> {
+    tracing::trace!("do_put: {query:?}");
+    let _start = TimeMeasurement::new("flight_do_put_prepared_statement_query_duration_ms", vec![]);
+
+    let flight_data = stream::once(async { Ok::<_, Status>(message) })
+        .chain(streaming_flight)
+        .map_err(FlightError::Tonic);
+    let batches: Vec<_> = FlightRecordBatchStream::new_from_flight_data(flight_data)
+        .try_collect()
+        .await
+        .map_err(|e| Status::invalid_argument(format!("Invalid parameters: {e}")))?;
+
+    let mut rows = batches.iter().filter(|batch| batch.num_rows() > 0);
+    let parameters = match (rows.next(), rows.next()) {
+        (None, _) => vec![],
+        (Some(batch), None) if batch.num_rows() == 1 => batch
+            .columns()
+            .iter()
+            .map(|column| ScalarValue::try_from_array(column, 0))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(handle_datafusion_error)?,
+        _ => {
+            return Err(Status::invalid_argument(
+                "Binding more than one row of parameters is not supported",
             ))
         }
-        Err(e) => Err(Status::invalid_argument(format!(
-            "Invalid prepared statement handle: {e}"
-        ))),
+    };
+
+    let mut prepared_statements = flight_svc.prepared_statements.write().await;
+    let statement = usable_statement(
+        &mut prepared_statements,
+        &query.prepared_statement_handle,
+        principal,
+    )?;
+
+    let expected = statement.parameter_schema.fields().len();
+    if parameters.len() != expected {
+        return Err(Status::invalid_argument(format!(
+            "Expected {expected} parameters, received {}",
+            parameters.len()
+        )));
     }
+    statement.parameters = Some(parameters);
+
+    Ok(Response::new(
+        stream::iter(vec![Ok(PutResult::default())]).boxed(),
+    ))
+}
+
+/// The schema of the `$1`, `$2`, ... placeholders of the plan, in order.
+///
+/// Placeholders whose type can't be inferred from the query are reported as `Null`.
+fn parameter_schema(plan: &LogicalPlan) -> Result<Schema, Status> {
+    let mut parameters: Vec<(usize, String, Option<DataType>)> = plan
+        .get_parameter_types()
+        .map_err(handle_datafusion_error)?
+        .into_iter()
+        .map(|(id, data_type)| {
+            let position = id
+                .trim_start_matches('$')
+                .parse::<usize>()
+                .map_err(|_| Status::invalid_argument(format!("Invalid placeholder: {id}")))?;
+            Ok((position, id, data_type))
+        })
+        .collect::<Result<_, Status>>()?;
+    parameters.sort_by_key(|(position, _, _)| *position);
+
+    Ok(Schema::new(
+        parameters
+            .into_iter()
+            .map(|(_, id, data_type)| Field::new(id, data_type.unwrap_or(DataType::Null), true))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Looks up the statement of `handle` for `principal` and marks it as used.
+///
+/// Statements of other principals are reported as unknown, like expired ones, so handles can't be probed.
+fn usable_statement<'a>(
+    prepared_statements: &'a mut HashMap<Bytes, PreparedStatement>,
+    handle: &Bytes,
+    principal: Option<&Principal>,
+) -> Result<&'a mut PreparedStatement, Status> {
+    let statement = prepared_statements
+        .get_mut(handle)
+        .filter(|statement| statement.is_usable_by(principal))
+        .ok_or_else(unknown_handle)?;
+    statement.last_used = Instant::now();
+    Ok(statement)
+}
+
+fn unknown_handle() -> Status {
+    Status::not_found("Unknown prepared statement handle")
 }
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use arrow_flight::{
+    decode::FlightRecordBatchStream, error::FlightError, flight_service_server::FlightService,
+    sql::Any, FlightData,
+};
+use bytes::Bytes;
+use futures::{stream, StreamExt, TryStreamExt};
+use prost::Message;
+use tonic::{Response, Status, Streaming};
+
+use crate::{
+    auth::Principal,
+    flight::{do_put, to_tonic_err, Service},
+    timing::TimeMeasurement,
+};
+
+use super::statement_update::update_result;
+
+pub(crate) const TYPE_URL: &str =
+    "type.googleapis.com/arrow.flight.protocol.sql.CommandStatementIngest";
+
+/// The Flight SQL bulk ingestion command, which is not yet part of the `arrow-flight` crate.
+///
+/// The `table_definition_options` field is not decoded, as ingestion only appends to existing datasets.
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct CommandStatementIngest {
+    #[prost(string, tag = "2")]
+    pub table: String,
+    #[prost(string, optional, tag = "3")]
+    pub schema: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub catalog: Option<String>,
+    #[prost(bool, tag = "5")]
+    pub temporary: bool,
+    #[prost(bytes = "bytes", optional, tag = "6")]
+    pub transaction_id: Option<Bytes>,
+    #[prost(map = "string, string", tag = "1000")]
+    pub options: HashMap<String, String>,
+}
+
+impl CommandStatementIngest {
+    pub(crate) fn try_from_any(any: &Any) -> Option<Self> {
+        if any.type_url != TYPE_URL {
+            return None;
+        }
+        Self::decode(any.value.clone()).ok()
+    }
+}
+
+/// Appends the record batches sent by the client to the dataset named by the command.
+pub(crate) async fn do_put(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    command: CommandStatementIngest,
+    message: FlightData,
+    streaming_flight: Streaming<FlightData>,
+) -> Result<Response<<Service as FlightService>::DoPutStream>, Status> {
+    tracing::trace!("do_put_statement_ingest: {command:?}");
+    let _start = TimeMeasurement::new("flight_do_put_statement_ingest_duration_ms", vec![]);
+
+    if command.temporary || command.transaction_id.is_some() {
+        return Err(Status::unimplemented(
+            "Temporary tables and transactions are not supported",
+        ));
+    }
+
+    let dataset_name = command.table;
+    {
+        let df = flight_svc.datafusion.read().await;
+        if !df.has_publishers(&dataset_name) {
+            return Err(Status::invalid_argument(format!(
+                "Dataset {dataset_name} is not writable, set `mode: read_write` to enable writes"
+            )));
+        }
+        df.access_policy()
+            .check_write(principal, &dataset_name)
+            .map_err(|e| Status::permission_denied(e.to_string()))?;
+    }
+
+    let flight_data = stream::once(async { Ok::<_, Status>(message) })
+        .chain(streaming_flight)
+        .map_err(FlightError::Tonic);
+    let batches: Vec<_> = FlightRecordBatchStream::new_from_flight_data(flight_data)
+        .try_collect()
+        .await
+        .map_err(to_tonic_err)?;
+
+    let record_count = do_put::publish_append(flight_svc, &dataset_name, batches).await?;
+
+    Ok(Response::new(
+        stream::iter(vec![Ok(update_result(record_count))]).boxed(),
+    ))
+}
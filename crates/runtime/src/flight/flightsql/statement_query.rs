@@ -9,7 +9,8 @@ use prost::Message;
 use tonic::{Request, Response, Status};
 
 use crate::{
-    flight::{to_tonic_err, Service},
+    auth::Principal,
+    flight::{to_tonic_err, QueryRequest, Service},
     timing::{TimeMeasurement, TimedStream},
 };
 
@@ -22,11 +23,14 @@ pub(crate) async fn get_flight_info(
     tracing::trace!("get_flight_info: {query:?}");
 
     let sql = query.query.as_str();
+    let principal = request.extensions().get::<Principal>().cloned();
 
-    let arrow_schema =
-        Service::get_arrow_schema(Arc::clone(&flight_svc.datafusion), sql.to_string())
-            .await
-            .map_err(to_tonic_err)?;
+    let arrow_schema = Service::get_arrow_schema(
+        Arc::clone(&flight_svc.datafusion),
+        sql.to_string(),
+        principal.as_ref(),
+    )
+    .await?;
 
     let fd = request.into_inner();
 
@@ -45,12 +49,13 @@ pub(crate) async fn get_flight_info(
 
 pub(crate) async fn do_get(
     flight_svc: &Service,
+    query_request: &QueryRequest,
     cmd: sql::CommandStatementQuery,
 ) -> Result<Response<<Service as FlightService>::DoGetStream>, Status> {
     let datafusion = Arc::clone(&flight_svc.datafusion);
     tracing::trace!("do_get_statement: {cmd:?}");
     let start = TimeMeasurement::new("flight_do_get_statement_query_duration_ms", vec![]);
-    let output = Service::sql_to_flight_stream(datafusion, cmd.query).await?;
+    let output = Service::sql_to_flight_stream(datafusion, cmd.query, query_request).await?;
     let timed_output = TimedStream::new(output, move || start);
     Ok(Response::new(
         Box::pin(timed_output) as <Service as FlightService>::DoGetStream
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use arrow_flight::{
+    flight_service_server::FlightService,
+    sql::{self, DoPutUpdateResult},
+    PutResult,
+};
+use datafusion::logical_expr::{DmlStatement, LogicalPlan, WriteOp};
+use futures::{stream, StreamExt};
+use prost::Message;
+use tonic::{Response, Status};
+
+use crate::{
+    auth::Principal,
+    flight::{do_put, handle_datafusion_error, Service},
+    timing::TimeMeasurement,
+};
+
+/// Executes an `INSERT`, `UPDATE` or `DELETE` statement against a writable dataset.
+///
+/// Inserted rows are published to the dataset like a `DoPut`, so they reach every accelerator and replica.
+/// Updates and deletes are executed as SQL directly on the accelerator, and are only supported when the
+/// accelerator is the dataset's only publisher. The reported record count is the number of rows inserted,
+/// or the number of accelerated rows updated or deleted.
+pub(crate) async fn do_put(
+    flight_svc: &Service,
+    principal: Option<&Principal>,
+    command: sql::CommandStatementUpdate,
+) -> Result<Response<<Service as FlightService>::DoPutStream>, Status> {
+    tracing::trace!("do_put_statement_update: {command:?}");
+    let _start = TimeMeasurement::new("flight_do_put_statement_update_duration_ms", vec![]);
+
+    let df = flight_svc.datafusion.read().await;
+    let plan = df
+        .ctx
+        .state()
+        .create_logical_plan(&command.query)
+        .await
+        .map_err(handle_datafusion_error)?;
+
+    let LogicalPlan::Dml(DmlStatement {
+        table_name,
+        op,
+        input,
+        ..
+    }) = &plan
+    else {
+        return Err(Status::invalid_argument(
+            "Only INSERT, UPDATE and DELETE statements are supported",
+        ));
+    };
+
+    df.access_policy()
+        .check_plan(principal, &plan)
+        .map_err(|e| Status::permission_denied(e.to_string()))?;
+
+    let dataset_name = table_name.table().to_string();
+    let Some((dataset, publishers)) = df.get_publishers(&dataset_name) else {
+        return Err(Status::invalid_argument(format!(
+            "Dataset {dataset_name} is not writable, set `mode: read_write` to enable writes"
+        )));
+    };
+    let dataset = Arc::clone(dataset);
+    let publishers = Arc::clone(publishers);
+
+    let record_count = match op {
+        WriteOp::InsertInto => {
+            let batches = df
+                .ctx
+                .execute_logical_plan(input.as_ref().clone())
+                .await
+                .map_err(handle_datafusion_error)?
+                .collect()
+                .await
+                .map_err(handle_datafusion_error)?;
+            drop(df);
+
+            do_put::publish_append(flight_svc, &dataset_name, batches).await?
+        }
+        WriteOp::Update | WriteOp::Delete => {
+            drop(df);
+
+            // Each publisher commits on its own, so a statement that only some of them can execute would
+            // leave them out of sync. Only a dataset whose sole publisher is its accelerator is updated.
+            let publishers = publishers.read().await;
+            let [publisher] = publishers.as_slice() else {
+                return Err(Status::invalid_argument(format!(
+                    "UPDATE and DELETE are only supported on datasets written to their accelerator alone, {dataset_name} has {} publishers",
+                    publishers.len()
+                )));
+            };
+            if !publisher.supports_dml() {
+                return Err(Status::invalid_argument(format!(
+                    "UPDATE and DELETE are not supported on {dataset_name}, accelerated with {}",
+                    publisher.name()
+                )));
+            }
+
+            publisher
+                .execute_dml(Arc::clone(&dataset), command.query.clone())
+                .await
+                .map_err(|e| {
+                    Status::invalid_argument(format!(
+                        "Unable to execute statement on {}: {e}",
+                        publisher.name()
+                    ))
+                })?
+        }
+        _ => {
+            return Err(Status::invalid_argument(format!(
+                "Unsupported statement: {op}"
+            )))
+        }
+    };
+
+    Ok(Response::new(
+        stream::iter(vec![Ok(update_result(record_count))]).boxed(),
+    ))
+}
+
+/// The `DoPutUpdateResult` response to a Flight SQL update or ingest command.
+pub(crate) fn update_result(record_count: u64) -> PutResult {
+    PutResult {
+        app_metadata: DoPutUpdateResult {
+            record_count: i64::try_from(record_count).unwrap_or(i64::MAX),
+        }
+        .encode_to_vec()
+        .into(),
+    }
+}
@@ -1,5 +1,5 @@
 use arrow_flight::{
-    sql::{Any, Command},
+    sql::{Any, Command, ProstMessageExt},
     FlightDescriptor, FlightEndpoint, FlightInfo, Ticket,
 };
 use prost::Message;
@@ -31,9 +31,29 @@ pub(crate) async fn handle(
         Command::CommandGetTables(token) => {
             Ok(flightsql::get_tables::get_flight_info(&token, request))
         }
+        Command::CommandGetTableTypes(token) => {
+            Ok(flightsql::get_table_types::get_flight_info(&token, request))
+        }
+        Command::CommandGetPrimaryKeys(token) => Ok(flightsql::get_primary_keys::get_flight_info(
+            &token, request,
+        )),
+        Command::CommandGetExportedKeys(token) => Ok(flightsql::get_foreign_keys::get_flight_info(
+            token.as_any().encode_to_vec(),
+            request,
+        )),
+        Command::CommandGetImportedKeys(token) => Ok(flightsql::get_foreign_keys::get_flight_info(
+            token.as_any().encode_to_vec(),
+            request,
+        )),
+        Command::CommandGetCrossReference(token) => Ok(
+            flightsql::get_foreign_keys::get_flight_info(token.as_any().encode_to_vec(), request),
+        ),
         Command::CommandGetSqlInfo(token) => {
             flightsql::get_sql_info::get_flight_info(&token, request)
         }
+        Command::CommandGetXdbcTypeInfo(token) => {
+            flightsql::get_xdbc_type_info::get_flight_info(&token, request)
+        }
         _ => Err(Status::unimplemented("Not yet implemented")),
     }
 }
@@ -0,0 +1,70 @@
+use arrow_flight::{
+    flight_descriptor::DescriptorType,
+    sql::{Any, Command},
+    FlightDescriptor, SchemaResult,
+};
+use prost::Message;
+use tonic::{Request, Response, Status};
+
+use crate::auth::Principal;
+
+use super::{handle_datafusion_error, Service};
+
+/// Returns the schema of the dataset named by a path descriptor, or of the results of the SQL query in a cmd descriptor.
+pub(crate) async fn handle(
+    flight_svc: &Service,
+    request: Request<FlightDescriptor>,
+) -> Result<Response<SchemaResult>, Status> {
+    let principal = request.extensions().get::<Principal>().cloned();
+    let fd = request.into_inner();
+    tracing::trace!("get_schema: {fd:?}");
+
+    let df = flight_svc.datafusion.read().await;
+    let schema = match fd.r#type() {
+        DescriptorType::Path => {
+            if fd.path.is_empty() {
+                return Err(Status::invalid_argument("No path provided"));
+            }
+            let dataset = fd.path.join(".");
+            df.access_policy()
+                .check_read(principal.as_ref(), &dataset)
+                .map_err(|e| Status::permission_denied(e.to_string()))?;
+            df.get_arrow_schema(&dataset)
+                .await
+                .map_err(|e| Status::not_found(e.to_string()))?
+        }
+        DescriptorType::Cmd => {
+            let sql = cmd_to_sql(&fd.cmd)?;
+            let plan = df
+                .ctx
+                .state()
+                .create_logical_plan(&sql)
+                .await
+                .map_err(handle_datafusion_error)?;
+            df.access_policy()
+                .check_plan(principal.as_ref(), &plan)
+                .map_err(|e| Status::permission_denied(e.to_string()))?;
+            plan.schema().as_ref().into()
+        }
+        DescriptorType::Unknown => {
+            return Err(Status::invalid_argument("Unknown flight descriptor type"));
+        }
+    };
+
+    Ok(Response::new(SchemaResult {
+        schema: Service::serialize_schema(&schema)?,
+    }))
+}
+
+/// A cmd descriptor holds either a Flight SQL `CommandStatementQuery` or the SQL text itself.
+fn cmd_to_sql(cmd: &[u8]) -> Result<String, Status> {
+    if let Ok(message) = Any::decode(cmd) {
+        if let Ok(Command::CommandStatementQuery(query)) = Command::try_from(message) {
+            return Ok(query.query);
+        }
+    }
+
+    std::str::from_utf8(cmd)
+        .map(ToString::to_string)
+        .map_err(|e| Status::invalid_argument(format!("Invalid command: {e}")))
+}
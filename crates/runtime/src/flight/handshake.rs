@@ -1,19 +1,47 @@
 use std::pin::Pin;
 
-use arrow_flight::HandshakeResponse;
-use futures::Stream;
-use tonic::{metadata::MetadataValue, Response, Status};
+use arrow_flight::{BasicAuth, HandshakeRequest, HandshakeResponse};
+use futures::{Stream, StreamExt};
+use prost::Message;
+use tonic::{metadata::MetadataValue, Request, Response, Status, Streaming};
 use uuid::Uuid;
 
+use crate::auth::Principal;
 use crate::timing::{TimeMeasurement, TimedStream};
 
+use super::Service;
+
 type HandshakeResponseStream =
     Pin<Box<dyn Stream<Item = Result<HandshakeResponse, Status>> + Send>>;
 
-pub(crate) fn handle() -> Result<Response<HandshakeResponseStream>, Status> {
-    // THIS IS PLACEHOLDER NO-OP AUTH THAT DOES NOT CHECK THE PROVIDED TOKEN AND SIMPLY RETURNS A UUID.
-    // TODO: Implement proper auth.
-    let token = Uuid::new_v4().to_string();
+pub(crate) async fn handle(
+    flight_svc: &Service,
+    request: Request<Streaming<HandshakeRequest>>,
+) -> Result<Response<HandshakeResponseStream>, Status> {
+    let token = match &flight_svc.authenticator {
+        // Authentication is disabled, any client is accepted.
+        None => Uuid::new_v4().to_string(),
+        Some(authenticator) => {
+            let header_principal = request
+                .metadata()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| authenticator.authenticate_header(value));
+
+            let principal = match header_principal {
+                Some(principal) => Some(principal),
+                None => authenticate_payload(flight_svc, request).await,
+            };
+
+            let Some(principal) = principal else {
+                metrics::counter!("flight_unauthenticated_requests").increment(1);
+                return Err(Status::unauthenticated("Invalid credentials"));
+            };
+
+            authenticator.issue_token(principal)
+        }
+    };
+
     let result = HandshakeResponse {
         protocol_version: 0,
         payload: token.as_bytes().to_vec().into(),
@@ -29,3 +57,17 @@ pub(crate) fn handle() -> Result<Response<HandshakeResponseStream>, Status> {
     resp.metadata_mut().insert("authorization", md);
     Ok(resp)
 }
+
+/// Validates the `BasicAuth` message sent as the payload of the first handshake request,
+/// used by clients that don't set the `authorization` header.
+async fn authenticate_payload(
+    flight_svc: &Service,
+    request: Request<Streaming<HandshakeRequest>>,
+) -> Option<Principal> {
+    let authenticator = flight_svc.authenticator.as_ref()?;
+    let mut stream = request.into_inner();
+    let handshake = stream.next().await?.ok()?;
+    let basic_auth = BasicAuth::decode(handshake.payload).ok()?;
+
+    authenticator.authenticate_basic(&basic_auth.username, &basic_auth.password)
+}
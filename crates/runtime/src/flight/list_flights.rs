@@ -0,0 +1,71 @@
+use arrow::datatypes::Schema;
+use arrow_flight::{
+    flight_service_server::FlightService, Criteria, FlightDescriptor, FlightEndpoint, FlightInfo,
+    Ticket,
+};
+use datafusion::sql::TableReference;
+use futures::stream::{self, StreamExt};
+use tonic::{Request, Response, Status};
+
+use crate::auth::Principal;
+
+use super::{to_tonic_err, Service};
+
+/// Lists a `FlightInfo` for each dataset the caller may read.
+///
+/// The criteria expression, if provided, filters the datasets by name prefix.
+pub(crate) async fn handle(
+    flight_svc: &Service,
+    request: Request<Criteria>,
+) -> Result<Response<<Service as FlightService>::ListFlightsStream>, Status> {
+    let principal = request.extensions().get::<Principal>().cloned();
+    let criteria = request.into_inner();
+    let prefix = std::str::from_utf8(&criteria.expression)
+        .map_err(|e| Status::invalid_argument(format!("Invalid criteria: {e}")))?;
+    tracing::trace!("list_flights: {prefix:?}");
+
+    let df = flight_svc.datafusion.read().await;
+    let mut table_names = df.get_table_names();
+    table_names.retain(|name| {
+        name.starts_with(prefix)
+            && df
+                .access_policy()
+                .check_read(principal.as_ref(), name)
+                .is_ok()
+    });
+    table_names.sort();
+
+    let mut flights = Vec::with_capacity(table_names.len());
+    for table_name in table_names {
+        // The names are of tables in the default schema, even when they contain a dot, i.e. `eth.blocks`.
+        let Ok(table_provider) = df
+            .ctx
+            .table_provider(TableReference::bare(table_name.as_str()))
+            .await
+        else {
+            continue;
+        };
+
+        // Approximate row counts are only available for providers that keep statistics
+        let total_records = table_provider
+            .statistics()
+            .and_then(|statistics| statistics.num_rows.get_value().copied())
+            .and_then(|num_rows| i64::try_from(num_rows).ok())
+            .unwrap_or(-1);
+
+        let schema: Schema = table_provider.schema().as_ref().clone();
+        let ticket = Ticket::new(format!(
+            r#"SELECT * FROM "{}""#,
+            table_name.replace('"', "\"\"")
+        ));
+        let info = FlightInfo::new()
+            .try_with_schema(&schema)
+            .map_err(to_tonic_err)?
+            .with_descriptor(FlightDescriptor::new_path(vec![table_name]))
+            .with_endpoint(FlightEndpoint::new().with_ticket(ticket))
+            .with_total_records(total_records);
+        flights.push(Ok(info));
+    }
+
+    Ok(Response::new(stream::iter(flights).boxed()))
+}
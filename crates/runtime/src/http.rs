@@ -1,13 +1,21 @@
 use std::{collections::HashMap, fmt::Debug, net::SocketAddr, sync::Arc};
 
 use app::App;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+};
 use snafu::prelude::*;
 use tokio::{
     net::{TcpListener, ToSocketAddrs},
     sync::RwLock,
 };
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
 
-use crate::{config, datafusion::DataFusion, model::Model};
+use crate::{auth::Authenticator, config, datafusion::DataFusion, model::Model, tls::TlsIdentity};
 
 mod routes;
 mod v1;
@@ -19,6 +27,9 @@ pub enum Error {
 
     #[snafu(display("Unable to start HTTP server: {source}"))]
     UnableToStartHttpServer { source: std::io::Error },
+
+    #[snafu(display("Unable to configure TLS for the HTTP server: {source}"))]
+    UnableToConfigureTls { source: crate::tls::Error },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -30,21 +41,65 @@ pub(crate) async fn start<A>(
     models: Arc<RwLock<HashMap<String, Model>>>,
     config: Arc<config::Config>,
     with_metrics: Option<SocketAddr>,
+    authenticator: Option<Arc<Authenticator>>,
+    tls: Option<TlsIdentity>,
 ) -> Result<()>
 where
     A: ToSocketAddrs + Debug,
 {
-    let routes = routes::routes(app, df, models, config, with_metrics);
+    let routes = routes::routes(app, df, models, config, with_metrics, authenticator);
 
     let listener = TcpListener::bind(&bind_address)
         .await
         .context(UnableToBindServerToPortSnafu)?;
-    tracing::info!("Spice Runtime HTTP listening on {bind_address:?}");
-
     metrics::counter!("spiced_runtime_http_server_start").increment(1);
 
-    axum::serve(listener, routes)
-        .await
-        .context(UnableToStartHttpServerSnafu)?;
-    Ok(())
+    match tls {
+        Some(tls) => {
+            let tls_config = tls.rustls_config().context(UnableToConfigureTlsSnafu)?;
+            tracing::info!("Spice Runtime HTTP listening on {bind_address:?} (TLS)");
+            serve_tls(listener, routes, TlsAcceptor::from(tls_config)).await
+        }
+        None => {
+            tracing::info!("Spice Runtime HTTP listening on {bind_address:?}");
+            axum::serve(listener, routes)
+                .await
+                .context(UnableToStartHttpServerSnafu)?;
+            Ok(())
+        }
+    }
+}
+
+async fn serve_tls(listener: TcpListener, routes: Router, acceptor: TlsAcceptor) -> Result<()> {
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                tracing::debug!("Unable to accept HTTP connection: {e}");
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let routes = routes.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!("TLS handshake with {remote_addr} failed: {e}");
+                    return;
+                }
+            };
+
+            let service = hyper::service::service_fn(move |request: hyper::Request<Incoming>| {
+                routes.clone().oneshot(request)
+            });
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("Error serving HTTP connection from {remote_addr}: {e}");
+            }
+        });
+    }
 }
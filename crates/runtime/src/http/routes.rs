@@ -1,14 +1,19 @@
-use crate::{config, datafusion::DataFusion, model::Model};
+use crate::{
+    auth::{Authenticator, Principal},
+    config,
+    datafusion::DataFusion,
+    model::Model,
+};
 use app::App;
 use std::net::SocketAddr;
 use std::{collections::HashMap, sync::Arc};
 
 use axum::{
     body::Body,
-    extract::MatchedPath,
-    http::Request,
+    extract::{MatchedPath, State},
+    http::{header, Request, StatusCode},
     middleware::{self, Next},
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     routing::{get, post, Router},
     Extension,
 };
@@ -22,15 +27,30 @@ pub(crate) fn routes(
     models: Arc<RwLock<HashMap<String, Model>>>,
     config: Arc<config::Config>,
     with_metrics: Option<SocketAddr>,
+    authenticator: Option<Arc<Authenticator>>,
 ) -> Router {
-    Router::new()
-        .route("/health", get(|| async { "ok\n" }))
+    let mut health = Router::new().route("/health", get(|| async { "ok\n" }));
+    let mut api = Router::new()
         .route("/v1/sql", post(v1::query::post))
         .route("/v1/status", get(v1::status::get))
         .route("/v1/datasets", get(v1::datasets::get))
+        .route("/v1/datasets/:name/refresh", post(v1::datasets::refresh))
         .route("/v1/models", get(v1::models::get))
         .route("/v1/models/:name/predict", get(v1::inference::get))
-        .route("/v1/predict", post(v1::inference::post))
+        .route("/v1/predict", post(v1::inference::post));
+
+    if let Some(authenticator) = authenticator {
+        api = api.route_layer(middleware::from_fn_with_state(
+            Arc::clone(&authenticator),
+            authenticate,
+        ));
+        if config.auth.http_auth_health {
+            health =
+                health.route_layer(middleware::from_fn_with_state(authenticator, authenticate));
+        }
+    }
+
+    api.merge(health)
         .route_layer(middleware::from_fn(track_metrics))
         .layer(Extension(app))
         .layer(Extension(df))
@@ -39,6 +59,40 @@ pub(crate) fn routes(
         .layer(Extension(config))
 }
 
+/// Validates the `Authorization` (basic or bearer) or `X-API-Key` header of the request, and makes the
+/// authenticated `Principal` available to handlers as a request extension.
+async fn authenticate(
+    State(authenticator): State<Arc<Authenticator>>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    let headers = req.headers();
+    let principal = if let Some(api_key) = headers.get("x-api-key") {
+        api_key
+            .to_str()
+            .ok()
+            .and_then(|api_key| authenticator.authenticate_bearer(api_key.trim()))
+    } else {
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| authenticator.authenticate_header(value))
+    };
+
+    let Some(principal): Option<Principal> = principal else {
+        metrics::counter!("http_unauthenticated_requests").increment(1);
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer, Basic")],
+            "Invalid or missing credentials\n",
+        )
+            .into_response();
+    };
+
+    req.extensions_mut().insert(principal);
+    next.run(req).await
+}
+
 async fn track_metrics(req: Request<Body>, next: Next) -> impl IntoResponse {
     let start = Instant::now();
     let path = if let Some(matched_path) = req.extensions().get::<MatchedPath>() {
@@ -9,15 +9,6 @@ pub enum Format {
     Csv,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum Status {
-    Initializing,
-    Disabled,
-    Ready,
-    Error,
-}
-
 fn convert_entry_to_csv<T: Serialize>(entries: &[T]) -> Result<String, Box<dyn std::error::Error>> {
     let mut w = Writer::from_writer(vec![]);
     for e in entries {
@@ -28,22 +19,140 @@ fn convert_entry_to_csv<T: Serialize>(entries: &[T]) -> Result<String, Box<dyn s
 }
 
 pub(crate) mod query {
-    use arrow::record_batch::RecordBatch;
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex, PoisonError},
+    };
+
+    use arrow::{csv, datatypes::SchemaRef, ipc::writer::StreamWriter, record_batch::RecordBatch};
     use axum::{
-        body::Bytes,
-        http::StatusCode,
+        body::{Body, Bytes},
+        extract::Query,
+        http::{header, HeaderMap, StatusCode},
         response::{IntoResponse, Response},
-        Extension,
+        Extension, Json,
     };
-    use std::sync::Arc;
+    use datafusion::{error::DataFusionError, parquet::arrow::ArrowWriter};
+    use futures::StreamExt;
+    use serde::Deserialize;
     use tokio::sync::RwLock;
 
-    use crate::datafusion::DataFusion;
+    use crate::{
+        auth::Principal,
+        datafusion::DataFusion,
+        query::{cache, limits, Protocol, QueryError, QueryErrorCode, QueryLimits},
+    };
+
+    type EncodeError = Box<dyn std::error::Error + Send + Sync>;
+
+    /// The formats query results can be returned in, selected by the `format` query parameter
+    /// or the `Accept` header.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ResultFormat {
+        Json,
+        Ndjson,
+        Csv,
+        Arrow,
+        Parquet,
+    }
+
+    impl ResultFormat {
+        const ALL: [ResultFormat; 5] = [
+            ResultFormat::Json,
+            ResultFormat::Ndjson,
+            ResultFormat::Csv,
+            ResultFormat::Arrow,
+            ResultFormat::Parquet,
+        ];
+
+        fn content_type(self) -> &'static str {
+            match self {
+                ResultFormat::Json => "application/json",
+                ResultFormat::Ndjson => "application/x-ndjson",
+                ResultFormat::Csv => "text/csv",
+                ResultFormat::Arrow => "application/vnd.apache.arrow.stream",
+                ResultFormat::Parquet => "application/vnd.apache.parquet",
+            }
+        }
+
+        /// Picks the first format listed in the `Accept` header, defaulting to JSON when the header
+        /// is missing or accepts any type.
+        ///
+        /// Returns `None` if none of the accepted media types is supported.
+        fn from_accept(accept: Option<&str>) -> Option<Self> {
+            let Some(accept) = accept else {
+                return Some(ResultFormat::Json);
+            };
+
+            accept
+                .split(',')
+                .map(|media_type| media_type.split(';').next().unwrap_or_default().trim())
+                .find_map(|media_type| match media_type {
+                    "" | "*/*" | "application/*" => Some(ResultFormat::Json),
+                    "text/*" => Some(ResultFormat::Csv),
+                    _ => ResultFormat::ALL
+                        .into_iter()
+                        .find(|format| format.content_type().eq_ignore_ascii_case(media_type)),
+                })
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct QueryParams {
+        format: Option<ResultFormat>,
+        /// A timeout stricter than the runtime's `--query-timeout`, i.e. `30s`.
+        timeout: Option<String>,
+        /// A result size limit stricter than the runtime's `--query-max-result-size`, i.e. `10MiB`.
+        max_result_size: Option<String>,
+    }
+
+    impl QueryParams {
+        fn limit_overrides(&self) -> Result<QueryLimits, String> {
+            Ok(QueryLimits {
+                timeout: self
+                    .timeout
+                    .as_deref()
+                    .map(limits::parse_duration)
+                    .transpose()?,
+                max_result_size: self
+                    .max_result_size
+                    .as_deref()
+                    .map(limits::parse_byte_size)
+                    .transpose()?,
+            })
+        }
+    }
 
     pub(crate) async fn post(
         Extension(df): Extension<Arc<RwLock<DataFusion>>>,
+        principal: Option<Extension<Principal>>,
+        Query(params): Query<QueryParams>,
+        headers: HeaderMap,
         body: Bytes,
     ) -> Response {
+        let format = match params.format {
+            Some(format) => format,
+            None => {
+                let accept = headers
+                    .get(header::ACCEPT)
+                    .and_then(|value| value.to_str().ok());
+                let Some(format) = ResultFormat::from_accept(accept) else {
+                    return (
+                        StatusCode::NOT_ACCEPTABLE,
+                        "Supported formats are application/json, application/x-ndjson, text/csv, application/vnd.apache.arrow.stream and application/vnd.apache.parquet",
+                    )
+                        .into_response();
+                };
+                format
+            }
+        };
+
+        let overrides = match params.limit_overrides() {
+            Ok(overrides) => overrides,
+            Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        };
+
         let query = match String::from_utf8(body.to_vec()) {
             Ok(query) => query,
             Err(e) => {
@@ -52,46 +161,281 @@ pub(crate) mod query {
             }
         };
 
-        let data_frame = match df.read().await.ctx.sql(&query).await {
+        let principal = principal.map(|Extension(principal)| principal);
+        let user_agent = headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok());
+
+        let df = df.read().await;
+        let tracker = df.query_history().start(
+            query.as_str(),
+            Protocol::Http,
+            principal.as_ref().map(|principal| principal.name.as_str()),
+            user_agent,
+        );
+        // `ctx.sql` runs DDL while planning, so the plan is checked before anything is executed.
+        let plan = match df.ctx.state().create_logical_plan(&query).await {
+            Ok(plan) => plan,
+            Err(e) => {
+                tracing::debug!("Error planning query: {e}");
+                tracker.fail(&e);
+                return query_error_response(&e);
+            }
+        };
+
+        if let Err(e) = df.access_policy().check_plan(principal.as_ref(), &plan) {
+            tracing::debug!("Query not allowed: {e}");
+            tracker.fail(&e);
+            return error_response(QueryError::from(&e));
+        }
+
+        let data_frame = match df.ctx.execute_logical_plan(plan).await {
             Ok(data_frame) => data_frame,
             Err(e) => {
                 tracing::debug!("Error running query: {e}");
-                return (StatusCode::BAD_REQUEST, query.to_string()).into_response();
+                tracker.fail(&e);
+                return query_error_response(&e);
             }
         };
+        let limits = df.query_limits().with_overrides(overrides);
+        let cache_key = match df.query_cache_key(data_frame.logical_plan()) {
+            Ok(cache_key) => cache_key,
+            Err(e) => {
+                tracker.fail(&e);
+                return query_error_response(&e);
+            }
+        };
+        drop(df);
 
-        let results = match data_frame.collect().await {
-            Ok(results) => results,
+        let mut batches = match cache::execute_stream(data_frame, cache_key).await {
+            Ok(batches) => tracker.track_stream(limits.limit_stream(batches)),
             Err(e) => {
+                tracing::debug!("Error executing query: {e}");
+                tracker.fail(&e);
+                return query_error_response(&e);
+            }
+        };
+
+        // Wait for the first batch so that queries failing early get a proper status code
+        let first_batch = match batches.next().await {
+            Some(Ok(batch)) => Some(batch),
+            Some(Err(e)) => {
                 tracing::debug!("Error collecting results: {e}");
+                return query_error_response(&e);
+            }
+            None => None,
+        };
+
+        let mut encoder = match BatchEncoder::try_new(format, batches.schema()) {
+            Ok(encoder) => encoder,
+            Err(e) => {
+                tracing::debug!("Error encoding results: {e}");
                 return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
             }
         };
 
-        let buf = Vec::new();
-        let mut writer = arrow_json::ArrayWriter::new(buf);
+        // Errors after the first chunk is sent can't change the status code anymore, they abort the response instead.
+        let body = async_stream::stream! {
+            yield encoder.start();
+            if let Some(batch) = first_batch {
+                yield encoder.encode(&batch);
+            }
+            while let Some(batch) = batches.next().await {
+                let batch = match batch {
+                    Ok(batch) => batch,
+                    Err(e) => {
+                        tracing::debug!("Error collecting results: {e}");
+                        yield Err(e.into());
+                        return;
+                    }
+                };
+                yield encoder.encode(&batch);
+            }
+            yield encoder.finish();
+        };
+
+        (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, format.content_type())],
+            Body::from_stream(body),
+        )
+            .into_response()
+    }
+
+    fn query_error_response(e: &DataFusionError) -> Response {
+        error_response(QueryError::from(e))
+    }
+
+    /// Planning errors are the client's fault, failures while running the query are the runtime's.
+    fn error_response(error: QueryError) -> Response {
+        let status = if error.is_client_error() {
+            StatusCode::BAD_REQUEST
+        } else if error.code == QueryErrorCode::AccessDenied {
+            StatusCode::FORBIDDEN
+        } else if error.code == QueryErrorCode::Timeout {
+            StatusCode::GATEWAY_TIMEOUT
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+
+        (status, Json(error)).into_response()
+    }
 
-        if let Err(e) =
-            writer.write_batches(results.iter().collect::<Vec<&RecordBatch>>().as_slice())
-        {
-            tracing::debug!("Error converting results to JSON: {e}");
-            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    /// A `Write` sink whose contents are taken after each batch, so the writers that need to own
+    /// their output can be streamed.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedBuffer {
+        fn take(&self) -> Bytes {
+            let mut buffer = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+            Bytes::from(std::mem::take(&mut *buffer))
         }
-        if let Err(e) = writer.finish() {
-            tracing::debug!("Error finishing JSON conversion: {e}");
-            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .extend_from_slice(buf);
+            Ok(buf.len())
         }
 
-        let buf = writer.into_inner();
-        let res = match String::from_utf8(buf) {
-            Ok(res) => res,
-            Err(e) => {
-                tracing::debug!("Error converting JSON buffer to string: {e}");
-                return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Encodes record batches into the chunks of the response body, one chunk per batch.
+    enum BatchEncoder {
+        Json { has_rows: bool },
+        Ndjson,
+        Csv { with_header: bool },
+        Arrow(Option<StreamWriter<SharedBuffer>>, SharedBuffer),
+        Parquet(Option<Box<ArrowWriter<SharedBuffer>>>, SharedBuffer),
+    }
+
+    impl BatchEncoder {
+        fn try_new(format: ResultFormat, schema: SchemaRef) -> Result<Self, EncodeError> {
+            let buffer = SharedBuffer::default();
+            Ok(match format {
+                ResultFormat::Json => BatchEncoder::Json { has_rows: false },
+                ResultFormat::Ndjson => BatchEncoder::Ndjson,
+                ResultFormat::Csv => BatchEncoder::Csv { with_header: true },
+                ResultFormat::Arrow => BatchEncoder::Arrow(
+                    Some(StreamWriter::try_new(buffer.clone(), &schema)?),
+                    buffer,
+                ),
+                ResultFormat::Parquet => BatchEncoder::Parquet(
+                    Some(Box::new(ArrowWriter::try_new(
+                        buffer.clone(),
+                        schema,
+                        None,
+                    )?)),
+                    buffer,
+                ),
+            })
+        }
+
+        fn start(&mut self) -> Result<Bytes, EncodeError> {
+            match self {
+                BatchEncoder::Json { .. } => Ok(Bytes::from_static(b"[")),
+                BatchEncoder::Arrow(_, buffer) | BatchEncoder::Parquet(_, buffer) => {
+                    Ok(buffer.take())
+                }
+                BatchEncoder::Ndjson | BatchEncoder::Csv { .. } => Ok(Bytes::new()),
             }
-        };
+        }
 
-        (StatusCode::OK, res).into_response()
+        fn encode(&mut self, batch: &RecordBatch) -> Result<Bytes, EncodeError> {
+            match self {
+                BatchEncoder::Json { has_rows } => {
+                    let lines = ndjson(batch)?;
+                    let mut buf = Vec::with_capacity(lines.len());
+                    for line in lines.split(|b| *b == b'\n').filter(|line| !line.is_empty()) {
+                        if *has_rows {
+                            buf.push(b',');
+                        }
+                        buf.extend_from_slice(line);
+                        *has_rows = true;
+                    }
+                    Ok(buf.into())
+                }
+                BatchEncoder::Ndjson => Ok(ndjson(batch)?.into()),
+                BatchEncoder::Csv { with_header } => {
+                    let mut writer = csv::WriterBuilder::new()
+                        .with_header(*with_header)
+                        .build(Vec::new());
+                    writer.write(batch)?;
+                    *with_header = false;
+                    Ok(writer.into_inner().into())
+                }
+                BatchEncoder::Arrow(writer, buffer) => {
+                    if let Some(writer) = writer {
+                        writer.write(batch)?;
+                    }
+                    Ok(buffer.take())
+                }
+                BatchEncoder::Parquet(writer, buffer) => {
+                    if let Some(writer) = writer {
+                        writer.write(batch)?;
+                        // Write a row group per batch instead of buffering the whole result
+                        writer.flush()?;
+                    }
+                    Ok(buffer.take())
+                }
+            }
+        }
+
+        fn finish(&mut self) -> Result<Bytes, EncodeError> {
+            match self {
+                BatchEncoder::Json { .. } => Ok(Bytes::from_static(b"]")),
+                BatchEncoder::Arrow(writer, buffer) => {
+                    if let Some(mut writer) = writer.take() {
+                        writer.finish()?;
+                    }
+                    Ok(buffer.take())
+                }
+                BatchEncoder::Parquet(writer, buffer) => {
+                    if let Some(writer) = writer.take() {
+                        writer.close()?;
+                    }
+                    Ok(buffer.take())
+                }
+                BatchEncoder::Ndjson | BatchEncoder::Csv { .. } => Ok(Bytes::new()),
+            }
+        }
+    }
+
+    fn ndjson(batch: &RecordBatch) -> Result<Vec<u8>, EncodeError> {
+        let mut writer = arrow_json::LineDelimitedWriter::new(Vec::new());
+        writer.write(batch)?;
+        writer.finish()?;
+        Ok(writer.into_inner())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_format_from_accept() {
+            assert_eq!(ResultFormat::from_accept(None), Some(ResultFormat::Json));
+            assert_eq!(
+                ResultFormat::from_accept(Some("*/*")),
+                Some(ResultFormat::Json)
+            );
+            assert_eq!(
+                ResultFormat::from_accept(Some("application/vnd.apache.arrow.stream")),
+                Some(ResultFormat::Arrow)
+            );
+            assert_eq!(
+                ResultFormat::from_accept(Some("application/xml, text/csv;q=0.9, */*;q=0.1")),
+                Some(ResultFormat::Csv)
+            );
+            assert_eq!(ResultFormat::from_accept(Some("application/xml")), None);
+        }
     }
 }
 
@@ -268,7 +612,8 @@ pub(crate) mod datasets {
 
     use app::App;
     use axum::{
-        extract::Query,
+        body::Bytes,
+        extract::{Path, Query},
         http::status,
         response::{IntoResponse, Response},
         Extension, Json,
@@ -278,7 +623,12 @@ pub(crate) mod datasets {
     use tokio::sync::RwLock;
     use tract_core::tract_data::itertools::Itertools;
 
-    use crate::datafusion::DataFusion;
+    use crate::{
+        auth::Principal,
+        datafusion::DataFusion,
+        refresh::{RefreshRequest, RefreshResponse},
+        status::DatasetState,
+    };
 
     use super::{convert_entry_to_csv, Format};
 
@@ -309,7 +659,11 @@ pub(crate) mod datasets {
         pub depends_on: Option<String>,
 
         #[serde(skip_serializing_if = "Option::is_none")]
-        pub status: Option<super::Status>,
+        pub status: Option<DatasetState>,
+
+        /// The last error of the dataset, only present if the status was requested.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub error: Option<Option<String>>,
     }
 
     pub(crate) async fn get(
@@ -341,29 +695,30 @@ pub(crate) mod datasets {
             datasets.retain(|d| !d.is_view());
         }
 
-        let df_read = df.read().await;
+        let runtime_status = df.read().await.status();
 
         let resp = datasets
             .iter()
-            .map(|d| DatasetResponseItem {
-                from: d.from.clone(),
-                name: d.name.clone(),
-                replication_enabled: d.replication.as_ref().is_some_and(|f| f.enabled),
-                acceleration_enabled: d.acceleration.as_ref().is_some_and(|f| f.enabled),
-                depends_on: if d.depends_on.is_empty() {
-                    None
-                } else {
-                    Some(d.depends_on.join(", "))
-                },
-                status: if params.status {
-                    if df_read.table_exists(d.name.as_str()) {
-                        Some(super::Status::Ready)
+            .map(|d| {
+                let dataset_status = params
+                    .status
+                    .then(|| runtime_status.dataset(&d.name).unwrap_or_default());
+                DatasetResponseItem {
+                    from: d.from.clone(),
+                    name: d.name.clone(),
+                    replication_enabled: d.replication.as_ref().is_some_and(|f| f.enabled),
+                    acceleration_enabled: d.acceleration.as_ref().is_some_and(|f| f.enabled),
+                    depends_on: if d.depends_on.is_empty() {
+                        None
                     } else {
-                        Some(super::Status::Error)
-                    }
-                } else {
-                    None
-                },
+                        Some(d.depends_on.join(", "))
+                    },
+                    status: dataset_status.as_ref().map(|status| status.state),
+                    error: dataset_status.map(|status| match status.state {
+                        DatasetState::Error => status.last_error,
+                        _ => None,
+                    }),
+                }
             })
             .collect_vec();
 
@@ -378,6 +733,51 @@ pub(crate) mod datasets {
             },
         }
     }
+
+    /// Refreshes the acceleration of a dataset immediately, optionally only the rows matching the
+    /// `filter` of the JSON body.
+    pub(crate) async fn refresh(
+        Extension(df): Extension<Arc<RwLock<DataFusion>>>,
+        principal: Option<Extension<Principal>>,
+        Path(name): Path<String>,
+        body: Bytes,
+    ) -> Response {
+        let request: RefreshRequest = if body.is_empty() {
+            RefreshRequest::default()
+        } else {
+            match serde_json::from_slice(&body) {
+                Ok(request) => request,
+                Err(e) => {
+                    return (status::StatusCode::BAD_REQUEST, e.to_string()).into_response();
+                }
+            }
+        };
+
+        let refresher = {
+            let df = df.read().await;
+            let principal = principal.as_ref().map(|Extension(principal)| principal);
+            if let Err(e) = df.access_policy().check_write(principal, &name) {
+                return (status::StatusCode::FORBIDDEN, e.to_string()).into_response();
+            }
+
+            match df.dataset_refresher(&name) {
+                Ok(refresher) => refresher,
+                Err(e) => return (status::StatusCode::NOT_FOUND, e.to_string()).into_response(),
+            }
+        };
+
+        match refresher.refresh(request.filter.as_deref()).await {
+            Ok(record) => {
+                let status_code = if record.error.is_some() {
+                    status::StatusCode::INTERNAL_SERVER_ERROR
+                } else {
+                    status::StatusCode::OK
+                };
+                (status_code, Json(RefreshResponse::from(&record))).into_response()
+            }
+            Err(e) => (status::StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        }
+    }
 }
 
 pub(crate) mod models {
@@ -4,12 +4,14 @@ use std::borrow::Borrow;
 use std::net::SocketAddr;
 use std::{collections::HashMap, sync::Arc};
 
+use ::datafusion::datasource::TableProvider;
 use app::App;
 use config::Config;
 use model::Model;
 pub use notify::Error as NotifyError;
 use secrets::spicepod_secret_store_type;
 use snafu::prelude::*;
+use spicepod::component::access::Role;
 use spicepod::component::dataset::Dataset;
 use spicepod::component::dataset::Mode;
 use spicepod::component::model::Model as SpicepodModel;
@@ -17,13 +19,19 @@ use std::time::Duration;
 use tokio::time::sleep;
 use tokio::{signal, sync::RwLock};
 
-use crate::{dataconnector::DataConnector, datafusion::DataFusion};
+use crate::{
+    dataconnector::DataConnector, datafusion::DataFusion, dependencygraph::DependencyGraph,
+    reload::DatasetChange, status::DatasetState,
+};
+pub mod accesscontrol;
+pub mod auth;
 pub mod config;
 pub mod databackend;
 pub mod dataconnector;
 pub mod datafusion;
 pub mod datapublisher;
 pub mod dataupdate;
+pub mod dependencygraph;
 mod flight;
 mod http;
 pub mod model;
@@ -32,7 +40,16 @@ pub mod modelruntime;
 pub mod modelsource;
 mod opentelemetry;
 pub mod podswatcher;
+pub mod query;
+pub mod refresh;
+pub mod reload;
+pub mod retention;
+pub mod status;
+pub mod systemtables;
+#[cfg(test)]
+pub(crate) mod testutil;
 pub mod timing;
+pub mod tls;
 pub(crate) mod tracers;
 
 #[derive(Debug, Snafu)]
@@ -46,6 +63,9 @@ pub enum Error {
     #[snafu(display("Unable to start OpenTelemetry server: {source}"))]
     UnableToStartOpenTelemetryServer { source: opentelemetry::Error },
 
+    #[snafu(display("Unable to load TLS configuration: {source}"))]
+    UnableToLoadTls { source: tls::Error },
+
     #[snafu(display("Unknown data source: {data_source}"))]
     UnknownDataSource { data_source: String },
 
@@ -75,6 +95,12 @@ pub enum Error {
         source: spicepod::component::dataset::Error,
     },
 
+    #[snafu(display("Unable to reload dataset {dataset} in place: {reason}"))]
+    UnableToReloadInPlace { dataset: String, reason: String },
+
+    #[snafu(display("Unable to swap in the new acceleration: {source}"))]
+    UnableToSwapAcceleration { source: datafusion::Error },
+
     #[snafu(display("Unable to attach data connector {data_connector}: {source}"))]
     UnableToAttachDataConnector {
         source: datafusion::Error,
@@ -92,6 +118,8 @@ pub struct Runtime {
     pub pods_watcher: podswatcher::PodsWatcher,
     pub secrets_provider: Arc<RwLock<secrets::SecretsProvider>>,
 
+    /// The dependencies between the datasets of the app, used to load datasets after their dependencies.
+    dependency_graph: Arc<RwLock<DependencyGraph>>,
     spaced_tracer: Arc<tracers::SpacedTracer>,
 }
 
@@ -110,6 +138,7 @@ impl Runtime {
             models: Arc::new(RwLock::new(HashMap::new())),
             pods_watcher,
             secrets_provider: Arc::new(RwLock::new(secrets::SecretsProvider::new())),
+            dependency_graph: Arc::new(RwLock::new(DependencyGraph::default())),
             spaced_tracer: Arc::new(tracers::SpacedTracer::new(Duration::from_secs(15))),
         }
     }
@@ -132,24 +161,138 @@ impl Runtime {
         }
     }
 
+    pub async fn load_access_policy(&self) {
+        let app_lock = self.app.read().await;
+        let roles = app_lock
+            .as_ref()
+            .map(|app| app.roles.clone())
+            .unwrap_or_default();
+        self.set_access_policy(roles).await;
+    }
+
+    async fn set_access_policy(&self, roles: Vec<Role>) {
+        let auth_config = &self.config.auth;
+        if !roles.is_empty() {
+            for (endpoint, enabled) in [
+                ("Flight", auth_config.flight_auth),
+                ("HTTP", auth_config.http_auth),
+            ] {
+                if !enabled {
+                    tracing::warn!("Access control roles are defined but {endpoint} authentication is disabled, {endpoint} requests for datasets will be denied");
+                }
+            }
+        }
+
+        self.df
+            .write()
+            .await
+            .set_access_policy(accesscontrol::AccessPolicy::new(roles));
+    }
+
+    /// Registers the `datasets`, `accelerations`, `dataset_refreshes` and `models` tables in the
+    /// `spice.runtime` schema.
+    pub async fn load_system_tables(&self) {
+        let df = self.df.read().await;
+        let status = df.status();
+
+        let tables: Vec<(&str, Arc<dyn TableProvider>)> = vec![
+            (
+                "datasets",
+                Arc::new(systemtables::DatasetsTable::new(
+                    Arc::clone(&self.app),
+                    Arc::clone(&status),
+                )),
+            ),
+            (
+                "accelerations",
+                Arc::new(systemtables::AccelerationsTable::new(
+                    Arc::clone(&self.app),
+                    Arc::clone(&status),
+                )),
+            ),
+            (
+                "dataset_refreshes",
+                Arc::new(systemtables::DatasetRefreshesTable::new(status)),
+            ),
+            (
+                "models",
+                Arc::new(systemtables::ModelsTable::new(
+                    Arc::clone(&self.app),
+                    Arc::clone(&self.models),
+                )),
+            ),
+        ];
+
+        for (name, table) in tables {
+            if let Err(e) = df.register_runtime_table(name, table) {
+                tracing::warn!("Unable to register system table {name}: {e}");
+            }
+        }
+    }
+
+    /// Loads the datasets of the app, each after the datasets it depends on.
+    ///
+    /// No dataset is loaded if a dataset depends on an unknown dataset or the dependencies form a cycle.
     pub async fn load_datasets(&self) {
         let app_lock = self.app.read().await;
         if let Some(app) = app_lock.as_ref() {
-            for ds in &app.datasets {
+            let dependency_graph = match DependencyGraph::new(&app.datasets) {
+                Ok(dependency_graph) => dependency_graph,
+                Err(e) => {
+                    tracing::error!("Unable to load datasets: {e}");
+                    return;
+                }
+            };
+
+            let mut current_graph = self.dependency_graph.write().await;
+            *current_graph = dependency_graph;
+            let load_order = current_graph.load_order().unwrap_or_default();
+
+            // Every dataset is marked as loading up front, so a dataset that starts waiting for its
+            // dependencies before they start loading doesn't see them as missing.
+            let status = self.df.read().await.status();
+            for ds in &load_order {
+                status.dataset_loading(&ds.name);
+            }
+            for ds in load_order {
                 self.load_dataset(ds);
             }
         }
     }
 
+    /// Loads `ds` in the background once the datasets it depends on are loaded.
     pub fn load_dataset(&self, ds: &Dataset) {
-        let df = Arc::clone(&self.df);
-        let spaced_tracer = Arc::clone(&self.spaced_tracer);
-        let shared_secrets_provider: Arc<RwLock<secrets::SecretsProvider>> =
-            Arc::clone(&self.secrets_provider);
-
-        let ds = ds.clone();
+        Runtime::spawn_load_dataset(
+            Arc::clone(&self.df),
+            Arc::clone(&self.secrets_provider),
+            Arc::clone(&self.spaced_tracer),
+            Arc::clone(&self.dependency_graph),
+            ds.clone(),
+        );
+    }
 
+    fn spawn_load_dataset(
+        df: Arc<RwLock<DataFusion>>,
+        shared_secrets_provider: Arc<RwLock<secrets::SecretsProvider>>,
+        spaced_tracer: Arc<tracers::SpacedTracer>,
+        dependency_graph: Arc<RwLock<DependencyGraph>>,
+        ds: Dataset,
+    ) {
         tokio::spawn(async move {
+            let status = df.read().await.status();
+            status.dataset_loading(&ds.name);
+
+            let dependencies = dependency_graph.read().await.dependencies(&ds.name);
+            for dependency in dependencies {
+                tracing::debug!("Dataset {} is waiting for dataset {dependency}", &ds.name);
+                // A failed dependency can still recover, i.e. once its source is reachable again.
+                while let Err(e) = status.wait_until_loaded(&dependency).await {
+                    warn_spaced!(spaced_tracer, "Unable to load dataset {}: {e}", &ds.name);
+                    status.set_error(&ds.name, &e);
+                    status.wait_until_recovered(&dependency).await;
+                }
+            }
+
             loop {
                 let secrets_provider = shared_secrets_provider.read().await;
 
@@ -172,6 +315,7 @@ impl Runtime {
                                 "Failed to get data connector from source for dataset {}, retrying: {err}",
                                 &ds.name
                             );
+                            status.dataset_retrying(&ds.name, &err);
                             sleep(Duration::from_secs(1)).await;
                             continue;
                         }
@@ -182,6 +326,7 @@ impl Runtime {
                     && !has_table_provider(&data_connector)
                 {
                     tracing::warn!("No acceleration specified for dataset: {}", ds.name);
+                    status.set_state(&ds.name, DatasetState::Disabled);
                     break;
                 };
 
@@ -202,29 +347,25 @@ impl Runtime {
                             "Failed to initialize data connector for dataset {}, retrying: {err}",
                             &ds.name
                         );
+                        status.dataset_retrying(&ds.name, &err);
                         sleep(Duration::from_secs(1)).await;
                         continue;
                     }
                 };
                 tracing::info!("Loaded dataset: {}", &ds.name);
-                let engine = ds.acceleration.map_or_else(
-                    || "None".to_string(),
-                    |acc| {
-                        if acc.enabled {
-                            acc.engine().to_string()
-                        } else {
-                            "None".to_string()
-                        }
-                    },
-                );
-                metrics::gauge!("datasets_count", "engine" => engine).increment(1.0);
+                metrics::gauge!("datasets_count", "engine" => dataset_engine(&ds)).increment(1.0);
                 break;
             }
         });
     }
 
     pub async fn remove_dataset(&self, ds: &Dataset) {
-        let mut df = self.df.write().await;
+        Runtime::unload_dataset(&self.df, ds).await;
+    }
+
+    async fn unload_dataset(df: &RwLock<DataFusion>, ds: &Dataset) {
+        let mut df = df.write().await;
+        df.status().remove_dataset(&ds.name);
 
         if df.table_exists(&ds.name) {
             if let Err(e) = df.remove_table(&ds.name) {
@@ -234,22 +375,173 @@ impl Runtime {
         }
 
         tracing::info!("Unloaded dataset: {}", &ds.name);
-        let engine = ds.acceleration.as_ref().map_or_else(
-            || "None".to_string(),
-            |acc| {
-                if acc.enabled {
-                    acc.engine().to_string()
-                } else {
-                    "None".to_string()
+        metrics::gauge!("datasets_count", "engine" => dataset_engine(ds)).decrement(1.0);
+    }
+
+    /// Applies the changes from `current` to `ds`.
+    ///
+    /// Only the data connector is replaced if the refresh settings changed, and a new acceleration is
+    /// loaded in the background if the acceleration changed, so the dataset can be queried throughout.
+    /// Any other change unloads the dataset and loads it again. The datasets that depend on it are
+    /// reloaded whenever its table is replaced.
+    pub async fn update_dataset(&self, current: &Dataset, ds: &Dataset) {
+        match DatasetChange::between(current, ds) {
+            None => {}
+            Some(DatasetChange::RefreshSettings) => {
+                if let Err(e) = self.replace_data_connector(ds).await {
+                    tracing::warn!("{e}, reloading it");
+                    self.reload_dataset(current, ds).await;
                 }
-            },
-        );
-        metrics::gauge!("datasets_count", "engine" => engine).decrement(1.0);
+            }
+            // Writes are routed to the current acceleration through its publishers, which aren't swapped.
+            Some(DatasetChange::Acceleration) if ds.mode() != Mode::ReadWrite => {
+                self.swap_acceleration(current, ds);
+            }
+            Some(_) => self.reload_dataset(current, ds).await,
+        }
     }
 
-    pub async fn update_dataset(&self, ds: &Dataset) {
-        self.remove_dataset(ds).await;
+    async fn reload_dataset(&self, current: &Dataset, ds: &Dataset) {
+        self.remove_dataset(current).await;
         self.load_dataset(ds);
+        Runtime::reload_dependents(
+            &self.df,
+            &self.secrets_provider,
+            &self.spaced_tracer,
+            &self.dependency_graph,
+            &ds.name,
+        )
+        .await;
+    }
+
+    /// Reloads the datasets that depend on `dataset`, i.e. views, which keep reading the table `dataset`
+    /// had when they were loaded.
+    async fn reload_dependents(
+        df: &Arc<RwLock<DataFusion>>,
+        secrets_provider: &Arc<RwLock<secrets::SecretsProvider>>,
+        spaced_tracer: &Arc<tracers::SpacedTracer>,
+        dependency_graph: &Arc<RwLock<DependencyGraph>>,
+        dataset: &str,
+    ) {
+        let dependents: Vec<Dataset> = dependency_graph
+            .read()
+            .await
+            .dependents(dataset)
+            .into_iter()
+            .cloned()
+            .collect();
+
+        for ds in dependents {
+            tracing::info!(
+                "Reloading dataset {} since it depends on {dataset}",
+                &ds.name
+            );
+            Runtime::unload_dataset(df, &ds).await;
+            Runtime::spawn_load_dataset(
+                Arc::clone(df),
+                Arc::clone(secrets_provider),
+                Arc::clone(spaced_tracer),
+                Arc::clone(dependency_graph),
+                ds,
+            );
+        }
+    }
+
+    async fn replace_data_connector(&self, ds: &Dataset) -> Result<()> {
+        let data_connector =
+            Runtime::get_dataconnector_from_source_for(ds, &self.secrets_provider).await?;
+
+        self.df
+            .write()
+            .await
+            .replace_data_connector(ds.clone(), data_connector)
+            .map_err(|e| Error::UnableToReloadInPlace {
+                dataset: ds.name.clone(),
+                reason: e.to_string(),
+            })?;
+
+        tracing::info!("Updated the refresh settings of dataset: {}", &ds.name);
+        Ok(())
+    }
+
+    /// Loads the new acceleration of `ds` in the background and swaps it in once it is loaded, keeping
+    /// the current acceleration if loading fails.
+    fn swap_acceleration(&self, current: &Dataset, ds: &Dataset) {
+        let df = Arc::clone(&self.df);
+        let secrets_provider = Arc::clone(&self.secrets_provider);
+        let spaced_tracer = Arc::clone(&self.spaced_tracer);
+        let dependency_graph = Arc::clone(&self.dependency_graph);
+        let current = current.clone();
+        let ds = ds.clone();
+
+        tokio::spawn(async move {
+            let result: Result<()> = async {
+                let data_connector =
+                    Runtime::get_dataconnector_from_source_for(&ds, &secrets_provider).await?;
+                let mut staged = df
+                    .read()
+                    .await
+                    .stage_acceleration(&ds, data_connector, Arc::clone(&secrets_provider))
+                    .await
+                    .context(UnableToCreateBackendSnafu)?;
+
+                tracing::info!("Loading the new acceleration of dataset: {}", &ds.name);
+                staged
+                    .loaded()
+                    .await
+                    .map_err(|reason| Error::UnableToReloadInPlace {
+                        dataset: ds.name.clone(),
+                        reason,
+                    })?;
+
+                df.write()
+                    .await
+                    .swap_acceleration(staged)
+                    .await
+                    .context(UnableToSwapAccelerationSnafu)
+            }
+            .await;
+
+            match result {
+                Ok(()) => {
+                    tracing::info!("Swapped in the new acceleration of dataset: {}", &ds.name);
+                    metrics::gauge!("datasets_count", "engine" => dataset_engine(&current))
+                        .decrement(1.0);
+                    metrics::gauge!("datasets_count", "engine" => dataset_engine(&ds))
+                        .increment(1.0);
+                    Runtime::reload_dependents(
+                        &df,
+                        &secrets_provider,
+                        &spaced_tracer,
+                        &dependency_graph,
+                        &ds.name,
+                    )
+                    .await;
+                }
+                Err(e) => {
+                    tracing::warn!("{e}, keeping the current acceleration");
+                }
+            }
+        });
+    }
+
+    /// Creates the data connector of `ds`, which must have one.
+    async fn get_dataconnector_from_source_for(
+        ds: &Dataset,
+        secrets_provider: &RwLock<secrets::SecretsProvider>,
+    ) -> Result<Box<dyn DataConnector + Send>> {
+        let source = ds.source();
+        let secrets_provider = secrets_provider.read().await;
+        Runtime::get_dataconnector_from_source(
+            &source,
+            &secrets_provider,
+            Arc::new(ds.params.clone()),
+        )
+        .await?
+        .ok_or_else(|| Error::UnableToReloadInPlace {
+            dataset: ds.name.clone(),
+            reason: format!("{source} datasets have no data connector"),
+        })
     }
 
     async fn get_dataconnector_from_source(
@@ -315,6 +607,13 @@ impl Runtime {
                         data_connector: source,
                     })?,
             ))),
+            "file" => Ok(Some(Box::new(
+                dataconnector::file::File::new(secrets_provider.get_secret(source).await, params)
+                    .await
+                    .context(UnableToInitializeDataConnectorSnafu {
+                        data_connector: source,
+                    })?,
+            ))),
             "localhost" => Ok(None),
             "debug" => Ok(Some(Box::new(dataconnector::debug::DebugSource {}))),
             _ => UnknownDataConnectorSnafu {
@@ -325,7 +624,7 @@ impl Runtime {
     }
 
     async fn initialize_dataconnector(
-        data_connector: Option<Box<dyn DataConnector + Send>>,
+        mut data_connector: Option<Box<dyn DataConnector + Send>>,
         df: Arc<RwLock<DataFusion>>,
         source: &str,
         ds: impl Borrow<Dataset>,
@@ -333,26 +632,34 @@ impl Runtime {
     ) -> Result<()> {
         let ds = ds.borrow();
         let view_sql = ds.view_sql().context(InvalidSQLViewSnafu)?;
-        let data_backend_publishing_enabled =
-            ds.mode() == Mode::ReadWrite || data_connector.is_none();
+        let is_accelerated = ds.acceleration.as_ref().is_some_and(|acc| acc.enabled);
 
         if view_sql.is_some() {
-            df.read()
-                .await
-                .attach_view(ds)
-                .context(UnableToAttachViewSnafu)?;
-            return Ok(());
-        }
-
-        if ds.acceleration.is_none() || ds.acceleration.as_ref().map_or(false, |acc| !acc.enabled) {
-            if let Some(data_connector) = data_connector {
+            if !is_accelerated {
                 df.read()
                     .await
-                    .attach_mesh(ds, data_connector)
+                    .attach_view(ds)
                     .await
-                    .context(UnableToAttachDataConnectorSnafu {
+                    .context(UnableToAttachViewSnafu)?;
+                return Ok(());
+            }
+
+            // Accelerated views are materialized, their SQL is the data connector of the acceleration.
+            data_connector = Some(Box::new(df.read().await.materialized_view()));
+        }
+
+        let data_backend_publishing_enabled =
+            ds.mode() == Mode::ReadWrite || data_connector.is_none();
+
+        if !is_accelerated {
+            if let Some(data_connector) = data_connector {
+                let df = df.read().await;
+                df.attach_mesh(ds, data_connector).await.context(
+                    UnableToAttachDataConnectorSnafu {
                         data_connector: source,
-                    })?;
+                    },
+                )?;
+                df.status().set_state(&ds.name, DatasetState::Ready);
                 return Ok(());
             }
         }
@@ -365,6 +672,10 @@ impl Runtime {
             .context(UnableToCreateBackendSnafu)?;
         let data_backend = Arc::new(data_backend);
 
+        df.write()
+            .await
+            .attach_retention(ds.clone(), Arc::clone(&data_backend));
+
         if data_backend_publishing_enabled {
             df.write()
                 .await
@@ -375,39 +686,42 @@ impl Runtime {
                 })?;
         }
 
-        if let Some(data_connector) = data_connector {
-            let replicate = ds.replication.as_ref().map_or(false, |r| r.enabled);
-
-            // Attach data publisher only if replicate is true and mode is ReadWrite
-            if replicate && ds.mode() == Mode::ReadWrite {
-                if let Some(data_publisher) = data_connector.get_data_publisher() {
-                    df.write()
-                        .await
-                        .attach_publisher(&ds.name.clone(), ds.clone(), Arc::new(data_publisher))
-                        .await
-                        .context(UnableToAttachDataConnectorSnafu {
-                            data_connector: source,
-                        })?;
-                } else {
-                    tracing::warn!(
-                        "Data connector {source} does not support writes, but dataset {ds_name} is configured to replicate",
-                        ds_name = ds.name
-                    );
-                }
-            }
-
-            df.write()
+        let Some(data_connector) = data_connector else {
+            // Without a data connector the acceleration is only written to through the data publisher.
+            df.read()
                 .await
-                .attach_connector_to_publisher(
-                    ds.clone(),
-                    data_connector,
-                    Arc::clone(&data_backend),
-                )
-                .context(UnableToAttachDataConnectorSnafu {
-                    data_connector: source,
-                })?;
+                .status()
+                .set_state(&ds.name, DatasetState::Ready);
+            return Ok(());
+        };
+
+        let replicate = ds.replication.as_ref().map_or(false, |r| r.enabled);
+
+        // Attach data publisher only if replicate is true and mode is ReadWrite
+        if replicate && ds.mode() == Mode::ReadWrite {
+            if let Some(data_publisher) = data_connector.get_data_publisher() {
+                df.write()
+                    .await
+                    .attach_publisher(&ds.name.clone(), ds.clone(), Arc::new(data_publisher))
+                    .await
+                    .context(UnableToAttachDataConnectorSnafu {
+                        data_connector: source,
+                    })?;
+            } else {
+                tracing::warn!(
+                    "Data connector {source} does not support writes, but dataset {ds_name} is configured to replicate",
+                    ds_name = ds.name
+                );
+            }
         }
 
+        df.write()
+            .await
+            .attach_connector_to_publisher(ds.clone(), data_connector, Arc::clone(&data_backend))
+            .context(UnableToAttachDataConnectorSnafu {
+                data_connector: source,
+            })?;
+
         Ok(())
     }
 
@@ -473,6 +787,31 @@ impl Runtime {
     }
 
     pub async fn start_servers(&mut self, with_metrics: Option<SocketAddr>) -> Result<()> {
+        // The HTTP and Flight endpoints share the authenticator so tokens issued by the Flight handshake
+        // are also accepted by the HTTP API.
+        let auth_config = &self.config.auth;
+        let authenticator = if auth_config.flight_auth || auth_config.http_auth {
+            let secret = self
+                .secrets_provider
+                .read()
+                .await
+                .get_secret(&auth_config.auth_secret)
+                .await;
+            Some(Arc::new(auth::Authenticator::new(
+                secret.as_ref(),
+                Duration::from_secs(auth_config.token_ttl_secs),
+            )))
+        } else {
+            None
+        };
+
+        let tls = tls::TlsIdentity::load(&self.config.tls, &*self.secrets_provider.read().await)
+            .await
+            .context(UnableToLoadTlsSnafu)?;
+        if tls.as_ref().is_some_and(tls::TlsIdentity::is_mutual) {
+            tracing::info!("Mutual TLS is enabled, clients must present a certificate");
+        }
+
         let http_server_future = http::start(
             self.config.http_bind_address,
             self.app.clone(),
@@ -480,11 +819,22 @@ impl Runtime {
             self.models.clone(),
             self.config.clone().into(),
             with_metrics,
+            authenticator.clone().filter(|_| auth_config.http_auth),
+            tls.clone(),
         );
 
-        let flight_server_future = flight::start(self.config.flight_bind_address, self.df.clone());
-        let open_telemetry_server_future =
-            opentelemetry::start(self.config.open_telemetry_bind_address, self.df.clone());
+        let flight_server_future = flight::start(
+            self.config.flight_bind_address,
+            self.app.clone(),
+            self.df.clone(),
+            authenticator.filter(|_| auth_config.flight_auth),
+            tls.clone(),
+        );
+        let open_telemetry_server_future = opentelemetry::start(
+            self.config.open_telemetry_bind_address,
+            self.df.clone(),
+            tls,
+        );
         let pods_watcher_future = self.start_pods_watcher();
 
         tokio::select! {
@@ -512,13 +862,22 @@ impl Runtime {
                 tracing::debug!("Updated pods information: {:?}", new_app);
                 tracing::debug!("Previous pods information: {:?}", current_app);
 
+                let dependency_graph = match DependencyGraph::new(&new_app.datasets) {
+                    Ok(dependency_graph) => dependency_graph,
+                    Err(e) => {
+                        tracing::error!("Unable to apply the updated pods: {e}");
+                        continue;
+                    }
+                };
+                *self.dependency_graph.write().await = dependency_graph;
+
                 // check for new and updated datasets
                 for ds in &new_app.datasets {
                     if let Some(current_ds) =
                         current_app.datasets.iter().find(|d| d.name == ds.name)
                     {
                         if current_ds != ds {
-                            self.update_dataset(ds).await;
+                            self.update_dataset(current_ds, ds).await;
                         }
                     } else {
                         self.load_dataset(ds);
@@ -545,6 +904,10 @@ impl Runtime {
                     }
                 }
 
+                if current_app.roles != new_app.roles {
+                    self.set_access_policy(new_app.roles.clone()).await;
+                }
+
                 // Remove datasets that are no longer in the app
                 for ds in &current_app.datasets {
                     if !new_app.datasets.iter().any(|d| d.name == ds.name) {
@@ -554,6 +917,7 @@ impl Runtime {
 
                 *current_app = new_app;
             } else {
+                self.set_access_policy(new_app.roles.clone()).await;
                 *app_lock = Some(new_app);
             }
         }
@@ -562,6 +926,20 @@ impl Runtime {
     }
 }
 
+/// The label of the acceleration engine of `ds` in the `datasets_count` metric.
+fn dataset_engine(ds: &Dataset) -> String {
+    ds.acceleration.as_ref().map_or_else(
+        || "None".to_string(),
+        |acc| {
+            if acc.enabled {
+                acc.engine().to_string()
+            } else {
+                "None".to_string()
+            }
+        },
+    )
+}
+
 fn has_table_provider(data_connector: &Option<Box<dyn DataConnector + Send>>) -> bool {
     data_connector.is_some()
         && data_connector
@@ -40,6 +40,7 @@ use tonic_health::pb::health_server::HealthServer;
 use crate::datafusion::DataFusion;
 use crate::dataupdate::DataUpdate;
 use crate::dataupdate::UpdateType;
+use crate::tls::TlsIdentity;
 use crate::{tracers::OnceTracer, warn_once};
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -51,6 +52,11 @@ pub enum Error {
         source: tonic_0_9_0::transport::Error,
     },
 
+    #[snafu(display("Unable to configure TLS: {source}"))]
+    UnableToConfigureTls {
+        source: tonic_0_9_0::transport::Error,
+    },
+
     #[snafu(display("Failed to build record batch from OpenTelemetry metrics: {source}"))]
     FailedToBuildRecordBatch { source: arrow::error::ArrowError },
 
@@ -584,16 +590,28 @@ fn append_null(
     }
 }
 
-pub async fn start(bind_address: SocketAddr, data_fusion: Arc<RwLock<DataFusion>>) -> Result<()> {
+pub async fn start(
+    bind_address: SocketAddr,
+    data_fusion: Arc<RwLock<DataFusion>>,
+    tls: Option<TlsIdentity>,
+) -> Result<()> {
     let service = Service {
         data_fusion,
         once_tracer: OnceTracer::new(),
     };
     let svc = MetricsServiceServer::new(service).accept_compressed(CompressionEncoding::Gzip);
 
-    tracing::info!("Spice Runtime OpenTelemetry listening on {bind_address}");
+    let mut server = Server::builder();
+    if let Some(tls) = &tls {
+        server = server
+            .tls_config(tls.tonic_0_9_0_config())
+            .context(UnableToConfigureTlsSnafu)?;
+        tracing::info!("Spice Runtime OpenTelemetry listening on {bind_address} (TLS)");
+    } else {
+        tracing::info!("Spice Runtime OpenTelemetry listening on {bind_address}");
+    }
 
-    Server::builder()
+    server
         .add_service(create_health_service().await)
         .add_service(svc)
         .serve(bind_address)
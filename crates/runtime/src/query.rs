@@ -0,0 +1,11 @@
+//! Behavior shared by the SQL query surfaces of the runtime: the HTTP `/v1/sql` endpoint and Flight.
+
+pub mod cache;
+pub mod error;
+pub mod history;
+pub mod limits;
+
+pub use cache::QueryCache;
+pub use error::{QueryError, QueryErrorCode};
+pub use history::{Protocol, QueryHistory, QueryTracker};
+pub use limits::QueryLimits;
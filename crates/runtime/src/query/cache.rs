@@ -0,0 +1,475 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll},
+    time::SystemTime,
+};
+
+use arrow::{datatypes::SchemaRef, record_batch::RecordBatch};
+use datafusion::{
+    common::tree_node::{TreeNode, VisitRecursion},
+    dataframe::DataFrame,
+    error::Result as DataFusionResult,
+    execution::SendableRecordBatchStream,
+    logical_expr::{
+        expr::ScalarFunction, Exists, Expr, InSubquery, LogicalPlan, ScalarFunctionDefinition,
+        Volatility,
+    },
+    physical_plan::{stream::RecordBatchStreamAdapter, RecordBatchStream},
+};
+use futures::{Stream, StreamExt};
+use indexmap::IndexMap;
+use spicepod::component::dataset::Dataset;
+
+use crate::{
+    datapublisher::{AddDataResult, DataPublisher, DeleteDataResult, ExecuteDmlResult},
+    dataupdate::DataUpdate,
+};
+
+const INFORMATION_SCHEMA: &str = "information_schema";
+
+/// Caches the results of queries against accelerated datasets, evicting the least recently used
+/// results once the cache exceeds its maximum size.
+///
+/// Results are invalidated when any dataset they were computed from changes.
+pub struct QueryCache {
+    max_size: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: IndexMap<LogicalPlan, CachedResult>,
+    size: usize,
+    /// Incremented every time a dataset changes, so results computed while it changed aren't cached.
+    generations: HashMap<String, u64>,
+}
+
+struct CachedResult {
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+    size: usize,
+    tables: BTreeSet<String>,
+}
+
+/// Identifies the results of a cacheable query.
+pub struct CacheKey {
+    cache: Arc<QueryCache>,
+    plan: LogicalPlan,
+    /// The generation of each dataset the query reads when it started.
+    tables: BTreeSet<(String, u64)>,
+}
+
+impl QueryCache {
+    #[must_use]
+    pub fn new(max_size: usize) -> Self {
+        QueryCache {
+            max_size,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// The key of the results of `plan`, or `None` if they can't be cached.
+    ///
+    /// Only deterministic queries that exclusively read datasets for which `is_accelerated` is
+    /// true are cached, since the cache relies on the accelerations to notify it of changes.
+    pub fn key(
+        self: &Arc<Self>,
+        plan: &LogicalPlan,
+        is_accelerated: impl Fn(&str) -> bool,
+    ) -> DataFusionResult<Option<CacheKey>> {
+        let mut tables = BTreeSet::new();
+        if !collect_tables(plan, &mut tables)?
+            || tables.is_empty()
+            || !tables.iter().all(|table| is_accelerated(table))
+        {
+            return Ok(None);
+        }
+
+        let state = self.lock();
+        let tables = tables
+            .into_iter()
+            .map(|table| {
+                let generation = state.generations.get(&table).copied().unwrap_or_default();
+                (table, generation)
+            })
+            .collect();
+
+        Ok(Some(CacheKey {
+            cache: Arc::clone(self),
+            plan: plan.clone(),
+            tables,
+        }))
+    }
+
+    /// Evicts the results of every query that read `table`.
+    pub fn invalidate(&self, table: &str) {
+        let mut state = self.lock();
+        *state.generations.entry(table.to_string()).or_default() += 1;
+
+        let mut evicted = 0;
+        state.entries.retain(|_, entry| {
+            let keep = !entry.tables.contains(table);
+            if !keep {
+                evicted += entry.size;
+            }
+            keep
+        });
+        state.size -= evicted;
+        state.record_metrics();
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<SendableRecordBatchStream> {
+        let mut state = self.lock();
+        let Some((plan, entry)) = state.entries.shift_remove_entry(&key.plan) else {
+            metrics::counter!("query_cache_misses").increment(1);
+            return None;
+        };
+        metrics::counter!("query_cache_hits").increment(1);
+
+        let stream = RecordBatchStreamAdapter::new(
+            Arc::clone(&entry.schema),
+            futures::stream::iter(entry.batches.clone().into_iter().map(Ok)),
+        );
+        // Move the entry to the back, making it the most recently used
+        state.entries.insert(plan, entry);
+
+        Some(Box::pin(stream))
+    }
+
+    fn insert(&self, key: CacheKey, schema: SchemaRef, batches: Vec<RecordBatch>, size: usize) {
+        if size > self.max_size {
+            return;
+        }
+
+        let mut state = self.lock();
+        let unchanged = key.tables.iter().all(|(table, generation)| {
+            state.generations.get(table).copied().unwrap_or_default() == *generation
+        });
+        if !unchanged {
+            return;
+        }
+
+        if let Some(previous) = state.entries.shift_remove(&key.plan) {
+            state.size -= previous.size;
+        }
+        while state.size + size > self.max_size {
+            let Some((_, evicted)) = state.entries.shift_remove_index(0) else {
+                break;
+            };
+            state.size -= evicted.size;
+        }
+
+        state.size += size;
+        state.entries.insert(
+            key.plan,
+            CachedResult {
+                schema,
+                batches,
+                size,
+                tables: key.tables.into_iter().map(|(table, _)| table).collect(),
+            },
+        );
+        state.record_metrics();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl CacheState {
+    #[allow(clippy::cast_precision_loss)]
+    fn record_metrics(&self) {
+        metrics::gauge!("query_cache_size_bytes").set(self.size as f64);
+        metrics::gauge!("query_cache_entries").set(self.entries.len() as f64);
+    }
+}
+
+/// Executes `data_frame`, serving the results from the cache when `key` is set and a valid result
+/// of the same query is cached, and caching the results once they are fully consumed otherwise.
+pub async fn execute_stream(
+    data_frame: DataFrame,
+    key: Option<CacheKey>,
+) -> DataFusionResult<SendableRecordBatchStream> {
+    let Some(key) = key else {
+        return data_frame.execute_stream().await;
+    };
+
+    if let Some(cached) = key.cache.get(&key) {
+        return Ok(cached);
+    }
+
+    let stream = data_frame.execute_stream().await?;
+    Ok(Box::pin(RecordingStream {
+        inner: stream,
+        key: Some(key),
+        batches: vec![],
+        size: 0,
+    }))
+}
+
+/// Keeps a copy of the batches of a query until it completes, then caches them.
+struct RecordingStream {
+    inner: SendableRecordBatchStream,
+    key: Option<CacheKey>,
+    batches: Vec<RecordBatch>,
+    size: usize,
+}
+
+impl Stream for RecordingStream {
+    type Item = DataFusionResult<RecordBatch>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let result = this.inner.poll_next_unpin(cx);
+        match &result {
+            Poll::Ready(Some(Ok(batch))) => {
+                if let Some(key) = &this.key {
+                    this.size += batch.get_array_memory_size();
+                    if this.size > key.cache.max_size {
+                        // Too large to be cached, stop recording
+                        this.key = None;
+                        this.batches = vec![];
+                    } else {
+                        this.batches.push(batch.clone());
+                    }
+                }
+            }
+            Poll::Ready(Some(Err(_))) => {
+                this.key = None;
+                this.batches = vec![];
+            }
+            Poll::Ready(None) => {
+                if let Some(key) = this.key.take() {
+                    let batches = std::mem::take(&mut this.batches);
+                    let cache = Arc::clone(&key.cache);
+                    cache.insert(key, this.inner.schema(), batches, this.size);
+                }
+            }
+            Poll::Pending => {}
+        }
+        result
+    }
+}
+
+impl RecordBatchStream for RecordingStream {
+    fn schema(&self) -> SchemaRef {
+        self.inner.schema()
+    }
+}
+
+/// Collects the tables read by `plan`, including through views and subqueries.
+///
+/// Returns false if the results of the plan can't be cached, i.e. because it modifies a table,
+/// reads the catalog or calls a function that doesn't always return the same result, like `now()`.
+fn collect_tables(plan: &LogicalPlan, tables: &mut BTreeSet<String>) -> DataFusionResult<bool> {
+    match plan {
+        LogicalPlan::TableScan(scan) => {
+            if scan.table_name.schema() == Some(INFORMATION_SCHEMA) {
+                return Ok(false);
+            }
+            match scan.source.get_logical_plan() {
+                Some(view) => {
+                    if !collect_tables(view, tables)? {
+                        return Ok(false);
+                    }
+                }
+                None => {
+                    tables.insert(scan.table_name.table().to_string());
+                }
+            }
+        }
+        LogicalPlan::Dml(_)
+        | LogicalPlan::Ddl(_)
+        | LogicalPlan::Copy(_)
+        | LogicalPlan::Statement(_)
+        | LogicalPlan::Prepare(_)
+        | LogicalPlan::Explain(_)
+        | LogicalPlan::Analyze(_)
+        | LogicalPlan::DescribeTable(_)
+        | LogicalPlan::Extension(_) => return Ok(false),
+        _ => {}
+    }
+
+    for expr in plan.expressions() {
+        let mut deterministic = true;
+        let mut subqueries = vec![];
+        expr.apply(&mut |expr| {
+            match expr {
+                Expr::ScalarFunction(function) if volatility(function) != Volatility::Immutable => {
+                    deterministic = false;
+                    return Ok(VisitRecursion::Stop);
+                }
+                Expr::ScalarSubquery(subquery)
+                | Expr::Exists(Exists { subquery, .. })
+                | Expr::InSubquery(InSubquery { subquery, .. }) => {
+                    subqueries.push(Arc::clone(&subquery.subquery));
+                }
+                _ => {}
+            }
+            Ok(VisitRecursion::Continue)
+        })?;
+
+        if !deterministic {
+            return Ok(false);
+        }
+        for subquery in subqueries {
+            if !collect_tables(&subquery, tables)? {
+                return Ok(false);
+            }
+        }
+    }
+
+    for input in plan.inputs() {
+        if !collect_tables(input, tables)? {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn volatility(function: &ScalarFunction) -> Volatility {
+    match &function.func_def {
+        ScalarFunctionDefinition::BuiltIn(function) => function.volatility(),
+        ScalarFunctionDefinition::UDF(udf) => udf.signature().volatility,
+        ScalarFunctionDefinition::Name(_) => Volatility::Volatile,
+    }
+}
+
+/// Invalidates the cached results that read a dataset whenever its acceleration changes.
+pub struct CacheInvalidatingPublisher {
+    inner: Box<dyn DataPublisher>,
+    cache: Arc<QueryCache>,
+}
+
+impl CacheInvalidatingPublisher {
+    #[must_use]
+    pub fn new(inner: Box<dyn DataPublisher>, cache: Arc<QueryCache>) -> Self {
+        CacheInvalidatingPublisher { inner, cache }
+    }
+}
+
+impl DataPublisher for CacheInvalidatingPublisher {
+    fn add_data(&self, dataset: Arc<Dataset>, data_update: DataUpdate) -> AddDataResult {
+        Box::pin(async move {
+            let result = self.inner.add_data(Arc::clone(&dataset), data_update).await;
+            self.cache.invalidate(&dataset.name);
+            result
+        })
+    }
+
+    fn delete_expired_data(&self, dataset: Arc<Dataset>, cutoff: SystemTime) -> DeleteDataResult {
+        Box::pin(async move {
+            let result = self
+                .inner
+                .delete_expired_data(Arc::clone(&dataset), cutoff)
+                .await;
+            self.cache.invalidate(&dataset.name);
+            result
+        })
+    }
+
+    fn execute_dml(&self, dataset: Arc<Dataset>, sql: String) -> ExecuteDmlResult {
+        Box::pin(async move {
+            let result = self.inner.execute_dml(Arc::clone(&dataset), sql).await;
+            self.cache.invalidate(&dataset.name);
+            result
+        })
+    }
+
+    fn supports_dml(&self) -> bool {
+        self.inner.supports_dml()
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::Int32Array,
+        datatypes::{DataType, Field, Schema},
+    };
+    use datafusion::{
+        datasource::MemTable,
+        execution::context::{SessionConfig, SessionContext},
+    };
+
+    use super::*;
+
+    fn context() -> SessionContext {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![Arc::new(Int32Array::from(vec![1, 2, 3]))],
+        )
+        .expect("valid batch");
+        let table = MemTable::try_new(schema, vec![vec![batch]]).expect("valid table");
+
+        let ctx =
+            SessionContext::new_with_config(SessionConfig::new().with_information_schema(true));
+        ctx.register_table("t", Arc::new(table))
+            .expect("table registered");
+        ctx
+    }
+
+    async fn run(
+        ctx: &SessionContext,
+        cache: &Arc<QueryCache>,
+        sql: &str,
+    ) -> Option<SendableRecordBatchStream> {
+        let data_frame = ctx.sql(sql).await.expect("valid query");
+        let key = cache
+            .key(data_frame.logical_plan(), |_| true)
+            .expect("plan inspected")?;
+        let stream = execute_stream(data_frame, Some(key))
+            .await
+            .expect("query executed");
+        Some(stream)
+    }
+
+    #[tokio::test]
+    async fn test_results_are_cached_until_invalidated() {
+        let ctx = context();
+        let cache = Arc::new(QueryCache::new(1 << 20));
+
+        let results: Vec<_> = run(&ctx, &cache, "SELECT a FROM t")
+            .await
+            .expect("cacheable")
+            .collect()
+            .await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(cache.lock().entries.len(), 1);
+
+        // Formatting differences produce the same plan
+        let data_frame = ctx.sql("select  a\nfrom t").await.expect("valid query");
+        let key = cache
+            .key(data_frame.logical_plan(), |_| true)
+            .expect("plan inspected")
+            .expect("cacheable");
+        assert!(cache.get(&key).is_some());
+
+        cache.invalidate("t");
+        assert!(cache.get(&key).is_none());
+        assert_eq!(cache.lock().size, 0);
+    }
+
+    #[tokio::test]
+    async fn test_uncacheable_queries() {
+        let ctx = context();
+        let cache = Arc::new(QueryCache::new(1 << 20));
+
+        assert!(run(&ctx, &cache, "SELECT a, random() FROM t")
+            .await
+            .is_none());
+        assert!(run(&ctx, &cache, "SELECT 1").await.is_none());
+        assert!(run(&ctx, &cache, "SELECT * FROM information_schema.tables")
+            .await
+            .is_none());
+    }
+}
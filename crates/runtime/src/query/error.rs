@@ -0,0 +1,219 @@
+use std::fmt;
+
+use datafusion::{error::DataFusionError, sql::sqlparser::parser::ParserError};
+use serde::Serialize;
+
+use super::limits;
+use crate::accesscontrol;
+
+/// The category of a failed query, reported to clients as the `code` of the error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryErrorCode {
+    /// The SQL could not be parsed.
+    ParseError,
+    /// The query references unknown tables, columns or functions, or is otherwise invalid.
+    PlanError,
+    /// The query doesn't match the schema of the tables it references.
+    SchemaError,
+    /// The query uses a feature that is not supported.
+    NotImplemented,
+    /// The query failed while it was running.
+    ExecutionError,
+    /// The query ran out of memory or exceeded the maximum result size.
+    ResourceExhausted,
+    /// The query ran longer than its timeout.
+    Timeout,
+    /// The principal is not allowed to read or write a table the query references.
+    AccessDenied,
+    /// Any other failure of the runtime.
+    InternalError,
+}
+
+impl QueryErrorCode {
+    /// Whether the query itself is at fault, as opposed to a failure while running it.
+    #[must_use]
+    pub fn is_client_error(self) -> bool {
+        matches!(
+            self,
+            QueryErrorCode::ParseError
+                | QueryErrorCode::PlanError
+                | QueryErrorCode::SchemaError
+                | QueryErrorCode::NotImplemented
+        )
+    }
+}
+
+/// The position of a syntax error in the query, both 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Position {
+    pub line: u64,
+    pub column: u64,
+}
+
+/// The diagnostics of a failed query, returned as JSON by both the HTTP and Flight endpoints.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueryError {
+    pub code: QueryErrorCode,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+}
+
+impl QueryError {
+    #[must_use]
+    pub fn new(code: QueryErrorCode, message: impl Into<String>) -> Self {
+        QueryError {
+            code,
+            message: message.into(),
+            position: None,
+        }
+    }
+
+    #[must_use]
+    pub fn is_client_error(&self) -> bool {
+        self.code.is_client_error()
+    }
+
+    /// The error serialized as a JSON object.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        // Serializing plain strings and integers can't fail
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl From<&DataFusionError> for QueryError {
+    fn from(e: &DataFusionError) -> Self {
+        match e {
+            DataFusionError::Context(_, inner) => QueryError::from(inner.as_ref()),
+            DataFusionError::SQL(sql_err, _) => {
+                let message = match sql_err {
+                    ParserError::RecursionLimitExceeded => "Recursion limit exceeded".to_string(),
+                    ParserError::ParserError(err_msg) | ParserError::TokenizerError(err_msg) => {
+                        err_msg.clone()
+                    }
+                };
+                QueryError {
+                    code: QueryErrorCode::ParseError,
+                    position: position_from_message(&message),
+                    message,
+                }
+            }
+            DataFusionError::Plan(err_msg) => QueryError::new(QueryErrorCode::PlanError, err_msg),
+            DataFusionError::SchemaError(schema_err, _) => {
+                QueryError::new(QueryErrorCode::SchemaError, schema_err.to_string())
+            }
+            DataFusionError::NotImplemented(err_msg) => {
+                QueryError::new(QueryErrorCode::NotImplemented, err_msg)
+            }
+            DataFusionError::Execution(err_msg) => {
+                QueryError::new(QueryErrorCode::ExecutionError, err_msg)
+            }
+            DataFusionError::ResourcesExhausted(err_msg) => {
+                QueryError::new(QueryErrorCode::ResourceExhausted, err_msg)
+            }
+            DataFusionError::External(err) => match err.downcast_ref::<limits::Error>() {
+                Some(limits::Error::TimeoutExceeded { .. }) => {
+                    QueryError::new(QueryErrorCode::Timeout, err.to_string())
+                }
+                Some(limits::Error::ResultSizeExceeded { .. }) => {
+                    QueryError::new(QueryErrorCode::ResourceExhausted, err.to_string())
+                }
+                None => QueryError::new(QueryErrorCode::InternalError, e.to_string()),
+            },
+            _ => QueryError::new(QueryErrorCode::InternalError, e.to_string()),
+        }
+    }
+}
+
+impl From<DataFusionError> for QueryError {
+    fn from(e: DataFusionError) -> Self {
+        QueryError::from(&e)
+    }
+}
+
+impl From<&accesscontrol::Error> for QueryError {
+    fn from(e: &accesscontrol::Error) -> Self {
+        match e {
+            accesscontrol::Error::UnableToInspectPlan { source } => QueryError::from(source),
+            _ => QueryError::new(QueryErrorCode::AccessDenied, e.to_string()),
+        }
+    }
+}
+
+/// Extracts the location `sqlparser` appends to its messages, i.e. `... at Line: 1, Column 8`.
+fn position_from_message(message: &str) -> Option<Position> {
+    let location = &message[message.rfind("Line: ")? + "Line: ".len()..];
+    let (line, rest) = location.split_once(',')?;
+    let column = rest.trim_start().strip_prefix("Column")?;
+    let column = column.trim_start_matches(|c: char| c == ':' || c.is_whitespace());
+    let column_end = column
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(column.len());
+
+    Some(Position {
+        line: line.trim().parse().ok()?,
+        column: column[..column_end].parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_position() {
+        let e = DataFusionError::SQL(
+            ParserError::ParserError(
+                "Expected end of statement, found: FORM at Line: 1, Column 10".to_string(),
+            ),
+            None,
+        );
+        let error = QueryError::from(&e);
+        assert_eq!(error.code, QueryErrorCode::ParseError);
+        assert_eq!(
+            error.position,
+            Some(Position {
+                line: 1,
+                column: 10
+            })
+        );
+        assert!(error.is_client_error());
+    }
+
+    #[test]
+    fn test_error_categories() {
+        let plan = DataFusionError::Context(
+            "planning".to_string(),
+            Box::new(DataFusionError::Plan("table 'foo' not found".to_string())),
+        );
+        let error = QueryError::from(&plan);
+        assert_eq!(error.code, QueryErrorCode::PlanError);
+        assert_eq!(error.position, None);
+        assert_eq!(
+            error.to_json(),
+            r#"{"code":"plan_error","message":"table 'foo' not found"}"#
+        );
+
+        let exhausted = DataFusionError::ResourcesExhausted("memory limit".to_string());
+        assert!(!QueryError::from(&exhausted).is_client_error());
+
+        let denied = accesscontrol::Error::ReadNotAllowed {
+            principal: "analyst".to_string(),
+            dataset: "payments".to_string(),
+        };
+        assert_eq!(
+            QueryError::from(&denied).to_json(),
+            r#"{"code":"access_denied","message":"analyst does not have read access to dataset payments"}"#
+        );
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use arrow::{
+    array::{ArrayRef, Float64Builder, StringBuilder, TimestampMillisecondBuilder, UInt64Builder},
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+    record_batch::RecordBatch,
+};
+use async_trait::async_trait;
+use datafusion::{
+    datasource::{TableProvider, TableType},
+    error::Result as DataFusionResult,
+    execution::{context::SessionState, SendableRecordBatchStream},
+    logical_expr::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan, RecordBatchStream},
+};
+use futures::{Stream, StreamExt};
+use once_cell::sync::Lazy;
+use uuid::Uuid;
+
+/// The most queries kept in the history, whatever the retention.
+const MAX_ENTRIES: usize = 10_000;
+
+static SCHEMA: Lazy<SchemaRef> = Lazy::new(|| {
+    let timestamp = DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()));
+    Arc::new(Schema::new(vec![
+        Field::new("query_id", DataType::Utf8, false),
+        Field::new("sql", DataType::Utf8, false),
+        Field::new("protocol", DataType::Utf8, false),
+        Field::new("principal", DataType::Utf8, true),
+        Field::new("client", DataType::Utf8, true),
+        Field::new("start_time", timestamp.clone(), false),
+        Field::new("end_time", timestamp, false),
+        Field::new("execution_time_ms", DataType::Float64, false),
+        Field::new("rows_produced", DataType::UInt64, false),
+        Field::new("bytes_produced", DataType::UInt64, false),
+        Field::new("status", DataType::Utf8, false),
+        Field::new("error_message", DataType::Utf8, true),
+    ]))
+});
+
+/// The endpoint a query was received on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Http,
+    Flight,
+}
+
+impl Protocol {
+    fn as_str(self) -> &'static str {
+        match self {
+            Protocol::Http => "http",
+            Protocol::Flight => "flight",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryStatus {
+    Succeeded,
+    Failed,
+    /// The client went away before all the results were sent.
+    Cancelled,
+}
+
+impl QueryStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            QueryStatus::Succeeded => "succeeded",
+            QueryStatus::Failed => "failed",
+            QueryStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryRecord {
+    pub query_id: Uuid,
+    pub sql: String,
+    pub protocol: Protocol,
+    pub principal: Option<String>,
+    /// The user agent of the client, if it sent one.
+    pub client: Option<String>,
+    pub start_time: SystemTime,
+    pub end_time: SystemTime,
+    pub execution_time: Duration,
+    pub rows_produced: u64,
+    pub bytes_produced: u64,
+    pub status: QueryStatus,
+    pub error_message: Option<String>,
+}
+
+/// The queries that completed within the retention period, most recent last.
+///
+/// Exposed to SQL as the `spice.runtime.query_history` table.
+pub struct QueryHistory {
+    retention: Duration,
+    records: Mutex<VecDeque<QueryRecord>>,
+}
+
+impl QueryHistory {
+    /// A history that keeps queries for `retention`, or none if it is zero.
+    #[must_use]
+    pub fn new(retention: Duration) -> Self {
+        QueryHistory {
+            retention,
+            records: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        !self.retention.is_zero()
+    }
+
+    /// Starts recording a query, which is added to the history once its tracker completes.
+    #[must_use]
+    pub fn start(
+        self: &Arc<Self>,
+        sql: impl Into<String>,
+        protocol: Protocol,
+        principal: Option<&str>,
+        client: Option<&str>,
+    ) -> QueryTracker {
+        QueryTracker {
+            history: self.is_enabled().then(|| Arc::clone(self)),
+            sql: sql.into(),
+            protocol,
+            principal: principal.map(str::to_string),
+            client: client.map(str::to_string),
+            start_time: SystemTime::now(),
+            started: Instant::now(),
+            rows_produced: 0,
+            bytes_produced: 0,
+        }
+    }
+
+    fn record(&self, record: QueryRecord) {
+        let mut records = self.lock();
+        records.push_back(record);
+        Self::prune(&mut records, self.retention);
+    }
+
+    #[must_use]
+    pub fn records(&self) -> Vec<QueryRecord> {
+        let mut records = self.lock();
+        Self::prune(&mut records, self.retention);
+        records.iter().cloned().collect()
+    }
+
+    fn prune(records: &mut VecDeque<QueryRecord>, retention: Duration) {
+        while records.len() > MAX_ENTRIES {
+            records.pop_front();
+        }
+
+        let Some(cutoff) = SystemTime::now().checked_sub(retention) else {
+            return;
+        };
+        while records
+            .front()
+            .is_some_and(|record| record.end_time < cutoff)
+        {
+            records.pop_front();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<QueryRecord>> {
+        self.records.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn to_record_batch(&self) -> DataFusionResult<RecordBatch> {
+        let records = self.records();
+
+        let mut query_ids = StringBuilder::new();
+        let mut sqls = StringBuilder::new();
+        let mut protocols = StringBuilder::new();
+        let mut principals = StringBuilder::new();
+        let mut clients = StringBuilder::new();
+        let mut start_times = TimestampMillisecondBuilder::new().with_timezone("UTC");
+        let mut end_times = TimestampMillisecondBuilder::new().with_timezone("UTC");
+        let mut execution_times = Float64Builder::new();
+        let mut rows = UInt64Builder::new();
+        let mut bytes = UInt64Builder::new();
+        let mut statuses = StringBuilder::new();
+        let mut errors = StringBuilder::new();
+
+        for record in &records {
+            query_ids.append_value(record.query_id.to_string());
+            sqls.append_value(&record.sql);
+            protocols.append_value(record.protocol.as_str());
+            principals.append_option(record.principal.as_deref());
+            clients.append_option(record.client.as_deref());
+            start_times.append_value(unix_millis(record.start_time));
+            end_times.append_value(unix_millis(record.end_time));
+            execution_times.append_value(record.execution_time.as_secs_f64() * 1000.0);
+            rows.append_value(record.rows_produced);
+            bytes.append_value(record.bytes_produced);
+            statuses.append_value(record.status.as_str());
+            errors.append_option(record.error_message.as_deref());
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(query_ids.finish()),
+            Arc::new(sqls.finish()),
+            Arc::new(protocols.finish()),
+            Arc::new(principals.finish()),
+            Arc::new(clients.finish()),
+            Arc::new(start_times.finish()),
+            Arc::new(end_times.finish()),
+            Arc::new(execution_times.finish()),
+            Arc::new(rows.finish()),
+            Arc::new(bytes.finish()),
+            Arc::new(statuses.finish()),
+            Arc::new(errors.finish()),
+        ];
+
+        Ok(RecordBatch::try_new(Arc::clone(&SCHEMA), columns)?)
+    }
+}
+
+pub(crate) fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| i64::try_from(duration.as_millis()).unwrap_or(i64::MAX))
+        .unwrap_or_default()
+}
+
+/// Records a single query in the history when it completes.
+///
+/// A query that is dropped without completing is recorded as cancelled.
+pub struct QueryTracker {
+    history: Option<Arc<QueryHistory>>,
+    sql: String,
+    protocol: Protocol,
+    principal: Option<String>,
+    client: Option<String>,
+    start_time: SystemTime,
+    started: Instant,
+    rows_produced: u64,
+    bytes_produced: u64,
+}
+
+impl QueryTracker {
+    /// Records the query as failed before it produced any results, i.e. because it could not be planned.
+    pub fn fail(mut self, error: &dyn fmt::Display) {
+        self.finish(QueryStatus::Failed, Some(error.to_string()));
+    }
+
+    /// Wraps the results of the query, recording it once they are consumed.
+    #[must_use]
+    pub fn track_stream(self, stream: SendableRecordBatchStream) -> SendableRecordBatchStream {
+        Box::pin(TrackedStream {
+            inner: stream,
+            tracker: Some(self),
+        })
+    }
+
+    fn finish(&mut self, status: QueryStatus, error_message: Option<String>) {
+        let Some(history) = self.history.take() else {
+            return;
+        };
+
+        history.record(QueryRecord {
+            query_id: Uuid::new_v4(),
+            sql: std::mem::take(&mut self.sql),
+            protocol: self.protocol,
+            principal: self.principal.take(),
+            client: self.client.take(),
+            start_time: self.start_time,
+            end_time: SystemTime::now(),
+            execution_time: self.started.elapsed(),
+            rows_produced: self.rows_produced,
+            bytes_produced: self.bytes_produced,
+            status,
+            error_message,
+        });
+    }
+}
+
+impl Drop for QueryTracker {
+    fn drop(&mut self) {
+        self.finish(QueryStatus::Cancelled, None);
+    }
+}
+
+struct TrackedStream {
+    inner: SendableRecordBatchStream,
+    tracker: Option<QueryTracker>,
+}
+
+impl Stream for TrackedStream {
+    type Item = DataFusionResult<RecordBatch>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let result = this.inner.poll_next_unpin(cx);
+        match &result {
+            Poll::Ready(Some(Ok(batch))) => {
+                if let Some(tracker) = &mut this.tracker {
+                    tracker.rows_produced += batch.num_rows() as u64;
+                    tracker.bytes_produced += batch.get_array_memory_size() as u64;
+                }
+            }
+            Poll::Ready(Some(Err(e))) => {
+                if let Some(mut tracker) = this.tracker.take() {
+                    tracker.finish(QueryStatus::Failed, Some(e.to_string()));
+                }
+            }
+            Poll::Ready(None) => {
+                if let Some(mut tracker) = this.tracker.take() {
+                    tracker.finish(QueryStatus::Succeeded, None);
+                }
+            }
+            Poll::Pending => {}
+        }
+        result
+    }
+}
+
+impl RecordBatchStream for TrackedStream {
+    fn schema(&self) -> SchemaRef {
+        self.inner.schema()
+    }
+}
+
+/// The `query_history` table, a snapshot of the history taken when it is scanned.
+pub struct QueryHistoryTable {
+    history: Arc<QueryHistory>,
+}
+
+impl QueryHistoryTable {
+    #[must_use]
+    pub fn new(history: Arc<QueryHistory>) -> Self {
+        QueryHistoryTable { history }
+    }
+}
+
+#[async_trait]
+impl TableProvider for QueryHistoryTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&SCHEMA)
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let batch = self.history.to_record_batch()?;
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.cloned(),
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker_records_once() {
+        let history = Arc::new(QueryHistory::new(Duration::from_secs(3600)));
+
+        history
+            .start("SELECT 1", Protocol::Http, Some("spice"), None)
+            .fail(&"planning failed");
+        drop(history.start("SELECT 2", Protocol::Flight, None, Some("grpc-go")));
+
+        let records = history.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].status, QueryStatus::Failed);
+        assert_eq!(records[0].error_message.as_deref(), Some("planning failed"));
+        assert_eq!(records[1].status, QueryStatus::Cancelled);
+
+        let batch = history.to_record_batch().expect("valid batch");
+        assert_eq!(batch.num_rows(), 2);
+    }
+
+    #[test]
+    fn test_disabled_history() {
+        let history = Arc::new(QueryHistory::new(Duration::ZERO));
+        history
+            .start("SELECT 1", Protocol::Http, None, None)
+            .fail(&"error");
+        assert!(history.records().is_empty());
+    }
+}
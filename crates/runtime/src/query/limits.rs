@@ -0,0 +1,262 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use arrow::{datatypes::SchemaRef, record_batch::RecordBatch};
+use datafusion::{
+    error::{DataFusionError, Result as DataFusionResult},
+    execution::{
+        disk_manager::DiskManagerConfig,
+        memory_pool::FairSpillPool,
+        runtime_env::{RuntimeConfig, RuntimeEnv},
+        SendableRecordBatchStream,
+    },
+    physical_plan::RecordBatchStream,
+};
+use futures::{Stream, StreamExt};
+use snafu::prelude::*;
+use tokio::time::Sleep;
+
+use crate::config::QueryConfig;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Query exceeded the timeout of {}s", timeout.as_secs_f64()))]
+    TimeoutExceeded { timeout: Duration },
+
+    #[snafu(display("Query results exceeded the maximum size of {max_result_size} bytes"))]
+    ResultSizeExceeded { max_result_size: usize },
+}
+
+/// The limits a single query runs under.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryLimits {
+    pub timeout: Option<Duration>,
+    /// The maximum in-memory size of the record batches returned by the query.
+    pub max_result_size: Option<usize>,
+}
+
+impl QueryLimits {
+    #[must_use]
+    pub fn new(config: &QueryConfig) -> Self {
+        QueryLimits {
+            timeout: config.timeout,
+            max_result_size: config.max_result_size,
+        }
+    }
+
+    /// Applies the limits requested by a client, which can only be stricter than the runtime's.
+    #[must_use]
+    pub fn with_overrides(self, overrides: QueryLimits) -> Self {
+        QueryLimits {
+            timeout: min_defined(self.timeout, overrides.timeout),
+            max_result_size: min_defined(self.max_result_size, overrides.max_result_size),
+        }
+    }
+
+    /// Wraps the results of a query so it fails once it exceeds the limits.
+    ///
+    /// The query is cancelled when the returned stream is dropped, i.e. when the client disconnects.
+    #[must_use]
+    pub fn limit_stream(self, stream: SendableRecordBatchStream) -> SendableRecordBatchStream {
+        Box::pin(LimitedStream {
+            deadline: self
+                .timeout
+                .map(|timeout| Box::pin(tokio::time::sleep(timeout))),
+            inner: stream,
+            limits: self,
+            result_size: 0,
+            done: false,
+        })
+    }
+}
+
+fn min_defined<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// The runtime environment shared by all queries, with the memory pool and spill directory of `config`.
+pub fn runtime_env(config: &QueryConfig) -> DataFusionResult<Arc<RuntimeEnv>> {
+    let mut runtime_config = RuntimeConfig::new();
+    if let Some(memory_limit) = config.memory_limit {
+        runtime_config =
+            runtime_config.with_memory_pool(Arc::new(FairSpillPool::new(memory_limit)));
+    }
+    if let Some(spill_dir) = &config.spill_dir {
+        runtime_config = runtime_config
+            .with_disk_manager(DiskManagerConfig::NewSpecified(vec![spill_dir.clone()]));
+    }
+
+    Ok(Arc::new(RuntimeEnv::new(runtime_config)?))
+}
+
+struct LimitedStream {
+    inner: SendableRecordBatchStream,
+    deadline: Option<Pin<Box<Sleep>>>,
+    limits: QueryLimits,
+    result_size: usize,
+    done: bool,
+}
+
+impl LimitedStream {
+    fn fail(&mut self, error: Error) -> Poll<Option<DataFusionResult<RecordBatch>>> {
+        self.done = true;
+        Poll::Ready(Some(Err(DataFusionError::External(Box::new(error)))))
+    }
+}
+
+impl Stream for LimitedStream {
+    type Item = DataFusionResult<RecordBatch>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        if let (Some(deadline), Some(timeout)) = (this.deadline.as_mut(), this.limits.timeout) {
+            if deadline.as_mut().poll(cx).is_ready() {
+                return this.fail(Error::TimeoutExceeded { timeout });
+            }
+        }
+
+        match this.inner.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(batch))) => {
+                this.result_size += batch.get_array_memory_size();
+                match this.limits.max_result_size {
+                    Some(max_result_size) if this.result_size > max_result_size => {
+                        this.fail(Error::ResultSizeExceeded { max_result_size })
+                    }
+                    _ => Poll::Ready(Some(Ok(batch))),
+                }
+            }
+            Poll::Ready(Some(Err(e))) => {
+                this.done = true;
+                Poll::Ready(Some(Err(e)))
+            }
+            Poll::Ready(None) => {
+                this.done = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl RecordBatchStream for LimitedStream {
+    fn schema(&self) -> SchemaRef {
+        self.inner.schema()
+    }
+}
+
+impl Drop for LimitedStream {
+    fn drop(&mut self) {
+        if !self.done {
+            tracing::debug!("Query cancelled before its results were consumed");
+            metrics::counter!("queries_cancelled").increment(1);
+        }
+    }
+}
+
+/// Parses a duration such as `30s`, `1.5m` or `1h`.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    fundu::parse_duration(value).map_err(|e| e.to_string())
+}
+
+/// Parses a size in bytes, with an optional decimal (`KB`, `MB`, ...) or binary (`KiB`, `MiB`, ...) unit.
+pub fn parse_byte_size(value: &str) -> Result<usize, String> {
+    let value = value.trim();
+    let unit_start = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+
+    let number: usize = number
+        .parse()
+        .map_err(|_| format!("Invalid size: {value}"))?;
+    let multiplier: usize = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return Err(format!("Invalid size unit: {unit}")),
+    };
+
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Size is too large: {value}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::Int32Array,
+        datatypes::{DataType, Field, Schema},
+    };
+    use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("1024"), Ok(1024));
+        assert_eq!(parse_byte_size("2KB"), Ok(2_000));
+        assert_eq!(parse_byte_size("512 MiB"), Ok(512 << 20));
+        assert!(parse_byte_size("1PB").is_err());
+        assert!(parse_byte_size("MiB").is_err());
+    }
+
+    #[test]
+    fn test_overrides_are_stricter() {
+        let limits = QueryLimits {
+            timeout: Some(Duration::from_secs(60)),
+            max_result_size: None,
+        };
+        let overridden = limits.with_overrides(QueryLimits {
+            timeout: Some(Duration::from_secs(600)),
+            max_result_size: Some(1024),
+        });
+
+        assert_eq!(overridden.timeout, Some(Duration::from_secs(60)));
+        assert_eq!(overridden.max_result_size, Some(1024));
+    }
+
+    #[tokio::test]
+    async fn test_result_size_exceeded() {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![Arc::new(Int32Array::from(vec![1; 1024]))],
+        )
+        .expect("valid batch");
+        let stream = Box::pin(RecordBatchStreamAdapter::new(
+            schema,
+            futures::stream::iter(vec![Ok(batch.clone()), Ok(batch)]),
+        ));
+
+        let limits = QueryLimits {
+            timeout: None,
+            max_result_size: Some(6000),
+        };
+        let results: Vec<_> = limits.limit_stream(stream).collect().await;
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            &results[1],
+            Err(DataFusionError::External(e)) if e.to_string().contains("maximum size")
+        ));
+    }
+}
@@ -0,0 +1,475 @@
+//! Writes data from a dataset's connector into its acceleration, either as the connector produces it
+//! or on demand through `POST /v1/datasets/:name/refresh` and the `RefreshDataset` Flight action.
+
+use std::{
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use datafusion::{execution::context::SessionContext, sql::sqlparser::dialect::GenericDialect};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+use spicepod::component::dataset::{acceleration::RefreshMode, Dataset};
+use tokio::{sync::oneshot, task};
+
+use crate::{
+    dataconnector::{self, render_filter, DataConnector, FailedDataUpdate},
+    datapublisher::DataPublisher,
+    dataupdate::{DataUpdate, UpdateType},
+    status::{DatasetState, RefreshRecord, RuntimeStatus},
+};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Dataset {dataset} does not exist or is not accelerated"))]
+    DatasetNotAccelerated { dataset: String },
+
+    #[snafu(display(
+        "The data connector for dataset {dataset} does not support filtered refreshes"
+    ))]
+    FilterNotSupported { dataset: String },
+
+    #[snafu(display(
+        "Dataset {dataset} needs an acceleration primary_key to be refreshed with a filter"
+    ))]
+    PrimaryKeyRequired { dataset: String },
+
+    #[snafu(display("{source}"))]
+    InvalidFilter { source: dataconnector::Error },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// A request to refresh a dataset immediately.
+#[derive(Debug, Default, Deserialize)]
+pub struct RefreshRequest {
+    /// A SQL predicate that limits the refresh to the matching rows.
+    ///
+    /// The matching rows are upserted by the primary key of the acceleration, while the rest are kept.
+    #[serde(default)]
+    pub filter: Option<String>,
+}
+
+/// The outcome of an on-demand refresh.
+#[derive(Debug, Serialize)]
+pub struct RefreshResponse {
+    pub dataset: String,
+    pub status: &'static str,
+    pub duration_ms: f64,
+    pub rows: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl From<&RefreshRecord> for RefreshResponse {
+    fn from(record: &RefreshRecord) -> Self {
+        RefreshResponse {
+            dataset: record.dataset.clone(),
+            status: if record.error.is_some() {
+                "failed"
+            } else {
+                "succeeded"
+            },
+            duration_ms: record.duration.as_secs_f64() * 1000.0,
+            rows: record.rows,
+            error: record.error.clone(),
+        }
+    }
+}
+
+/// Everything needed to refresh the acceleration of a dataset from its data connector.
+#[derive(Clone)]
+pub struct DatasetRefresher {
+    dataset: Arc<Dataset>,
+    data_connector: Arc<dyn DataConnector>,
+    publisher: Arc<Box<dyn DataPublisher>>,
+    status: Arc<RuntimeStatus>,
+}
+
+impl DatasetRefresher {
+    #[must_use]
+    pub fn new(
+        dataset: Arc<Dataset>,
+        data_connector: Arc<dyn DataConnector>,
+        publisher: Arc<Box<dyn DataPublisher>>,
+        status: Arc<RuntimeStatus>,
+    ) -> Self {
+        DatasetRefresher {
+            dataset,
+            data_connector,
+            publisher,
+            status,
+        }
+    }
+
+    #[must_use]
+    pub fn dataset(&self) -> &Arc<Dataset> {
+        &self.dataset
+    }
+
+    #[must_use]
+    pub fn publisher(&self) -> &Arc<Box<dyn DataPublisher>> {
+        &self.publisher
+    }
+
+    /// Starts writing the updates produced by the data connector into the acceleration.
+    ///
+    /// `ctx` is where the acceleration is registered, used to find where incremental refreshes resume.
+    /// The returned receiver resolves with the outcome of the first refresh.
+    #[must_use]
+    pub fn spawn(
+        self,
+        ctx: Arc<SessionContext>,
+    ) -> (task::JoinHandle<()>, oneshot::Receiver<RefreshRecord>) {
+        let (first_refresh_tx, first_refresh_rx) = oneshot::channel();
+        let task_handle = task::spawn(async move {
+            let dataset = Arc::clone(&self.dataset);
+            let data_connector = Arc::clone(&self.data_connector);
+            let mut stream = if dataset.refresh_mode() == RefreshMode::Incremental {
+                data_connector.get_incremental_data(&dataset, ctx)
+            } else {
+                data_connector.get_data(&dataset)
+            };
+
+            let mut first_refresh_tx = Some(first_refresh_tx);
+            while let Some(data_update) = stream.next().await {
+                let record = match data_update {
+                    Ok(data_update) => self.apply(data_update).await,
+                    Err(failed) => fail_update(
+                        &self.dataset,
+                        &self.status,
+                        failed,
+                        (SystemTime::now(), Instant::now()),
+                    ),
+                };
+                if let Some(tx) = first_refresh_tx.take() {
+                    let _ = tx.send(record);
+                }
+            }
+        });
+
+        (task_handle, first_refresh_rx)
+    }
+
+    /// Fetches the data of the dataset from its connector and writes it into the acceleration.
+    ///
+    /// Without a filter the acceleration is overwritten, with one only the matching rows are replaced.
+    /// If the data can't be fetched, the acceleration is left as it is and the refresh is recorded as failed.
+    pub async fn refresh(&self, filter: Option<&str>) -> Result<RefreshRecord> {
+        let dataset = &self.dataset;
+        let start_time = SystemTime::now();
+        let started = Instant::now();
+
+        let filter = match filter {
+            Some(filter) => {
+                ensure!(
+                    self.data_connector.supports_filtered_data(),
+                    FilterNotSupportedSnafu {
+                        dataset: dataset.name.clone()
+                    }
+                );
+                let Some(primary_key) = dataset
+                    .acceleration
+                    .as_ref()
+                    .and_then(|acc| acc.primary_key.clone())
+                else {
+                    return PrimaryKeyRequiredSnafu {
+                        dataset: dataset.name.clone(),
+                    }
+                    .fail();
+                };
+                // Rejected here so a filter that is not a single predicate never reaches the source.
+                let filter =
+                    render_filter(filter, &GenericDialect {}).context(InvalidFilterSnafu)?;
+                Some((filter, primary_key))
+            }
+            None => None,
+        };
+
+        self.status
+            .set_state(&dataset.name, DatasetState::Refreshing);
+        let (data, update_type) = match filter {
+            // Refreshing the same rows again replaces them instead of adding duplicates.
+            Some((filter, primary_key)) => (
+                self.data_connector
+                    .get_filtered_data(dataset, &filter)
+                    .await,
+                UpdateType::Upsert { keys: primary_key },
+            ),
+            None => (
+                self.data_connector.get_all_data(dataset).await,
+                UpdateType::Overwrite,
+            ),
+        };
+
+        let record = match data {
+            Ok(data) => {
+                apply_update(
+                    dataset,
+                    &self.publisher,
+                    &self.status,
+                    DataUpdate { data, update_type },
+                    (start_time, started),
+                )
+                .await
+            }
+            Err(error) => fail_update(
+                dataset,
+                &self.status,
+                FailedDataUpdate { update_type, error },
+                (start_time, started),
+            ),
+        };
+        Ok(record)
+    }
+
+    /// Writes an update produced by the data connector into the acceleration.
+    pub async fn apply(&self, data_update: DataUpdate) -> RefreshRecord {
+        apply_update(
+            &self.dataset,
+            &self.publisher,
+            &self.status,
+            data_update,
+            (SystemTime::now(), Instant::now()),
+        )
+        .await
+    }
+}
+
+/// Writes `data_update` into `publisher`, moving the dataset through `Refreshing` to `Ready` or `Error`.
+///
+/// The refresh is recorded as having started at `started`, i.e. when its data started being fetched.
+async fn apply_update(
+    dataset: &Arc<Dataset>,
+    publisher: &Arc<Box<dyn DataPublisher>>,
+    status: &RuntimeStatus,
+    data_update: DataUpdate,
+    (start_time, started): (SystemTime, Instant),
+) -> RefreshRecord {
+    let rows = data_update
+        .data
+        .iter()
+        .map(|batch| batch.num_rows() as u64)
+        .sum();
+    let update_type = data_update.update_type.clone();
+
+    status.set_state(&dataset.name, DatasetState::Refreshing);
+    let error = match publisher.add_data(Arc::clone(dataset), data_update).await {
+        Ok(()) => {
+            status.set_state(&dataset.name, DatasetState::Ready);
+            None
+        }
+        Err(e) => {
+            tracing::error!("Error adding data: {e}");
+            status.set_error(&dataset.name, &e);
+            Some(e.to_string())
+        }
+    };
+
+    let record = RefreshRecord {
+        dataset: dataset.name.clone(),
+        start_time,
+        duration: started.elapsed(),
+        rows,
+        update_type,
+        error,
+    };
+    status.record_refresh(record.clone());
+    record
+}
+
+/// Records a refresh whose data could not be fetched, moving the dataset to `Error`.
+fn fail_update(
+    dataset: &Dataset,
+    status: &RuntimeStatus,
+    failed: FailedDataUpdate,
+    (start_time, started): (SystemTime, Instant),
+) -> RefreshRecord {
+    tracing::error!("Error fetching data for {}: {}", dataset.name, failed.error);
+    status.set_error(&dataset.name, &failed.error);
+
+    let record = RefreshRecord {
+        dataset: dataset.name.clone(),
+        start_time,
+        duration: started.elapsed(),
+        rows: 0,
+        update_type: failed.update_type,
+        error: Some(failed.error.to_string()),
+    };
+    status.record_refresh(record.clone());
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, future::Future, pin::Pin};
+
+    use arrow::{
+        array::{Int32Array, RecordBatch, StringArray},
+        datatypes::{DataType, Field, Schema},
+    };
+    use async_trait::async_trait;
+    use secrets::Secret;
+    use spicepod::component::dataset::acceleration::Acceleration;
+
+    use super::*;
+    use crate::{
+        databackend::memtable::MemTableBackend,
+        dataconnector::{self, DataResult},
+    };
+
+    /// Returns the same rows for every refresh, like a source whose filtered window did not change.
+    struct FixedData;
+
+    #[async_trait]
+    impl DataConnector for FixedData {
+        fn new(
+            _secret: Option<Secret>,
+            _params: Arc<Option<HashMap<String, String>>>,
+        ) -> Pin<Box<dyn Future<Output = dataconnector::Result<Self>> + Send>> {
+            Box::pin(async { Ok(FixedData) })
+        }
+
+        fn get_all_data(
+            &self,
+            _dataset: &Dataset,
+        ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+            Box::pin(async {
+                let schema = Arc::new(Schema::new(vec![
+                    Field::new("id", DataType::Int32, false),
+                    Field::new("value", DataType::Utf8, false),
+                ]));
+                let batch = RecordBatch::try_new(
+                    schema,
+                    vec![
+                        Arc::new(Int32Array::from(vec![1, 2])),
+                        Arc::new(StringArray::from(vec!["a", "b"])),
+                    ],
+                )
+                .expect("Unable to create record batch");
+                Ok(vec![batch])
+            })
+        }
+
+        fn supports_filtered_data(&self) -> bool {
+            true
+        }
+
+        fn get_filtered_data(
+            &self,
+            dataset: &Dataset,
+            _filter: &str,
+        ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+            self.get_all_data(dataset)
+        }
+    }
+
+    /// Fails every fetch, like a source that can't be reached.
+    struct Unavailable;
+
+    #[async_trait]
+    impl DataConnector for Unavailable {
+        fn new(
+            _secret: Option<Secret>,
+            _params: Arc<Option<HashMap<String, String>>>,
+        ) -> Pin<Box<dyn Future<Output = dataconnector::Result<Self>> + Send>> {
+            Box::pin(async { Ok(Unavailable) })
+        }
+
+        fn get_all_data(
+            &self,
+            _dataset: &Dataset,
+        ) -> Pin<Box<dyn Future<Output = DataResult> + Send>> {
+            Box::pin(async { Err("connection refused".into()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filtered_refresh_replaces_rows() {
+        let ctx = Arc::new(SessionContext::new());
+        let mut dataset = Dataset::new("fixed:items".to_string(), "items".to_string());
+        dataset.acceleration = Some(Acceleration {
+            enabled: true,
+            mode: None,
+            engine: None,
+            refresh_interval: None,
+            refresh_mode: None,
+            watermark_column: None,
+            retention: None,
+            retention_check_interval: None,
+            params: None,
+            engine_secret: None,
+            primary_key: Some(vec!["id".to_string()]),
+        });
+        let refresher = DatasetRefresher::new(
+            Arc::new(dataset),
+            Arc::new(FixedData),
+            Arc::new(Box::new(MemTableBackend::new(Arc::clone(&ctx), "items"))),
+            Arc::new(RuntimeStatus::new()),
+        );
+
+        for _ in 0..2 {
+            let record = refresher
+                .refresh(Some("id < 10"))
+                .await
+                .expect("Unable to refresh");
+            assert_eq!(record.error, None);
+        }
+
+        let count = ctx
+            .sql("SELECT * FROM items")
+            .await
+            .expect("Unable to execute query")
+            .count()
+            .await
+            .expect("Unable to count rows");
+        assert_eq!(count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_refresh_keeps_data() {
+        let ctx = Arc::new(SessionContext::new());
+        let dataset = Arc::new(Dataset::new(
+            "unavailable:items".to_string(),
+            "items".to_string(),
+        ));
+        let publisher: Arc<Box<dyn DataPublisher>> =
+            Arc::new(Box::new(MemTableBackend::new(Arc::clone(&ctx), "items")));
+        let data = FixedData
+            .get_all_data(&dataset)
+            .await
+            .expect("Unable to get data");
+        publisher
+            .add_data(
+                Arc::clone(&dataset),
+                DataUpdate {
+                    data,
+                    update_type: UpdateType::Overwrite,
+                },
+            )
+            .await
+            .expect("Unable to add data");
+
+        let status = Arc::new(RuntimeStatus::new());
+        let refresher = DatasetRefresher::new(
+            Arc::clone(&dataset),
+            Arc::new(Unavailable),
+            publisher,
+            Arc::clone(&status),
+        );
+        let record = refresher.refresh(None).await.expect("Unable to refresh");
+        assert!(record.error.is_some());
+        assert_eq!(RefreshResponse::from(&record).status, "failed");
+
+        let count = ctx
+            .sql("SELECT * FROM items")
+            .await
+            .expect("Unable to execute query")
+            .count()
+            .await
+            .expect("Unable to count rows");
+        assert_eq!(count, 2);
+    }
+}
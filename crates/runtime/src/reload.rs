@@ -0,0 +1,238 @@
+//! Applies changes to a dataset's definition without tearing down more of it than the change requires.
+
+use std::{any::Any, sync::Arc};
+
+use arrow::datatypes::SchemaRef;
+use async_trait::async_trait;
+use datafusion::{
+    datasource::{TableProvider, TableType},
+    error::Result as DataFusionResult,
+    execution::context::{SessionContext, SessionState},
+    logical_expr::{Expr, TableProviderFilterPushDown},
+    physical_plan::ExecutionPlan,
+};
+use spicepod::component::dataset::Dataset;
+use tokio::{sync::oneshot, task};
+
+use crate::{refresh::DatasetRefresher, status::RefreshRecord};
+
+/// How much of a dataset has to be rebuilt to apply a change to its definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatasetChange {
+    /// Only the refresh, retention or connector settings changed. The accelerated table is kept and the
+    /// data connector is replaced in place.
+    RefreshSettings,
+    /// The acceleration itself changed, i.e. its engine or mode. A new accelerated table is loaded in the
+    /// background and swapped in once it is ready.
+    Acceleration,
+    /// Anything else, the dataset is unloaded and loaded again.
+    Definition,
+}
+
+impl DatasetChange {
+    /// Classifies the change from `current` to `new`, or `None` if they are the same.
+    #[must_use]
+    pub fn between(current: &Dataset, new: &Dataset) -> Option<Self> {
+        if current == new {
+            return None;
+        }
+
+        let is_accelerated =
+            |ds: &Dataset| ds.acceleration.as_ref().is_some_and(|acc| acc.enabled) && !ds.is_view();
+        if !is_accelerated(current) || !is_accelerated(new) {
+            return Some(DatasetChange::Definition);
+        }
+
+        // Undo the changes to the refresh settings, if nothing else changed only those need applying.
+        let mut unchanged_refresh = new.clone();
+        unchanged_refresh.params = current.params.clone();
+        unchanged_refresh.time_column = current.time_column.clone();
+        unchanged_refresh.time_format = current.time_format.clone();
+        unchanged_refresh.depends_on = current.depends_on.clone();
+        if let (Some(acceleration), Some(current_acceleration)) = (
+            unchanged_refresh.acceleration.as_mut(),
+            current.acceleration.as_ref(),
+        ) {
+            acceleration.refresh_interval = current_acceleration.refresh_interval.clone();
+            acceleration.refresh_mode = current_acceleration.refresh_mode.clone();
+            acceleration.watermark_column = current_acceleration.watermark_column.clone();
+            acceleration.retention = current_acceleration.retention.clone();
+            acceleration.retention_check_interval =
+                current_acceleration.retention_check_interval.clone();
+        }
+        if unchanged_refresh == *current {
+            return Some(DatasetChange::RefreshSettings);
+        }
+
+        unchanged_refresh.acceleration = current.acceleration.clone();
+        if unchanged_refresh == *current {
+            return Some(DatasetChange::Acceleration);
+        }
+
+        Some(DatasetChange::Definition)
+    }
+}
+
+/// An acceleration being loaded in its own `SessionContext`, to replace the one of a dataset once loaded.
+///
+/// The acceleration is abandoned if this is dropped before being swapped in.
+pub struct StagedAcceleration {
+    pub(crate) ctx: Arc<SessionContext>,
+    pub(crate) refresher: DatasetRefresher,
+    pub(crate) task: Option<task::JoinHandle<()>>,
+    first_refresh: Option<oneshot::Receiver<RefreshRecord>>,
+}
+
+impl StagedAcceleration {
+    #[must_use]
+    pub(crate) fn new(
+        ctx: Arc<SessionContext>,
+        refresher: DatasetRefresher,
+        task: task::JoinHandle<()>,
+        first_refresh: oneshot::Receiver<RefreshRecord>,
+    ) -> Self {
+        StagedAcceleration {
+            ctx,
+            refresher,
+            task: Some(task),
+            first_refresh: Some(first_refresh),
+        }
+    }
+
+    /// Waits for the first refresh of the acceleration, returning its error if it failed.
+    pub async fn loaded(&mut self) -> Result<(), String> {
+        let Some(first_refresh) = self.first_refresh.take() else {
+            return Ok(());
+        };
+
+        match first_refresh.await {
+            Ok(RefreshRecord {
+                error: Some(error), ..
+            }) => Err(error),
+            Ok(_) => Ok(()),
+            Err(_) => Err("the data connector stopped before producing any data".to_string()),
+        }
+    }
+}
+
+impl Drop for StagedAcceleration {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// The table registered for a dataset whose acceleration was swapped in, which reads the acceleration
+/// from the `SessionContext` it was loaded in.
+pub struct StagedTable {
+    ctx: Arc<SessionContext>,
+    name: String,
+    schema: SchemaRef,
+}
+
+impl StagedTable {
+    #[must_use]
+    pub fn new(ctx: Arc<SessionContext>, name: String, schema: SchemaRef) -> Self {
+        StagedTable { ctx, name, schema }
+    }
+}
+
+#[async_trait]
+impl TableProvider for StagedTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&self.schema)
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
+        Ok(vec![TableProviderFilterPushDown::Inexact; filters.len()])
+    }
+
+    async fn scan(
+        &self,
+        state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        // The accelerated table is looked up on every scan, since overwriting it replaces it.
+        let table = self.ctx.table_provider(self.name.as_str()).await?;
+        table.scan(state, projection, filters, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spicepod::component::dataset::acceleration::{Acceleration, Mode};
+
+    use super::*;
+
+    fn accelerated_dataset() -> Dataset {
+        let mut dataset = Dataset::new("postgres:trips".to_string(), "trips".to_string());
+        dataset.acceleration = Some(Acceleration {
+            enabled: true,
+            mode: None,
+            engine: None,
+            refresh_interval: Some("10s".to_string()),
+            refresh_mode: None,
+            watermark_column: None,
+            retention: None,
+            retention_check_interval: None,
+            params: None,
+            engine_secret: None,
+            primary_key: None,
+        });
+        dataset
+    }
+
+    #[test]
+    fn test_dataset_change() {
+        let current = accelerated_dataset();
+        assert_eq!(DatasetChange::between(&current, &current.clone()), None);
+
+        let mut new = current.clone();
+        if let Some(acceleration) = new.acceleration.as_mut() {
+            acceleration.refresh_interval = Some("1m".to_string());
+            acceleration.retention = Some("1d".to_string());
+        }
+        new.params = Some([("host".to_string(), "replica".to_string())].into());
+        assert_eq!(
+            DatasetChange::between(&current, &new),
+            Some(DatasetChange::RefreshSettings)
+        );
+
+        if let Some(acceleration) = new.acceleration.as_mut() {
+            acceleration.mode = Some(Mode::File);
+        }
+        assert_eq!(
+            DatasetChange::between(&current, &new),
+            Some(DatasetChange::Acceleration)
+        );
+
+        new.from = "postgres:other_trips".to_string();
+        assert_eq!(
+            DatasetChange::between(&current, &new),
+            Some(DatasetChange::Definition)
+        );
+
+        let mut disabled = current.clone();
+        if let Some(acceleration) = disabled.acceleration.as_mut() {
+            acceleration.enabled = false;
+        }
+        assert_eq!(
+            DatasetChange::between(&current, &disabled),
+            Some(DatasetChange::Definition)
+        );
+    }
+}
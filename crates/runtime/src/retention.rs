@@ -0,0 +1,124 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use spicepod::component::dataset::{Dataset, TimeFormat};
+use tokio::task;
+use tokio::time::sleep;
+
+use crate::datapublisher::DataPublisher;
+
+/// How often expired rows are evicted when the dataset doesn't specify `retention_check_interval`.
+const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Spawns a task that periodically evicts the rows of `dataset` that fall outside its retention window.
+///
+/// Returns `None` if the dataset has no retention policy.
+#[must_use]
+pub fn spawn_retention_task(
+    dataset: Arc<Dataset>,
+    publisher: Arc<Box<dyn DataPublisher>>,
+) -> Option<task::JoinHandle<()>> {
+    let retention = dataset.retention()?;
+    if dataset.time_column.is_none() {
+        tracing::warn!(
+            "Dataset {} has a retention period but no time_column, rows will not be evicted",
+            dataset.name
+        );
+        return None;
+    }
+
+    let check_interval = dataset
+        .retention_check_interval()
+        .unwrap_or(DEFAULT_CHECK_INTERVAL);
+
+    Some(task::spawn(async move {
+        loop {
+            sleep(check_interval).await;
+
+            let Some(cutoff) = SystemTime::now().checked_sub(retention) else {
+                continue;
+            };
+
+            match publisher
+                .delete_expired_data(Arc::clone(&dataset), cutoff)
+                .await
+            {
+                Ok(evicted) => {
+                    if evicted > 0 {
+                        tracing::debug!(
+                            "Evicted {evicted} rows from dataset {} older than the retention period",
+                            dataset.name
+                        );
+                    }
+                    metrics::counter!("datasets_retention_evicted_rows", "dataset" => dataset.name.clone())
+                        .increment(evicted);
+                }
+                Err(e) => {
+                    metrics::counter!("datasets_retention_error", "dataset" => dataset.name.clone())
+                        .increment(1);
+                    tracing::error!(
+                        "Unable to evict expired rows from dataset {}: {e}",
+                        dataset.name
+                    );
+                }
+            }
+        }
+    }))
+}
+
+/// Returns a SQL predicate that matches the rows of `dataset` that are older than `cutoff`.
+///
+/// `to_timestamp` converts seconds since the unix epoch into a timestamp expression in the
+/// dialect of the accelerator; it is only used when the time column holds timestamps.
+pub(crate) fn expired_rows_predicate(
+    dataset: &Dataset,
+    cutoff: SystemTime,
+    to_timestamp: impl Fn(u64) -> String,
+) -> Option<String> {
+    let time_column = dataset.time_column.as_ref()?;
+    let since_epoch = cutoff.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+
+    let cutoff_value = match dataset.time_format() {
+        TimeFormat::Timestamp => to_timestamp(since_epoch.as_secs()),
+        TimeFormat::UnixSeconds => since_epoch.as_secs().to_string(),
+        TimeFormat::UnixMillis => since_epoch.as_millis().to_string(),
+        TimeFormat::UnixNanos => since_epoch.as_nanos().to_string(),
+    };
+
+    Some(format!(r#""{time_column}" < {cutoff_value}"#))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dataset_with_time_column(time_format: Option<TimeFormat>) -> Dataset {
+        let mut dataset = Dataset::new("localhost".to_string(), "test".to_string());
+        dataset.time_column = Some("ts".to_string());
+        dataset.time_format = time_format;
+        dataset
+    }
+
+    #[test]
+    fn test_expired_rows_predicate() {
+        let cutoff = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let dataset = dataset_with_time_column(None);
+        assert_eq!(
+            expired_rows_predicate(&dataset, cutoff, |secs| format!("to_timestamp({secs})")),
+            Some(r#""ts" < to_timestamp(1700000000)"#.to_string())
+        );
+
+        let dataset = dataset_with_time_column(Some(TimeFormat::UnixMillis));
+        assert_eq!(
+            expired_rows_predicate(&dataset, cutoff, |secs| format!("to_timestamp({secs})")),
+            Some(r#""ts" < 1700000000000"#.to_string())
+        );
+
+        let dataset = Dataset::new("localhost".to_string(), "test".to_string());
+        assert_eq!(
+            expired_rows_predicate(&dataset, cutoff, |secs| format!("to_timestamp({secs})")),
+            None
+        );
+    }
+}
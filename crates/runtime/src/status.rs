@@ -0,0 +1,332 @@
+//! Tracks the lifecycle and refreshes of datasets, exposed by `/v1/datasets`, the `datasets_status`
+//! metric and the `spice.runtime` system tables.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+use tokio::sync::Notify;
+
+use crate::dataupdate::UpdateType;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Dependency {dataset} failed: {reason}"))]
+    DependencyFailed { dataset: String, reason: String },
+
+    #[snafu(display("Dependency {dataset} is not loaded"))]
+    DependencyMissing { dataset: String },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The most refreshes kept, across all datasets.
+const MAX_REFRESHES: usize = 1_000;
+
+/// Where a dataset is in its lifecycle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DatasetState {
+    /// The data connector is being created, or the dataset is waiting for the datasets it depends on.
+    #[default]
+    Initializing,
+    /// Data is being loaded into the acceleration.
+    Refreshing,
+    /// The dataset can be queried.
+    Ready,
+    /// The last attempt to load or refresh the dataset failed, see `last_error`.
+    Error,
+    /// The dataset can't be queried because it has neither an acceleration nor a federated table.
+    Disabled,
+}
+
+impl DatasetState {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DatasetState::Initializing => "initializing",
+            DatasetState::Refreshing => "refreshing",
+            DatasetState::Ready => "ready",
+            DatasetState::Error => "error",
+            DatasetState::Disabled => "disabled",
+        }
+    }
+}
+
+/// The load state of a single dataset.
+#[derive(Debug, Clone, Default)]
+pub struct DatasetStatus {
+    pub state: DatasetState,
+    /// How many times loading the dataset has failed since it was last (re)loaded.
+    pub retry_attempts: u32,
+    pub last_error: Option<String>,
+    /// When the dataset first became `Ready` since it was last (re)loaded.
+    pub loaded_at: Option<SystemTime>,
+}
+
+/// A single update applied to the acceleration of a dataset.
+#[derive(Debug, Clone)]
+pub struct RefreshRecord {
+    pub dataset: String,
+    pub start_time: SystemTime,
+    /// How long the refresh took. Only the time to write the update is known for refreshes scheduled by
+    /// the data connector, since their data is fetched before they are received.
+    pub duration: Duration,
+    pub rows: u64,
+    pub update_type: UpdateType,
+    pub error: Option<String>,
+}
+
+/// The load state and recent refreshes of every dataset.
+#[derive(Debug, Default)]
+pub struct RuntimeStatus {
+    datasets: Mutex<HashMap<String, DatasetStatus>>,
+    refreshes: Mutex<VecDeque<RefreshRecord>>,
+    /// Notified whenever the state of a dataset changes.
+    state_changed: Notify,
+}
+
+impl RuntimeStatus {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks `dataset` as initializing, clearing the state of any previous load.
+    pub fn dataset_loading(&self, dataset: &str) {
+        let previous = lock(&self.datasets).insert(dataset.to_string(), DatasetStatus::default());
+        record_state_change(
+            dataset,
+            previous.map(|status| status.state),
+            DatasetState::Initializing,
+        );
+        self.state_changed.notify_waiters();
+    }
+
+    /// Records a failed attempt to load `dataset` that will be retried.
+    pub fn dataset_retrying(&self, dataset: &str, error: &dyn std::fmt::Display) {
+        self.update(dataset, |status| {
+            status.retry_attempts += 1;
+            status.state = DatasetState::Error;
+            status.last_error = Some(error.to_string());
+        });
+    }
+
+    pub fn set_state(&self, dataset: &str, state: DatasetState) {
+        self.update(dataset, |status| status.state = state);
+    }
+
+    /// Moves `dataset` to the `Error` state with `error` as its last error.
+    pub fn set_error(&self, dataset: &str, error: &dyn std::fmt::Display) {
+        self.update(dataset, |status| {
+            status.state = DatasetState::Error;
+            status.last_error = Some(error.to_string());
+        });
+    }
+
+    /// Waits until `dataset` has been `Ready`, or is `Disabled` and so never will be.
+    ///
+    /// Fails as soon as `dataset` is in the `Error` state or isn't being loaded at all.
+    pub async fn wait_until_loaded(&self, dataset: &str) -> Result<()> {
+        self.wait_for(|datasets| match datasets.get(dataset) {
+            None => Some(DependencyMissingSnafu { dataset }.fail()),
+            Some(status)
+                if status.loaded_at.is_some() || status.state == DatasetState::Disabled =>
+            {
+                Some(Ok(()))
+            }
+            Some(status) if status.state == DatasetState::Error => Some(
+                DependencyFailedSnafu {
+                    dataset,
+                    reason: status.last_error.clone().unwrap_or_default(),
+                }
+                .fail(),
+            ),
+            Some(_) => None,
+        })
+        .await
+    }
+
+    /// Waits until `dataset` is being loaded and isn't in the `Error` state, i.e. after a failed load is retried.
+    pub async fn wait_until_recovered(&self, dataset: &str) {
+        self.wait_for(|datasets| {
+            datasets
+                .get(dataset)
+                .is_some_and(|status| status.state != DatasetState::Error)
+                .then_some(())
+        })
+        .await;
+    }
+
+    /// Waits until `f` returns a value for the status of the datasets.
+    async fn wait_for<T>(&self, f: impl Fn(&HashMap<String, DatasetStatus>) -> Option<T>) -> T {
+        loop {
+            // Created before checking the state, so a change in between isn't missed.
+            let state_changed = self.state_changed.notified();
+            if let Some(value) = f(&lock(&self.datasets)) {
+                return value;
+            }
+            state_changed.await;
+        }
+    }
+
+    fn update(&self, dataset: &str, f: impl FnOnce(&mut DatasetStatus)) {
+        {
+            let mut datasets = lock(&self.datasets);
+            let status = datasets.entry(dataset.to_string()).or_default();
+            let previous = status.state;
+            f(status);
+            if status.state == DatasetState::Ready && status.loaded_at.is_none() {
+                status.loaded_at = Some(SystemTime::now());
+            }
+            record_state_change(dataset, Some(previous), status.state);
+        }
+        self.state_changed.notify_waiters();
+    }
+
+    pub fn remove_dataset(&self, dataset: &str) {
+        if let Some(status) = lock(&self.datasets).remove(dataset) {
+            metrics::gauge!("datasets_status", "dataset" => dataset.to_string(), "status" => status.state.as_str()).set(0.0);
+        }
+        lock(&self.refreshes).retain(|refresh| refresh.dataset != dataset);
+    }
+
+    #[must_use]
+    pub fn dataset(&self, dataset: &str) -> Option<DatasetStatus> {
+        lock(&self.datasets).get(dataset).cloned()
+    }
+
+    pub fn record_refresh(&self, refresh: RefreshRecord) {
+        let mut refreshes = lock(&self.refreshes);
+        refreshes.push_back(refresh);
+        while refreshes.len() > MAX_REFRESHES {
+            refreshes.pop_front();
+        }
+    }
+
+    /// The recent refreshes of all datasets, oldest first.
+    #[must_use]
+    pub fn refreshes(&self) -> Vec<RefreshRecord> {
+        lock(&self.refreshes).iter().cloned().collect()
+    }
+
+    /// The most recent refresh of `dataset`.
+    #[must_use]
+    pub fn last_refresh(&self, dataset: &str) -> Option<RefreshRecord> {
+        lock(&self.refreshes)
+            .iter()
+            .rev()
+            .find(|refresh| refresh.dataset == dataset)
+            .cloned()
+    }
+}
+
+/// Moves the `datasets_status` gauge of `dataset` from `previous` to `current`.
+fn record_state_change(dataset: &str, previous: Option<DatasetState>, current: DatasetState) {
+    if let Some(previous) = previous.filter(|previous| *previous != current) {
+        metrics::gauge!("datasets_status", "dataset" => dataset.to_string(), "status" => previous.as_str()).set(0.0);
+    }
+    metrics::gauge!("datasets_status", "dataset" => dataset.to_string(), "status" => current.as_str()).set(1.0);
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_state_is_reset_on_reload() {
+        let status = RuntimeStatus::new();
+
+        status.dataset_loading("taxi_trips");
+        status.dataset_retrying("taxi_trips", &"connection refused");
+        status.dataset_retrying("taxi_trips", &"connection refused");
+
+        let current = status.dataset("taxi_trips").expect("dataset status");
+        assert_eq!(current.retry_attempts, 2);
+        assert_eq!(current.state, DatasetState::Error);
+        assert!(current.loaded_at.is_none());
+
+        status.set_state("taxi_trips", DatasetState::Ready);
+        let current = status.dataset("taxi_trips").expect("dataset status");
+        assert_eq!(current.state, DatasetState::Ready);
+        assert!(current.loaded_at.is_some());
+        assert_eq!(current.last_error.as_deref(), Some("connection refused"));
+
+        status.dataset_loading("taxi_trips");
+        let current = status.dataset("taxi_trips").expect("dataset status");
+        assert_eq!(current.state, DatasetState::Initializing);
+        assert_eq!(current.retry_attempts, 0);
+        assert!(current.last_error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_loaded() {
+        let status = std::sync::Arc::new(RuntimeStatus::new());
+        status.dataset_loading("taxi_trips");
+
+        let waiter = tokio::spawn({
+            let status = std::sync::Arc::clone(&status);
+            async move { status.wait_until_loaded("taxi_trips").await }
+        });
+        status.set_state("taxi_trips", DatasetState::Refreshing);
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        status.set_state("taxi_trips", DatasetState::Ready);
+        status.set_state("taxi_trips", DatasetState::Refreshing);
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("waiter finished")
+            .expect("waiter succeeded")
+            .expect("dataset loaded");
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_loaded_fails_with_dependency() {
+        let status = std::sync::Arc::new(RuntimeStatus::new());
+        assert!(matches!(
+            status.wait_until_loaded("taxi_trips").await,
+            Err(Error::DependencyMissing { .. })
+        ));
+
+        status.dataset_loading("taxi_trips");
+        let waiter = tokio::spawn({
+            let status = std::sync::Arc::clone(&status);
+            async move { status.wait_until_loaded("taxi_trips").await }
+        });
+        tokio::task::yield_now().await;
+        status.dataset_retrying("taxi_trips", &"connection refused");
+        let result = tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("waiter finished")
+            .expect("waiter succeeded");
+        let Err(e) = result else {
+            panic!("expected the dependency to fail");
+        };
+        assert_eq!(
+            e.to_string(),
+            "Dependency taxi_trips failed: connection refused"
+        );
+
+        let recovered = tokio::spawn({
+            let status = std::sync::Arc::clone(&status);
+            async move { status.wait_until_recovered("taxi_trips").await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!recovered.is_finished());
+        status.set_state("taxi_trips", DatasetState::Ready);
+        tokio::time::timeout(Duration::from_secs(1), recovered)
+            .await
+            .expect("waiter finished")
+            .expect("waiter succeeded");
+        assert!(status.wait_until_loaded("taxi_trips").await.is_ok());
+    }
+}
@@ -0,0 +1,511 @@
+//! The `spice.runtime` tables that describe the datasets, accelerations and models of the loaded app.
+//!
+//! Each table is a snapshot of the runtime taken when it is scanned.
+
+use std::{any::Any, collections::HashMap, sync::Arc};
+
+use app::App;
+use arrow::{
+    array::{
+        Array, ArrayRef, BooleanBuilder, Float64Builder, Int64Array, StringBuilder,
+        TimestampMillisecondBuilder, UInt32Builder, UInt64Builder,
+    },
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+    record_batch::RecordBatch,
+};
+use async_trait::async_trait;
+use datafusion::{
+    datasource::{TableProvider, TableType},
+    error::Result as DataFusionResult,
+    execution::context::{SessionContext, SessionState},
+    logical_expr::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+use once_cell::sync::Lazy;
+use spicepod::component::dataset::{
+    acceleration::{self, RefreshMode},
+    Dataset, Mode,
+};
+use tokio::sync::RwLock;
+
+use crate::{
+    dataupdate::UpdateType, model::Model, query::history::unix_millis, status::RuntimeStatus,
+};
+
+fn timestamp() -> DataType {
+    DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()))
+}
+
+static DATASETS_SCHEMA: Lazy<SchemaRef> = Lazy::new(|| {
+    Arc::new(Schema::new(vec![
+        Field::new("name", DataType::Utf8, false),
+        Field::new("from", DataType::Utf8, false),
+        Field::new("source", DataType::Utf8, false),
+        Field::new("mode", DataType::Utf8, false),
+        Field::new("is_view", DataType::Boolean, false),
+        Field::new("accelerated", DataType::Boolean, false),
+        Field::new("loaded_at", timestamp(), true),
+        Field::new("status", DataType::Utf8, false),
+        Field::new("retry_attempts", DataType::UInt32, false),
+        Field::new("last_error", DataType::Utf8, true),
+    ]))
+});
+
+static ACCELERATIONS_SCHEMA: Lazy<SchemaRef> = Lazy::new(|| {
+    Arc::new(Schema::new(vec![
+        Field::new("dataset", DataType::Utf8, false),
+        Field::new("engine", DataType::Utf8, false),
+        Field::new("mode", DataType::Utf8, false),
+        Field::new("refresh_mode", DataType::Utf8, false),
+        Field::new("refresh_interval", DataType::Utf8, true),
+        Field::new("retention", DataType::Utf8, true),
+        Field::new("primary_key", DataType::Utf8, true),
+        Field::new("last_refresh_time", timestamp(), true),
+        Field::new("last_refresh_duration_ms", DataType::Float64, true),
+        Field::new("row_count", DataType::UInt64, true),
+        Field::new("last_error", DataType::Utf8, true),
+    ]))
+});
+
+static DATASET_REFRESHES_SCHEMA: Lazy<SchemaRef> = Lazy::new(|| {
+    Arc::new(Schema::new(vec![
+        Field::new("dataset", DataType::Utf8, false),
+        Field::new("start_time", timestamp(), false),
+        Field::new("duration_ms", DataType::Float64, false),
+        Field::new("rows", DataType::UInt64, false),
+        Field::new("update_type", DataType::Utf8, false),
+        Field::new("status", DataType::Utf8, false),
+        Field::new("error_message", DataType::Utf8, true),
+    ]))
+});
+
+static MODELS_SCHEMA: Lazy<SchemaRef> = Lazy::new(|| {
+    Arc::new(Schema::new(vec![
+        Field::new("name", DataType::Utf8, false),
+        Field::new("from", DataType::Utf8, false),
+        Field::new("source", DataType::Utf8, false),
+        Field::new("datasets", DataType::Utf8, true),
+        Field::new("loaded", DataType::Boolean, false),
+    ]))
+});
+
+/// The `datasets` table: every dataset in the app and whether it has loaded.
+pub struct DatasetsTable {
+    app: Arc<RwLock<Option<App>>>,
+    status: Arc<RuntimeStatus>,
+}
+
+impl DatasetsTable {
+    #[must_use]
+    pub fn new(app: Arc<RwLock<Option<App>>>, status: Arc<RuntimeStatus>) -> Self {
+        DatasetsTable { app, status }
+    }
+
+    async fn to_record_batch(&self) -> DataFusionResult<RecordBatch> {
+        let datasets = app_datasets(&self.app).await;
+
+        let mut names = StringBuilder::new();
+        let mut froms = StringBuilder::new();
+        let mut sources = StringBuilder::new();
+        let mut modes = StringBuilder::new();
+        let mut is_views = BooleanBuilder::new();
+        let mut accelerated = BooleanBuilder::new();
+        let mut loaded_at = TimestampMillisecondBuilder::new().with_timezone("UTC");
+        let mut statuses = StringBuilder::new();
+        let mut retry_attempts = UInt32Builder::new();
+        let mut errors = StringBuilder::new();
+
+        for ds in &datasets {
+            let status = self.status.dataset(&ds.name).unwrap_or_default();
+            names.append_value(&ds.name);
+            froms.append_value(&ds.from);
+            sources.append_value(ds.source());
+            modes.append_value(dataset_mode(&ds.mode()));
+            is_views.append_value(ds.is_view());
+            accelerated.append_value(is_accelerated(ds));
+            loaded_at.append_option(status.loaded_at.map(unix_millis));
+            statuses.append_value(status.state.as_str());
+            retry_attempts.append_value(status.retry_attempts);
+            errors.append_option(status.last_error.as_deref());
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(names.finish()),
+            Arc::new(froms.finish()),
+            Arc::new(sources.finish()),
+            Arc::new(modes.finish()),
+            Arc::new(is_views.finish()),
+            Arc::new(accelerated.finish()),
+            Arc::new(loaded_at.finish()),
+            Arc::new(statuses.finish()),
+            Arc::new(retry_attempts.finish()),
+            Arc::new(errors.finish()),
+        ];
+
+        Ok(RecordBatch::try_new(Arc::clone(&DATASETS_SCHEMA), columns)?)
+    }
+}
+
+/// The `accelerations` table: the acceleration of every accelerated dataset and its last refresh.
+pub struct AccelerationsTable {
+    app: Arc<RwLock<Option<App>>>,
+    status: Arc<RuntimeStatus>,
+}
+
+impl AccelerationsTable {
+    #[must_use]
+    pub fn new(app: Arc<RwLock<Option<App>>>, status: Arc<RuntimeStatus>) -> Self {
+        AccelerationsTable { app, status }
+    }
+
+    async fn to_record_batch(&self, state: &SessionState) -> DataFusionResult<RecordBatch> {
+        let datasets = app_datasets(&self.app).await;
+        let ctx = SessionContext::new_with_state(state.clone());
+
+        let mut names = StringBuilder::new();
+        let mut engines = StringBuilder::new();
+        let mut modes = StringBuilder::new();
+        let mut refresh_modes = StringBuilder::new();
+        let mut refresh_intervals = StringBuilder::new();
+        let mut retentions = StringBuilder::new();
+        let mut primary_keys = StringBuilder::new();
+        let mut refresh_times = TimestampMillisecondBuilder::new().with_timezone("UTC");
+        let mut refresh_durations = Float64Builder::new();
+        let mut row_counts = UInt64Builder::new();
+        let mut errors = StringBuilder::new();
+
+        for ds in datasets.iter().filter(|ds| is_accelerated(ds)) {
+            let Some(acceleration) = &ds.acceleration else {
+                continue;
+            };
+            let last_refresh = self.status.last_refresh(&ds.name);
+            let status = self.status.dataset(&ds.name).unwrap_or_default();
+
+            names.append_value(&ds.name);
+            engines.append_value(acceleration.engine().to_string());
+            modes.append_value(acceleration_mode(&acceleration.mode()));
+            refresh_modes.append_value(refresh_mode(&ds.refresh_mode()));
+            refresh_intervals.append_option(acceleration.refresh_interval.as_deref());
+            retentions.append_option(acceleration.retention.as_deref());
+            primary_keys.append_option(acceleration.primary_key.as_ref().map(|key| key.join(", ")));
+            refresh_times.append_option(
+                last_refresh
+                    .as_ref()
+                    .map(|refresh| unix_millis(refresh.start_time)),
+            );
+            refresh_durations.append_option(
+                last_refresh
+                    .as_ref()
+                    .map(|refresh| refresh.duration.as_secs_f64() * 1000.0),
+            );
+            row_counts.append_option(if status.loaded_at.is_some() {
+                row_count(&ctx, &ds.name).await
+            } else {
+                None
+            });
+            errors.append_option(
+                last_refresh
+                    .and_then(|refresh| refresh.error)
+                    .or(status.last_error)
+                    .as_deref(),
+            );
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(names.finish()),
+            Arc::new(engines.finish()),
+            Arc::new(modes.finish()),
+            Arc::new(refresh_modes.finish()),
+            Arc::new(refresh_intervals.finish()),
+            Arc::new(retentions.finish()),
+            Arc::new(primary_keys.finish()),
+            Arc::new(refresh_times.finish()),
+            Arc::new(refresh_durations.finish()),
+            Arc::new(row_counts.finish()),
+            Arc::new(errors.finish()),
+        ];
+
+        Ok(RecordBatch::try_new(
+            Arc::clone(&ACCELERATIONS_SCHEMA),
+            columns,
+        )?)
+    }
+}
+
+/// The `dataset_refreshes` table: the recent updates written into dataset accelerations.
+pub struct DatasetRefreshesTable {
+    status: Arc<RuntimeStatus>,
+}
+
+impl DatasetRefreshesTable {
+    #[must_use]
+    pub fn new(status: Arc<RuntimeStatus>) -> Self {
+        DatasetRefreshesTable { status }
+    }
+
+    fn to_record_batch(&self) -> DataFusionResult<RecordBatch> {
+        let refreshes = self.status.refreshes();
+
+        let mut names = StringBuilder::new();
+        let mut start_times = TimestampMillisecondBuilder::new().with_timezone("UTC");
+        let mut durations = Float64Builder::new();
+        let mut rows = UInt64Builder::new();
+        let mut update_types = StringBuilder::new();
+        let mut statuses = StringBuilder::new();
+        let mut errors = StringBuilder::new();
+
+        for refresh in &refreshes {
+            names.append_value(&refresh.dataset);
+            start_times.append_value(unix_millis(refresh.start_time));
+            durations.append_value(refresh.duration.as_secs_f64() * 1000.0);
+            rows.append_value(refresh.rows);
+            update_types.append_value(update_type(&refresh.update_type));
+            statuses.append_value(if refresh.error.is_some() {
+                "failed"
+            } else {
+                "succeeded"
+            });
+            errors.append_option(refresh.error.as_deref());
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(names.finish()),
+            Arc::new(start_times.finish()),
+            Arc::new(durations.finish()),
+            Arc::new(rows.finish()),
+            Arc::new(update_types.finish()),
+            Arc::new(statuses.finish()),
+            Arc::new(errors.finish()),
+        ];
+
+        Ok(RecordBatch::try_new(
+            Arc::clone(&DATASET_REFRESHES_SCHEMA),
+            columns,
+        )?)
+    }
+}
+
+/// The `models` table: every model in the app and whether it has loaded.
+pub struct ModelsTable {
+    app: Arc<RwLock<Option<App>>>,
+    models: Arc<RwLock<HashMap<String, Model>>>,
+}
+
+impl ModelsTable {
+    #[must_use]
+    pub fn new(app: Arc<RwLock<Option<App>>>, models: Arc<RwLock<HashMap<String, Model>>>) -> Self {
+        ModelsTable { app, models }
+    }
+
+    async fn to_record_batch(&self) -> DataFusionResult<RecordBatch> {
+        let models = self
+            .app
+            .read()
+            .await
+            .as_ref()
+            .map(|app| app.models.clone())
+            .unwrap_or_default();
+        let loaded_models = self.models.read().await;
+
+        let mut names = StringBuilder::new();
+        let mut froms = StringBuilder::new();
+        let mut sources = StringBuilder::new();
+        let mut datasets = StringBuilder::new();
+        let mut loaded = BooleanBuilder::new();
+
+        for model in &models {
+            names.append_value(&model.name);
+            froms.append_value(&model.from);
+            sources.append_value(crate::model::source(&model.from));
+            datasets.append_option((!model.datasets.is_empty()).then(|| model.datasets.join(", ")));
+            loaded.append_value(loaded_models.contains_key(&model.name));
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(names.finish()),
+            Arc::new(froms.finish()),
+            Arc::new(sources.finish()),
+            Arc::new(datasets.finish()),
+            Arc::new(loaded.finish()),
+        ];
+
+        Ok(RecordBatch::try_new(Arc::clone(&MODELS_SCHEMA), columns)?)
+    }
+}
+
+#[async_trait]
+impl TableProvider for DatasetsTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&DATASETS_SCHEMA)
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        memory_exec(self.to_record_batch().await?, projection)
+    }
+}
+
+#[async_trait]
+impl TableProvider for AccelerationsTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&ACCELERATIONS_SCHEMA)
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    async fn scan(
+        &self,
+        state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        memory_exec(self.to_record_batch(state).await?, projection)
+    }
+}
+
+#[async_trait]
+impl TableProvider for DatasetRefreshesTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&DATASET_REFRESHES_SCHEMA)
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        memory_exec(self.to_record_batch()?, projection)
+    }
+}
+
+#[async_trait]
+impl TableProvider for ModelsTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&MODELS_SCHEMA)
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        memory_exec(self.to_record_batch().await?, projection)
+    }
+}
+
+fn memory_exec(
+    batch: RecordBatch,
+    projection: Option<&Vec<usize>>,
+) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+    let schema = batch.schema();
+    Ok(Arc::new(MemoryExec::try_new(
+        &[vec![batch]],
+        schema,
+        projection.cloned(),
+    )?))
+}
+
+async fn app_datasets(app: &RwLock<Option<App>>) -> Vec<Dataset> {
+    app.read()
+        .await
+        .as_ref()
+        .map(|app| app.datasets.clone())
+        .unwrap_or_default()
+}
+
+fn is_accelerated(ds: &Dataset) -> bool {
+    ds.acceleration.as_ref().is_some_and(|acc| acc.enabled)
+}
+
+/// Counts the rows of the accelerated `table`, or `None` if it can't be read.
+async fn row_count(ctx: &SessionContext, table: &str) -> Option<u64> {
+    let batches = ctx
+        .sql(&format!(r#"SELECT COUNT(*) FROM "{table}""#))
+        .await
+        .ok()?
+        .collect()
+        .await
+        .ok()?;
+    let counts = batches
+        .first()?
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()?;
+    if counts.is_empty() || counts.is_null(0) {
+        return None;
+    }
+
+    u64::try_from(counts.value(0)).ok()
+}
+
+fn dataset_mode(mode: &Mode) -> &'static str {
+    match mode {
+        Mode::Read => "read",
+        Mode::ReadWrite => "read_write",
+        Mode::Append => "append",
+    }
+}
+
+fn acceleration_mode(mode: &acceleration::Mode) -> &'static str {
+    match mode {
+        acceleration::Mode::Memory => "memory",
+        acceleration::Mode::File => "file",
+    }
+}
+
+fn refresh_mode(mode: &RefreshMode) -> &'static str {
+    match mode {
+        RefreshMode::Full => "full",
+        RefreshMode::Append => "append",
+        RefreshMode::Incremental => "incremental",
+    }
+}
+
+fn update_type(update_type: &UpdateType) -> &'static str {
+    match update_type {
+        UpdateType::Append => "append",
+        UpdateType::Overwrite => "overwrite",
+        UpdateType::Upsert { .. } => "upsert",
+        UpdateType::Delete { .. } => "delete",
+    }
+}
@@ -0,0 +1,20 @@
+//! Helpers shared by the unit tests of this crate.
+
+use arrow::{array::StringArray, record_batch::RecordBatch};
+
+/// Collects the values of the string column at `column` across `batches`, with nulls as empty strings.
+pub(crate) fn string_values(batches: &[RecordBatch], column: usize) -> Vec<String> {
+    batches
+        .iter()
+        .flat_map(|batch| {
+            batch
+                .column(column)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .expect("Expected a string column")
+                .iter()
+                .map(|value| value.unwrap_or_default().to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
@@ -0,0 +1,200 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use rustls_pemfile::Item;
+use snafu::prelude::*;
+
+use crate::config::TlsConfig;
+
+const PEM_PREFIX: &str = "-----BEGIN";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("A TLS certificate was provided without a private key, specify --tls-key"))]
+    MissingPrivateKey,
+
+    #[snafu(display("A TLS private key was provided without a certificate, specify --tls-cert"))]
+    MissingCertificate,
+
+    #[snafu(display("Unable to read {}: {source}", path.display()))]
+    UnableToReadFile {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("Unable to parse the {kind} PEM: {source}"))]
+    UnableToParsePem {
+        source: std::io::Error,
+        kind: &'static str,
+    },
+
+    #[snafu(display("No private key found in the TLS private key PEM"))]
+    NoPrivateKey,
+
+    #[snafu(display("Invalid client CA certificate: {source}"))]
+    InvalidClientCa { source: rustls::Error },
+
+    #[snafu(display("Unable to build the TLS server configuration: {source}"))]
+    UnableToBuildServerConfig { source: rustls::Error },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The PEM encoded certificate chain and private key the runtime endpoints are served with,
+/// and the CA certificates client certificates are verified against when mutual TLS is enabled.
+#[derive(Clone)]
+pub struct TlsIdentity {
+    cert: Vec<u8>,
+    key: Vec<u8>,
+    client_ca: Option<Vec<u8>>,
+}
+
+impl std::fmt::Debug for TlsIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsIdentity")
+            .field("mutual_tls", &self.client_ca.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TlsIdentity {
+    /// Resolves the TLS settings from the command line options, falling back to the `cert`, `key`
+    /// and `client_ca` keys of the TLS secret.
+    ///
+    /// Returns `None` if TLS is not configured.
+    pub async fn load(
+        config: &TlsConfig,
+        secrets_provider: &secrets::SecretsProvider,
+    ) -> Result<Option<Self>> {
+        let secret = match &config.secret {
+            Some(secret_name) => {
+                let secret = secrets_provider.get_secret(secret_name).await;
+                if secret.is_none() {
+                    tracing::warn!("TLS secret {secret_name} not found");
+                }
+                secret
+            }
+            None => None,
+        };
+        let from_secret = |key: &str| secret.as_ref().and_then(|s| s.get(key).map(str::to_string));
+
+        let cert = match &config.cert {
+            Some(path) => Some(read_file(path)?),
+            None => from_secret("cert").map(read_pem_or_file).transpose()?,
+        };
+        let key = match &config.key {
+            Some(path) => Some(read_file(path)?),
+            None => from_secret("key").map(read_pem_or_file).transpose()?,
+        };
+        let client_ca = match &config.client_ca {
+            Some(path) => Some(read_file(path)?),
+            None => from_secret("client_ca").map(read_pem_or_file).transpose()?,
+        };
+
+        match (cert, key) {
+            (Some(cert), Some(key)) => Ok(Some(TlsIdentity {
+                cert,
+                key,
+                client_ca,
+            })),
+            (Some(_), None) => MissingPrivateKeySnafu.fail(),
+            (None, Some(_)) => MissingCertificateSnafu.fail(),
+            (None, None) => Ok(None),
+        }
+    }
+
+    #[must_use]
+    pub fn is_mutual(&self) -> bool {
+        self.client_ca.is_some()
+    }
+
+    /// The TLS configuration of the Flight server.
+    #[must_use]
+    pub fn tonic_config(&self) -> tonic::transport::ServerTlsConfig {
+        let config = tonic::transport::ServerTlsConfig::new()
+            .identity(tonic::transport::Identity::from_pem(&self.cert, &self.key));
+        match &self.client_ca {
+            Some(client_ca) => {
+                config.client_ca_root(tonic::transport::Certificate::from_pem(client_ca))
+            }
+            None => config,
+        }
+    }
+
+    /// The TLS configuration of the OpenTelemetry server, which is built on an older `tonic`.
+    #[must_use]
+    pub fn tonic_0_9_0_config(&self) -> tonic_0_9_0::transport::ServerTlsConfig {
+        let config = tonic_0_9_0::transport::ServerTlsConfig::new().identity(
+            tonic_0_9_0::transport::Identity::from_pem(&self.cert, &self.key),
+        );
+        match &self.client_ca {
+            Some(client_ca) => {
+                config.client_ca_root(tonic_0_9_0::transport::Certificate::from_pem(client_ca))
+            }
+            None => config,
+        }
+    }
+
+    /// The TLS configuration of the HTTP server.
+    pub fn rustls_config(&self) -> Result<Arc<ServerConfig>> {
+        let certs = rustls_pemfile::certs(&mut self.cert.as_slice())
+            .context(UnableToParsePemSnafu {
+                kind: "certificate",
+            })?
+            .into_iter()
+            .map(Certificate)
+            .collect();
+        let key = private_key(&self.key)?;
+
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &self.client_ca {
+            Some(client_ca) => {
+                let mut roots = RootCertStore::empty();
+                for cert in rustls_pemfile::certs(&mut client_ca.as_slice())
+                    .context(UnableToParsePemSnafu { kind: "client CA" })?
+                {
+                    roots
+                        .add(&Certificate(cert))
+                        .context(InvalidClientCaSnafu)?;
+                }
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder
+            .with_single_cert(certs, key)
+            .context(UnableToBuildServerConfigSnafu)?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(Arc::new(config))
+    }
+}
+
+fn private_key(pem: &[u8]) -> Result<PrivateKey> {
+    let mut reader = pem;
+    while let Some(item) = rustls_pemfile::read_one(&mut reader).context(UnableToParsePemSnafu {
+        kind: "private key",
+    })? {
+        if let Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) = item {
+            return Ok(PrivateKey(key));
+        }
+    }
+
+    NoPrivateKeySnafu.fail()
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).context(UnableToReadFileSnafu { path })
+}
+
+/// Secret values hold either the PEM contents, i.e. when loaded from a Kubernetes secret, or a file path.
+fn read_pem_or_file(value: String) -> Result<Vec<u8>> {
+    if value.trim_start().starts_with(PEM_PREFIX) {
+        return Ok(value.into_bytes());
+    }
+
+    read_file(Path::new(&value))
+}
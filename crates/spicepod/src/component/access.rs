@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+/// Matches any principal or dataset.
+pub const WILDCARD: &str = "*";
+
+/// A role grants its principals read and/or write access to datasets.
+///
+/// Example:
+/// ```yaml
+/// roles:
+///   - name: analysts
+///     principals: [alice, dashboards]
+///     read: [orders, customers]
+///   - name: ingest
+///     principals: [ingest_service]
+///     read: ["*"]
+///     write: [orders]
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Role {
+    pub name: String,
+
+    /// The authenticated principals that are members of this role, `*` matches any principal.
+    #[serde(default)]
+    pub principals: Vec<String>,
+
+    /// The datasets members of this role may query, `*` matches any dataset.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub read: Vec<String>,
+
+    /// The datasets members of this role may write to, `*` matches any dataset.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub write: Vec<String>,
+}
+
+impl Role {
+    #[must_use]
+    pub fn has_principal(&self, principal: &str) -> bool {
+        matches(&self.principals, principal)
+    }
+
+    #[must_use]
+    pub fn can_read(&self, dataset: &str) -> bool {
+        matches(&self.read, dataset)
+    }
+
+    #[must_use]
+    pub fn can_write(&self, dataset: &str) -> bool {
+        matches(&self.write, dataset)
+    }
+}
+
+fn matches(entries: &[String], value: &str) -> bool {
+    entries
+        .iter()
+        .any(|entry| entry == WILDCARD || entry.eq_ignore_ascii_case(value))
+}
@@ -24,6 +24,17 @@ pub enum Mode {
     Append,
 }
 
+/// Describes how the values of a dataset's `time_column` are encoded.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    #[default]
+    Timestamp,
+    UnixSeconds,
+    UnixMillis,
+    UnixNanos,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Dataset {
     #[serde(default, skip_serializing_if = "String::is_empty")]
@@ -51,6 +62,13 @@ pub struct Dataset {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub acceleration: Option<acceleration::Acceleration>,
 
+    /// The column that holds the time of each row, used for retention.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_column: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_format: Option<TimeFormat>,
+
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(rename = "dependsOn", default)]
     pub depends_on: Vec<String>,
@@ -68,6 +86,8 @@ impl Dataset {
             params: Option::default(),
             replication: None,
             acceleration: None,
+            time_column: None,
+            time_format: None,
             depends_on: Vec::default(),
         }
     }
@@ -167,6 +187,65 @@ impl Dataset {
         None
     }
 
+    #[must_use]
+    pub fn refresh_mode(&self) -> acceleration::RefreshMode {
+        self.acceleration
+            .as_ref()
+            .and_then(|acc| acc.refresh_mode.clone())
+            .unwrap_or(acceleration::RefreshMode::Full)
+    }
+
+    /// Returns the column whose maximum value marks how far an incremental refresh has progressed.
+    #[must_use]
+    pub fn watermark_column(&self) -> Option<String> {
+        self.acceleration
+            .as_ref()
+            .and_then(|acc| acc.watermark_column.clone())
+            .or_else(|| self.time_column.clone())
+    }
+
+    /// Returns how long rows are kept in the accelerated table before being evicted.
+    #[must_use]
+    pub fn retention(&self) -> Option<Duration> {
+        if let Some(acceleration) = &self.acceleration {
+            if let Some(retention) = &acceleration.retention {
+                if let Ok(duration) = fundu::parse_duration(retention) {
+                    return Some(duration);
+                }
+                tracing::warn!(
+                    "Unable to parse retention for dataset {}: {}",
+                    self.name,
+                    retention
+                );
+            }
+        }
+
+        None
+    }
+
+    #[must_use]
+    pub fn retention_check_interval(&self) -> Option<Duration> {
+        if let Some(acceleration) = &self.acceleration {
+            if let Some(retention_check_interval) = &acceleration.retention_check_interval {
+                if let Ok(duration) = fundu::parse_duration(retention_check_interval) {
+                    return Some(duration);
+                }
+                tracing::warn!(
+                    "Unable to parse retention check interval for dataset {}: {}",
+                    self.name,
+                    retention_check_interval
+                );
+            }
+        }
+
+        None
+    }
+
+    #[must_use]
+    pub fn time_format(&self) -> TimeFormat {
+        self.time_format.clone().unwrap_or_default()
+    }
+
     #[must_use]
     pub fn is_view(&self) -> bool {
         self.sql.is_some() || self.sql_ref.is_some()
@@ -203,6 +282,8 @@ impl WithDependsOn<Dataset> for Dataset {
             params: self.params.clone(),
             replication: self.replication.clone(),
             acceleration: self.acceleration.clone(),
+            time_column: self.time_column.clone(),
+            time_format: self.time_format.clone(),
             depends_on: depends_on.to_vec(),
         }
     }
@@ -219,6 +300,7 @@ pub mod acceleration {
     pub enum RefreshMode {
         Full,
         Append,
+        Incremental,
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
@@ -273,14 +355,28 @@ pub mod acceleration {
         #[serde(default, skip_serializing_if = "Option::is_none")]
         pub refresh_mode: Option<RefreshMode>,
 
+        /// The column used to track refresh progress in `incremental` refresh mode.
+        ///
+        /// Defaults to the dataset's `time_column`. Without a `primary_key` it must be strictly increasing,
+        /// since rows with the latest value already loaded are not fetched again.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub watermark_column: Option<String>,
+
         #[serde(default, skip_serializing_if = "Option::is_none")]
         pub retention: Option<String>,
 
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub retention_check_interval: Option<String>,
+
         #[serde(default, skip_serializing_if = "Option::is_none")]
         pub params: Option<HashMap<String, String>>,
 
         #[serde(default, skip_serializing_if = "Option::is_none")]
         pub engine_secret: Option<String>,
+
+        /// The columns that uniquely identify a row; appends with an existing key replace the row.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub primary_key: Option<Vec<String>>,
     }
 
     const fn default_true() -> bool {
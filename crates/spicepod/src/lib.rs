@@ -4,6 +4,7 @@
 use snafu::prelude::*;
 use std::{fmt::Debug, path::PathBuf};
 
+use component::access::Role;
 use component::dataset::Dataset;
 use component::model::Model;
 use component::secrets::Secrets;
@@ -38,6 +39,8 @@ pub struct Spicepod {
 
     pub models: Vec<Model>,
 
+    pub roles: Vec<Role>,
+
     pub dependencies: Vec<String>,
 }
 
@@ -116,6 +119,7 @@ fn from_definition(
         secrets: spicepod_definition.secrets,
         datasets,
         models,
+        roles: spicepod_definition.roles,
         dependencies: spicepod_definition.dependencies,
     }
 }
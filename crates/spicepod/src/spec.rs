@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use serde_yaml::{self, Value};
 use std::{collections::HashMap, fmt::Debug};
 
+use crate::component::access::Role;
 use crate::component::secrets::Secrets;
 use crate::component::{dataset::Dataset, model::Model, ComponentOrReference};
 
@@ -36,6 +37,11 @@ pub struct SpicepodDefinition {
     #[serde(default)]
     pub models: Vec<ComponentOrReference<Model>>,
 
+    /// Optional access control roles, all authenticated principals may access all datasets if empty
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub roles: Vec<Role>,
+
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(default)]
     pub dependencies: Vec<String>,